@@ -0,0 +1,52 @@
+extern crate tern;
+
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use std::process;
+use tern::program::archive::Archive;
+use tern::program::object::ObjectFile;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 2 {
+        let program_name = env::args().nth(0).unwrap();
+        println!("usage: {} <archive> <object>...", program_name);
+        return;
+    }
+
+    let mut archive = Archive::new();
+    for path in &args[1..] {
+        let mut contents = String::new();
+        let result = File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut contents))
+            .map_err(|e| e.to_string())
+            .and_then(|_| ObjectFile::parse(&contents).map_err(|e| e.to_string()));
+
+        match result {
+            Ok(object) => {
+                let name = Path::new(path).file_name().unwrap().to_string_lossy().into_owned();
+
+                // member names are written as a single field
+                if name.contains(char::is_whitespace) {
+                    eprintln!("error: {}: member names can't contain whitespace", path);
+                    process::exit(1);
+                }
+
+                archive.add_member(name, object);
+            }
+
+            Err(message) => {
+                eprintln!("error: {}: {}", path, message);
+                process::exit(1);
+            }
+        }
+    }
+
+    let written = File::create(&args[0]).and_then(|mut file| write!(file, "{}", archive));
+    if let Err(error) = written {
+        eprintln!("error: can't write `{}`: {}", args[0], error);
+        process::exit(1);
+    }
+}
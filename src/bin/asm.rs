@@ -0,0 +1,65 @@
+extern crate tern;
+
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use std::process;
+use tern::program::{DecodedProgram, EncodedProgram};
+use tern::program::object::ObjectFile;
+use tern::program::report::ErrorReport;
+use tern::util::split_include_args;
+use tern::vm::VM;
+
+fn main() {
+    let (include_paths, mut args) = split_include_args(env::args().skip(1));
+    let line_table = args.iter().position(|arg| arg == "-g").map(|i| args.remove(i)).is_some();
+    let (output, path) = match args.len() {
+        1 => (Path::new(&args[0]).with_extension("o"), &args[0]),
+        3 if args[0] == "-o" => (Path::new(&args[1]).to_path_buf(), &args[2]),
+        _ => {
+            let program_name = env::args().nth(0).unwrap();
+            println!("usage: {} [-g] [-I <dir>]... [-o <object>] <file>", program_name);
+            return;
+        }
+    };
+
+    let mut program = DecodedProgram::new();
+    program.set_relocatable(true);
+    for dir in &include_paths {
+        program.add_include_path(dir);
+    }
+
+    let mut report = ErrorReport::new();
+    if let Err(errors) = program.read_file(path) {
+        report.add_parse_errors(errors);
+    }
+
+    let vm = VM::new(program.size());
+    let mut encoder = EncodedProgram::new(vm.memory, vm.memory_size);
+    let result = encoder.encode_object(program);
+    report.add_encode_warnings(encoder.take_warnings());
+
+    let object = match result {
+        Ok(object) if line_table => Some(object),
+        Ok(object) => Some(ObjectFile { lines: None, ..object }),
+        Err(errors) => {
+            report.add_encode_errors(errors);
+            None
+        }
+    };
+
+    if !report.is_empty() {
+        eprintln!("{}", report);
+    }
+
+    if let (Some(object), false) = (object, report.has_errors()) {
+        let written = File::create(&output).and_then(|mut file| write!(file, "{}", object));
+        if let Err(error) = written {
+            eprintln!("error: can't write `{}`: {}", output.display(), error);
+            process::exit(1);
+        }
+    } else {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,78 @@
+extern crate tern;
+
+use std::env;
+use std::io;
+use std::process;
+use std::time::{Duration, Instant};
+use tern::jit::BlockCompiler;
+use tern::program::report::ErrorReport;
+use tern::util::vm_from_file;
+use tern::vm::VM;
+
+static DEFAULT_PROGRAMS: [&'static str; 3] =
+    ["programs/loop.tasm", "programs/hash.tasm", "programs/sum.tasm"];
+const DEFAULT_ITERATIONS: usize = 100;
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+// programs are loaded from their paths so their includes resolve, and what they print is
+// thrown away so it doesn't count towards their time
+fn bench<F>(path: &str, iterations: usize, mut f: F) -> Result<f64, ErrorReport>
+    where F: FnMut(&mut VM)
+{
+    let mut total = 0.0;
+    for _ in 0..iterations {
+        let mut vm = try!(vm_from_file(path));
+        vm.set_output(io::sink());
+
+        let start = Instant::now();
+        f(&mut vm);
+        total += seconds(start.elapsed());
+    }
+
+    Ok(total / iterations as f64)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let (iterations, paths) = match args.first().and_then(|s| s.parse().ok()) {
+        Some(n) => (n, args[1..].to_vec()),
+        None => (DEFAULT_ITERATIONS, args.clone()),
+    };
+
+    let paths = if paths.is_empty() {
+        DEFAULT_PROGRAMS.iter().map(|s| s.to_string()).collect()
+    } else {
+        paths
+    };
+
+    for path in paths {
+        let times = bench(&path, iterations, |vm| vm.run()).and_then(|step_time| {
+            let uncached_time = try!(bench(&path, iterations, |vm| {
+                vm.set_caching(false);
+                vm.run();
+            }));
+            let block_time = try!(bench(&path, iterations, |vm| BlockCompiler::new().run(vm)));
+            Ok((step_time, uncached_time, block_time))
+        });
+
+        let (step_time, uncached_time, block_time) = match times {
+            Ok(times) => times,
+            Err(report) => {
+                eprintln!("{}", report);
+                process::exit(1);
+            }
+        };
+
+        eprintln!("{}: step {:.3}ms, uncached {:.3}ms ({:.2}x), blocks {:.3}ms ({:.2}x)",
+                  path,
+                  step_time * 1e3,
+                  uncached_time * 1e3,
+                  uncached_time / step_time,
+                  block_time * 1e3,
+                  step_time / block_time);
+    }
+}
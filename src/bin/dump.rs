@@ -1,30 +1,39 @@
 extern crate tern;
 
 use std::env;
-use std::fs::File;
 use std::io;
-use std::io::prelude::*;
+use std::process;
 use tern::program::DecodedProgram;
+use tern::program::report::ErrorReport;
+use tern::util::split_include_args;
 
 fn main() {
-    if let Some(path) = env::args().nth(1) {
-        let reader: Box<Read> = match &path[..] {
-            "-" => Box::new(io::stdin()),
-            _ => Box::new(File::open(path).unwrap()),
+    let (include_paths, args) = split_include_args(env::args().skip(1));
+    if let Some(path) = args.get(0) {
+        let mut program = DecodedProgram::new();
+        for dir in &include_paths {
+            program.add_include_path(dir);
+        }
+
+        let result = match &path[..] {
+            "-" => program.read(io::stdin()),
+            _ => program.read_file(path),
         };
 
-        let mut program = DecodedProgram::new();
-        match program.read(reader) {
+        match result {
             Ok(()) => {
                 program.debug();
             }
 
-            Err(e) => {
-                println!("error: {:?}", e);
+            Err(errors) => {
+                let mut report = ErrorReport::new();
+                report.add_parse_errors(errors);
+                eprintln!("{}", report);
+                process::exit(1);
             }
         }
     } else {
         let program_name = env::args().nth(0).unwrap();
-        println!("usage: {} <file>", program_name);
+        println!("usage: {} [-I <dir>]... <file>", program_name);
     }
 }
@@ -0,0 +1,68 @@
+extern crate tern;
+
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+use std::process;
+use tern::program::archive::Archive;
+use tern::program::link::Linker;
+use tern::program::object::ObjectFile;
+
+// archives are searched after every object has been added, whatever order they're given in
+fn add_input(linker: &mut Linker, path: &str) -> Result<(), String> {
+    let mut contents = String::new();
+    try!(File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|e| format!("can't read `{}`: {}", path, e)));
+
+    if Archive::is_archive(&contents) {
+        let archive = try!(Archive::parse(&contents).map_err(|e| format!("{}: {}", path, e)));
+        linker.add_archive(path.to_string(), archive);
+    } else {
+        let object = try!(ObjectFile::parse(&contents).map_err(|e| format!("{}: {}", path, e)));
+        linker.add_object(path.to_string(), object);
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut output = "a.out".to_string();
+    if args.len() > 1 && args[0] == "-o" {
+        output = args.remove(1);
+        args.remove(0);
+    }
+
+    if args.is_empty() {
+        let program_name = env::args().nth(0).unwrap();
+        println!("usage: {} [-o <image>] <object | archive>...", program_name);
+        return;
+    }
+
+    let mut linker = Linker::new();
+    for path in &args {
+        if let Err(message) = add_input(&mut linker, path) {
+            eprintln!("error: {}", message);
+            process::exit(1);
+        }
+    }
+
+    match linker.link() {
+        Ok(image) => {
+            let written = File::create(&output).and_then(|mut file| write!(file, "{}", image));
+            if let Err(error) = written {
+                eprintln!("error: can't write `{}`: {}", output, error);
+                process::exit(1);
+            }
+        }
+
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("error: {}", error);
+            }
+
+            process::exit(1);
+        }
+    }
+}
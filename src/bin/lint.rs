@@ -0,0 +1,49 @@
+extern crate tern;
+
+use std::env;
+use std::process;
+use tern::program::DecodedProgram;
+use tern::program::lint::lint;
+use tern::program::report::ErrorReport;
+use tern::util::split_include_args;
+
+fn main() {
+    let (include_paths, paths) = split_include_args(env::args().skip(1));
+    if paths.is_empty() {
+        let program_name = env::args().nth(0).unwrap();
+        println!("usage: {} [-I <dir>]... <file>...", program_name);
+        return;
+    }
+
+    let mut failed = false;
+    let mut warning_count = 0;
+
+    for path in &paths {
+        let mut program = DecodedProgram::new();
+        for dir in &include_paths {
+            program.add_include_path(dir);
+        }
+
+        if let Err(errors) = program.read_file(path) {
+            let mut report = ErrorReport::new();
+            report.add_parse_errors(errors);
+            eprintln!("{}", report);
+            failed = true;
+            continue;
+        }
+
+        for warning in lint(&program) {
+            eprintln!("warning: {}\n", warning);
+            warning_count += 1;
+        }
+    }
+
+    if warning_count > 0 {
+        let plural = if warning_count == 1 { "" } else { "s" };
+        eprintln!("{} warning{}", warning_count, plural);
+    }
+
+    if failed || warning_count > 0 {
+        process::exit(1);
+    }
+}
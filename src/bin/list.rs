@@ -0,0 +1,66 @@
+extern crate tern;
+
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::process;
+use tern::program::{DecodedProgram, EncodedProgram};
+use tern::program::report::ErrorReport;
+use tern::util::split_include_args;
+use tern::vm::VM;
+
+fn main() {
+    let (include_paths, args) = split_include_args(env::args().skip(1));
+    let (map_path, path) = match args.len() {
+        1 => (None, &args[0]),
+        3 if args[0] == "-m" => (Some(&args[1]), &args[2]),
+        _ => {
+            let program_name = env::args().nth(0).unwrap();
+            println!("usage: {} [-I <dir>]... [-m <symbol map>] <file>", program_name);
+            return;
+        }
+    };
+
+    let mut program = DecodedProgram::new();
+    for dir in &include_paths {
+        program.add_include_path(dir);
+    }
+
+    let result = match &path[..] {
+        "-" => program.read(io::stdin()),
+        _ => program.read_file(path),
+    };
+
+    let mut report = ErrorReport::new();
+    if let Err(errors) = result {
+        report.add_parse_errors(errors);
+    }
+
+    let vm = VM::new(program.size());
+    let mut encoder = EncodedProgram::new(vm.memory, vm.memory_size);
+    if let Err(errors) = encoder.encode(program) {
+        report.add_encode_errors(errors);
+    }
+
+    report.add_encode_warnings(encoder.take_warnings());
+    if !report.is_empty() {
+        eprintln!("{}", report);
+    }
+
+    if report.has_errors() {
+        process::exit(1);
+    }
+
+    print!("{}", encoder.listing());
+
+    if let Some(map_path) = map_path {
+        let written = File::create(map_path)
+            .and_then(|mut file| write!(file, "{}", encoder.symbol_map()));
+
+        if let Err(error) = written {
+            eprintln!("error: can't write `{}`: {}", map_path, error);
+            process::exit(1);
+        }
+    }
+}
@@ -4,26 +4,71 @@ use std::env;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
-use tern::util::vm_from_reader;
+use std::process;
+use tern::program::DecodedProgram;
+use tern::program::object::Image;
+use tern::util::{assemble, split_include_args, vm_from_image};
+use tern::vm::VM;
+
+fn load_image(path: &str) -> Option<VM> {
+    let mut contents = String::new();
+    if File::open(path).and_then(|mut file| file.read_to_string(&mut contents)).is_err() ||
+       !Image::is_image(&contents) {
+        return None;
+    }
+
+    match Image::parse(&contents) {
+        Ok(image) => Some(vm_from_image(&image)),
+        Err(error) => {
+            eprintln!("error: {}: {}", path, error);
+            process::exit(1);
+        }
+    }
+}
+
+fn assemble_file(path: &str, include_paths: &[String]) -> VM {
+    let mut program = DecodedProgram::new();
+    for dir in include_paths {
+        program.add_include_path(dir);
+    }
+
+    let result = match path {
+        "-" => program.read(io::stdin()),
+        _ => program.read_file(path),
+    };
+
+    let (vm, report) = assemble(program, result);
+    if !report.is_empty() {
+        eprintln!("{}", report);
+    }
+
+    if report.has_errors() {
+        process::exit(1);
+    }
+
+    vm
+}
 
 fn main() {
-    if let Some(path) = env::args().nth(1) {
-        let reader: Box<Read> = match &path[..] {
-            "-" => Box::new(io::stdin()),
-            _ => Box::new(File::open(path).unwrap()),
+    let (include_paths, args) = split_include_args(env::args().skip(1));
+    if let Some(path) = args.get(0) {
+        let mut vm = match load_image(path) {
+            Some(vm) => vm,
+            None => assemble_file(path, &include_paths),
         };
 
-        match vm_from_reader(reader) {
-            Ok(mut vm) => {
-                vm.run();
-            }
+        vm.run();
+
+        if let Some(error) = vm.error() {
+            eprintln!("error: {}", error);
+            process::exit(1);
+        }
 
-            Err(e) => {
-                println!("error: {}", e);
-            }
+        if let Some(status) = vm.exit_status() {
+            process::exit(status);
         }
     } else {
         let program_name = env::args().nth(0).unwrap();
-        println!("usage: {} <file>", program_name);
+        println!("usage: {} [-I <dir>]... <file | image>", program_name);
     }
 }
@@ -0,0 +1,91 @@
+extern crate tern;
+
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+use std::process;
+use tern::program::format::format_source;
+use tern::util::{encode_source, split_include_args};
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Mode {
+    Print,
+    Write,
+    Check,
+}
+
+// formatting must leave the assembled program exactly as it was. errors come back as the whole
+// text to print, with any assembly report ahead of the message
+fn format_file(path: &str, include_paths: &[String]) -> Result<(String, bool), String> {
+    let mut source = String::new();
+    try!(File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut source))
+        .map_err(|e| format!("error: can't read `{}`: {}", path, e)));
+
+    let formatted = format_source(&source);
+    if formatted == source {
+        return Ok((formatted, false));
+    }
+
+    let before = try!(encode_source(path, &source, include_paths)
+        .map_err(|report| {
+            format!("{}\nerror: can't format `{}` until it assembles", report, path)
+        }));
+
+    match encode_source(path, &formatted, include_paths) {
+        Ok(ref after) if *after == before => Ok((formatted, true)),
+        _ => {
+            Err(format!("error: formatting `{}` would change its encoding, leaving it as it is",
+                        path))
+        }
+    }
+}
+
+fn main() {
+    let (include_paths, args) = split_include_args(env::args().skip(1));
+    let (mode, paths) = match args.first().map(|arg| &arg[..]) {
+        Some("-w") => (Mode::Write, &args[1..]),
+        Some("--check") => (Mode::Check, &args[1..]),
+        _ => (Mode::Print, &args[..]),
+    };
+
+    if paths.is_empty() {
+        let program_name = env::args().nth(0).unwrap();
+        println!("usage: {} [-I <dir>]... [-w | --check] <file>...", program_name);
+        return;
+    }
+
+    let mut failed = false;
+    for path in paths {
+        let (formatted, changed) = match format_file(path, &include_paths) {
+            Ok(result) => result,
+            Err(message) => {
+                eprintln!("{}", message);
+                failed = true;
+                continue;
+            }
+        };
+
+        match mode {
+            Mode::Print => print!("{}", formatted),
+            Mode::Write if changed => {
+                let written = File::create(path)
+                    .and_then(|mut file| file.write_all(formatted.as_bytes()));
+
+                if let Err(error) = written {
+                    eprintln!("error: can't write `{}`: {}", path, error);
+                    failed = true;
+                }
+            }
+            Mode::Check if changed => {
+                println!("{}", path);
+                failed = true;
+            }
+            _ => {}
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
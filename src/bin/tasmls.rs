@@ -0,0 +1,29 @@
+extern crate tern;
+
+use std::env;
+use std::io;
+use std::process;
+use tern::lsp::server::Server;
+use tern::util::split_include_args;
+
+fn main() {
+    let (include_paths, args) = split_include_args(env::args().skip(1));
+    if !args.is_empty() {
+        let program_name = env::args().nth(0).unwrap();
+        println!("usage: {} [-I <dir>]...", program_name);
+        println!("serves the language server protocol over stdin and stdout");
+        return;
+    }
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut server = Server::new(stdout.lock(), include_paths);
+
+    match server.run(&mut stdin.lock()) {
+        Ok(code) => process::exit(code),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use ternary;
+use types::*;
+use opcodes::Opcode;
+use registers::Register;
+
+#[derive(Clone, Copy, Debug)]
+pub enum CachedOp {
+    Mov(Register, Register),
+    Movi(Register, Half),
+    Movw(Register, Word),
+    Mova(Register, Addr),
+    Load(Register, Register, isize, isize),
+    Store(Register, Register, isize, isize),
+    Add(Register, Register, Register),
+    Addi(Register, Half),
+    Mul(Register, Register),
+    Muli(Register, Half),
+    Not(Register, Register),
+    And(Register, Register, Register),
+    Or(Register, Register, Register),
+    Shf(Register, Register, Register),
+    Shfi(Register, isize),
+    Cmp(Register, Register, Register),
+    Jmp(Addr),
+    JT(Register, RelAddr),
+    J0(Register, RelAddr),
+    J1(Register, RelAddr),
+    JT0(Register, RelAddr),
+    JT1(Register, RelAddr),
+    J01(Register, RelAddr),
+    Call(Addr),
+    Ret,
+    Syscall,
+    Halt,
+    Nop,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CachedInstruction {
+    pub op: CachedOp,
+    pub size: usize,
+}
+
+impl CachedInstruction {
+    pub unsafe fn decode(memory: *const Trit) -> CachedInstruction {
+        let (t0, t1, t2, t3) = ternary::read_trytes(memory);
+        let opcode = Opcode::from(t0);
+
+        let half = || {
+            let mut half = EMPTY_HALF;
+            ternary::copy(mut_ptr!(half), tryte_offset!(memory, 2), HALF_ISIZE);
+            half
+        };
+
+        let half_isize = || ternary::to_int(tryte_offset!(memory, 2), HALF_ISIZE);
+
+        let next_word = || {
+            let mut word = EMPTY_WORD;
+            ternary::copy(mut_ptr!(word), memory.offset(WORD_ISIZE), WORD_ISIZE);
+            word
+        };
+
+        let next_addr = || ternary::to_int(memory.offset(WORD_ISIZE), WORD_ISIZE) as Addr;
+
+        let op = match opcode {
+            Opcode::Mov => CachedOp::Mov(Register::from(t1), Register::from(t2)),
+            Opcode::Movi => CachedOp::Movi(Register::from(t1), half()),
+            Opcode::Movw => CachedOp::Movw(Register::from(t1), next_word()),
+            Opcode::Mova => CachedOp::Mova(Register::from(t1), next_addr()),
+            Opcode::Lt => CachedOp::Load(Register::from(t1), Register::from(t2), t3, TRYTE_ISIZE),
+            Opcode::Lh => CachedOp::Load(Register::from(t1), Register::from(t2), t3, HALF_ISIZE),
+            Opcode::Lw => CachedOp::Load(Register::from(t1), Register::from(t2), t3, WORD_ISIZE),
+            Opcode::St => CachedOp::Store(Register::from(t1), Register::from(t2), t3, TRYTE_ISIZE),
+            Opcode::Sh => CachedOp::Store(Register::from(t1), Register::from(t2), t3, HALF_ISIZE),
+            Opcode::Sw => CachedOp::Store(Register::from(t1), Register::from(t2), t3, WORD_ISIZE),
+            Opcode::Add => {
+                CachedOp::Add(Register::from(t1), Register::from(t2), Register::from(t3))
+            }
+            Opcode::Addi => CachedOp::Addi(Register::from(t1), half()),
+            Opcode::Mul => CachedOp::Mul(Register::from(t1), Register::from(t2)),
+            Opcode::Muli => CachedOp::Muli(Register::from(t1), half()),
+            Opcode::Not => CachedOp::Not(Register::from(t1), Register::from(t2)),
+            Opcode::And => {
+                CachedOp::And(Register::from(t1), Register::from(t2), Register::from(t3))
+            }
+            Opcode::Or => CachedOp::Or(Register::from(t1), Register::from(t2), Register::from(t3)),
+            Opcode::Shf => {
+                CachedOp::Shf(Register::from(t1), Register::from(t2), Register::from(t3))
+            }
+            Opcode::Shfi => CachedOp::Shfi(Register::from(t1), half_isize()),
+            Opcode::Cmp => {
+                CachedOp::Cmp(Register::from(t1), Register::from(t2), Register::from(t3))
+            }
+            Opcode::Jmp => CachedOp::Jmp(next_addr()),
+            Opcode::JT => CachedOp::JT(Register::from(t1), half_isize()),
+            Opcode::J0 => CachedOp::J0(Register::from(t1), half_isize()),
+            Opcode::J1 => CachedOp::J1(Register::from(t1), half_isize()),
+            Opcode::JT0 => CachedOp::JT0(Register::from(t1), half_isize()),
+            Opcode::JT1 => CachedOp::JT1(Register::from(t1), half_isize()),
+            Opcode::J01 => CachedOp::J01(Register::from(t1), half_isize()),
+            Opcode::Call => CachedOp::Call(next_addr()),
+            Opcode::Ret => CachedOp::Ret,
+            Opcode::Syscall => CachedOp::Syscall,
+            Opcode::Halt => CachedOp::Halt,
+            _ => CachedOp::Nop,
+        };
+
+        CachedInstruction {
+            op: op,
+            size: opcode.size(),
+        }
+    }
+}
+
+pub struct InstructionCache {
+    entries: Vec<Option<CachedInstruction>>,
+}
+
+impl InstructionCache {
+    pub fn new(memory_size: usize) -> InstructionCache {
+        InstructionCache { entries: vec![None; memory_size / WORD_SIZE] }
+    }
+
+    pub fn get(&self, pc: Addr) -> Option<CachedInstruction> {
+        if pc % WORD_SIZE != 0 {
+            return None;
+        }
+
+        self.entries.get(pc / WORD_SIZE).cloned().unwrap_or(None)
+    }
+
+    pub fn insert(&mut self, pc: Addr, inst: CachedInstruction) {
+        if pc % WORD_SIZE != 0 {
+            return;
+        }
+
+        if let Some(entry) = self.entries.get_mut(pc / WORD_SIZE) {
+            *entry = Some(inst);
+        }
+    }
+
+    // two-word instructions read the word after them, so a write into a word
+    // also invalidates whatever starts one word earlier. returns whether anything was removed
+    pub fn invalidate(&mut self, addr: isize, len: isize) -> bool {
+        if len <= 0 || addr + len <= 0 {
+            return false;
+        }
+
+        let start = (addr.max(0) as usize / WORD_SIZE).saturating_sub(1);
+        let end = ((addr + len - 1) as usize / WORD_SIZE + 1).min(self.entries.len());
+
+        let mut removed = false;
+        for i in start..end {
+            removed |= self.entries[i].take().is_some();
+        }
+
+        removed
+    }
+
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = None;
+        }
+    }
+}
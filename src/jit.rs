@@ -0,0 +1,326 @@
+use ternary;
+use trit::Trit;
+use types::*;
+use registers::Register;
+use cache::CachedOp;
+use vm::VM;
+
+// taken[trit + 1] says whether a conditional jump is taken when its register's lowest trit is
+// `trit`
+type Condition = [bool; 3];
+
+// immediates are widened to words once, when the block is compiled, instead of on every run,
+// and multiplying by one only adds in the shifted copies for its nonzero trits. anything else
+// goes through the interpreter's own `execute`
+enum BlockOp {
+    SetWord(Register, Word),
+    AddWord(Register, Word),
+    MulTrits(Register, Vec<(isize, Trit)>),
+    Execute(CachedOp),
+    // a store can fault or overwrite compiled code, so the block stops to check after it.
+    // `Addr` is where the instruction after the store starts
+    Store(CachedOp, Addr),
+    ClearZero,
+}
+
+enum BlockExit {
+    Jump(Addr),
+    Branch(Register, Condition, Addr),
+    // a `cmp` straight before a branch on its result, so the trit is tested without being read
+    // back out of the register
+    CompareBranch(Register, Register, Register, Condition, Addr),
+    Call(Addr, Word),
+    Ret,
+    Execute(CachedOp),
+    FallThrough,
+}
+
+struct Block {
+    ops: Vec<BlockOp>,
+    exit: BlockExit,
+    end: Addr,
+}
+
+// runs programs a basic block at a time. a block is the straight-line run of instructions up to
+// and including the first jump, call, ret, syscall or halt, compiled the first time it's reached
+// and kept until something writes over code the VM has fetched
+pub struct BlockCompiler {
+    blocks: Vec<Block>,
+    index: Vec<Option<usize>>,
+}
+
+impl BlockCompiler {
+    pub fn new() -> BlockCompiler {
+        BlockCompiler {
+            blocks: Vec::new(),
+            index: Vec::new(),
+        }
+    }
+
+    pub fn run(&mut self, vm: &mut VM) {
+        // blocks are compiled from fetched instructions, so the VM's cache is what notices
+        // writes to them
+        vm.set_caching(true);
+        vm.init();
+
+        self.blocks.clear();
+        self.index = vec![None; vm.memory_size / WORD_SIZE];
+
+        while vm.running {
+            unsafe { self.step(vm) };
+        }
+
+        vm.flush_output();
+    }
+
+    // runs the block at pc, falling back to a single interpreter step where there isn't one
+    pub unsafe fn step(&mut self, vm: &mut VM) {
+        let pc = vm.pc;
+        if pc % WORD_SIZE != 0 || pc / WORD_SIZE >= self.index.len() {
+            vm.step();
+            return;
+        }
+
+        let id = match self.index[pc / WORD_SIZE] {
+            Some(id) => id,
+            None => {
+                match compile(vm, pc) {
+                    Some(block) => {
+                        self.blocks.push(block);
+                        self.index[pc / WORD_SIZE] = Some(self.blocks.len() - 1);
+                        self.blocks.len() - 1
+                    }
+                    None => {
+                        vm.step();
+                        return;
+                    }
+                }
+            }
+        };
+
+        let code_writes = vm.code_writes();
+        run_block(&self.blocks[id], vm, code_writes);
+
+        if vm.code_writes() != code_writes {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        self.blocks.clear();
+        for entry in self.index.iter_mut() {
+            *entry = None;
+        }
+    }
+}
+
+unsafe fn compile(vm: &mut VM, start: Addr) -> Option<Block> {
+    let mut ops = Vec::new();
+    let mut pc = start;
+
+    let exit = loop {
+        if pc + WORD_SIZE > vm.memory_size {
+            break BlockExit::FallThrough;
+        }
+
+        let inst = vm.fetch(pc);
+        if pc + inst.size > vm.memory_size {
+            break BlockExit::FallThrough;
+        }
+
+        pc += inst.size;
+
+        match inst.op {
+            CachedOp::Jmp(addr) => break BlockExit::Jump(addr),
+            CachedOp::JT(r, offset) => break branch(&mut ops, r, [true, false, false], pc, offset),
+            CachedOp::J0(r, offset) => break branch(&mut ops, r, [false, true, false], pc, offset),
+            CachedOp::J1(r, offset) => break branch(&mut ops, r, [false, false, true], pc, offset),
+            CachedOp::JT0(r, offset) => break branch(&mut ops, r, [true, true, false], pc, offset),
+            CachedOp::JT1(r, offset) => break branch(&mut ops, r, [true, false, true], pc, offset),
+            CachedOp::J01(r, offset) => break branch(&mut ops, r, [false, true, true], pc, offset),
+            CachedOp::Call(addr) => {
+                let mut word = EMPTY_WORD;
+                ternary::from_int(mut_ptr!(word), pc as isize, WORD_ISIZE);
+                break BlockExit::Call(addr, word);
+            }
+            CachedOp::Ret => break BlockExit::Ret,
+            CachedOp::Syscall | CachedOp::Halt => break BlockExit::Execute(inst.op),
+            op => compile_op(&mut ops, op, pc),
+        }
+    };
+
+    if ops.is_empty() {
+        if let BlockExit::FallThrough = exit {
+            return None;
+        }
+    }
+
+    Some(Block {
+        ops: ops,
+        exit: exit,
+        end: pc,
+    })
+}
+
+fn compile_op(ops: &mut Vec<BlockOp>, op: CachedOp, next_pc: Addr) {
+    let block_op = match op {
+        CachedOp::Movi(r, half) => BlockOp::SetWord(r, widen(half)),
+        CachedOp::Movw(r, word) => BlockOp::SetWord(r, word),
+        CachedOp::Mova(r, addr) => {
+            let mut word = EMPTY_WORD;
+            unsafe { ternary::from_int(mut_ptr!(word), addr as isize, WORD_ISIZE) };
+            BlockOp::SetWord(r, word)
+        }
+        CachedOp::Addi(r, half) => BlockOp::AddWord(r, widen(half)),
+        CachedOp::Muli(r, half) => {
+            let trits = (0..HALF_ISIZE)
+                .map(|i| (i, half[i as usize]))
+                .filter(|&(_, trit)| trit != Trit::Zero)
+                .collect();
+            BlockOp::MulTrits(r, trits)
+        }
+        CachedOp::Store(..) => BlockOp::Store(op, next_pc),
+        CachedOp::Nop => return,
+        _ => BlockOp::Execute(op),
+    };
+
+    ops.push(block_op);
+
+    // the interpreter clears $zero after every instruction, but only these can have set it
+    if dest_register(op) == Some(Register::ZERO) {
+        ops.push(BlockOp::ClearZero);
+    }
+}
+
+fn branch(ops: &mut Vec<BlockOp>,
+          r: Register,
+          condition: Condition,
+          next_pc: Addr,
+          offset: RelAddr)
+          -> BlockExit {
+    let target = (next_pc as RelAddr + offset) as Addr;
+
+    if r == Register::ZERO {
+        return if condition[1] {
+            BlockExit::Jump(target)
+        } else {
+            BlockExit::FallThrough
+        };
+    }
+
+    if let Some(&BlockOp::Execute(CachedOp::Cmp(r_dest, r_lhs, r_rhs))) = ops.last() {
+        if r_dest == r {
+            ops.pop();
+            return BlockExit::CompareBranch(r_dest, r_lhs, r_rhs, condition, target);
+        }
+    }
+
+    BlockExit::Branch(r, condition, target)
+}
+
+fn widen(half: Half) -> Word {
+    let mut word = EMPTY_WORD;
+    unsafe { ternary::copy(mut_ptr!(word), ptr!(half), HALF_ISIZE) };
+    word
+}
+
+fn dest_register(op: CachedOp) -> Option<Register> {
+    match op {
+        CachedOp::Mov(r, _) |
+        CachedOp::Movi(r, _) |
+        CachedOp::Movw(r, _) |
+        CachedOp::Mova(r, _) |
+        CachedOp::Load(r, _, _, _) |
+        CachedOp::Add(r, _, _) |
+        CachedOp::Addi(r, _) |
+        CachedOp::Not(r, _) |
+        CachedOp::And(r, _, _) |
+        CachedOp::Or(r, _, _) |
+        CachedOp::Shf(r, _, _) |
+        CachedOp::Shfi(r, _) |
+        CachedOp::Cmp(r, _, _) => Some(r),
+        _ => None,
+    }
+}
+
+fn taken(condition: &Condition, trit: Trit) -> bool {
+    condition[(trit as i8 + 1) as usize]
+}
+
+unsafe fn run_block(block: &Block, vm: &mut VM, code_writes: usize) {
+    for op in block.ops.iter() {
+        match *op {
+            BlockOp::SetWord(r, ref word) => {
+                ternary::copy(vm.dest(r), ptr!(word), WORD_ISIZE);
+            }
+
+            // same as the VM's add and multiply: the carry goes in $hi, the product in $lo:$hi
+            BlockOp::AddWord(r, ref word) => {
+                let carry = ternary::add(vm.dest(r), vm.src(r), ptr!(word), WORD_ISIZE);
+                vm.clear(Register::HI);
+                *vm.dest(Register::HI) = carry;
+            }
+
+            BlockOp::MulTrits(r, ref trits) => {
+                vm.clear(Register::LO);
+                vm.clear(Register::HI);
+
+                let dest = vm.dest(Register::LO);
+                let lhs = vm.src(r);
+                for &(i, trit) in trits.iter() {
+                    *dest.offset(i + WORD_ISIZE) =
+                        ternary::addmul(dest.offset(i), lhs, trit, WORD_ISIZE);
+                }
+            }
+
+            BlockOp::Execute(op) => vm.execute(op),
+
+            BlockOp::Store(op, next_pc) => {
+                vm.execute(op);
+                if !vm.running || vm.code_writes() != code_writes {
+                    vm.pc = next_pc;
+                    return;
+                }
+            }
+
+            BlockOp::ClearZero => vm.clear(Register::ZERO),
+        }
+    }
+
+    vm.pc = block.end;
+
+    match block.exit {
+        BlockExit::Jump(addr) => vm.pc = addr,
+
+        BlockExit::Branch(r, ref condition, target) => {
+            if taken(condition, *vm.src(r)) {
+                vm.pc = target;
+            }
+        }
+
+        BlockExit::CompareBranch(r_dest, r_lhs, r_rhs, ref condition, target) => {
+            let trit = ternary::compare(vm.src(r_lhs), vm.src(r_rhs), WORD_ISIZE);
+            let dest = vm.dest(r_dest);
+            ternary::clear(dest, WORD_ISIZE);
+            *dest = trit;
+
+            if taken(condition, trit) {
+                vm.pc = target;
+            }
+        }
+
+        BlockExit::Call(addr, ref ret) => {
+            ternary::copy(vm.dest(Register::RA), ptr!(ret), WORD_ISIZE);
+            vm.pc = addr;
+        }
+
+        BlockExit::Ret => vm.pc = vm.read(Register::RA) as Addr,
+
+        BlockExit::Execute(op) => {
+            vm.execute(op);
+            vm.clear(Register::ZERO);
+        }
+
+        BlockExit::FallThrough => {}
+    }
+}
@@ -1,7 +1,6 @@
 extern crate core;
 extern crate libc;
 extern crate rand;
-extern crate regex;
 
 #[macro_use]
 mod macros;
@@ -11,8 +10,11 @@ pub mod types;
 pub mod opcodes;
 pub mod registers;
 pub mod syscalls;
+pub mod cache;
 pub mod vm;
+pub mod jit;
 pub mod program;
+pub mod lsp;
 pub mod text;
 pub mod util;
 
@@ -0,0 +1,665 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use opcodes::Opcode;
+use registers::{Register, REGISTER_COUNT};
+use program::{DecodedProgram, EncodedProgram};
+use program::encode::{EncodeError, EncodeWarning};
+use program::lexer::{is_ident_char, is_numeric_label_ref, tokenize, Span, Token, TokenKind};
+use program::lint::{lint, LintWarning};
+use program::location::{Expansion, Location};
+use program::parser::{pseudo_arity, CodeDecl, DataDecl, ParseError};
+use program::symbols::{display_label, is_numeric_label};
+use vm::VM;
+
+static START_LABEL: &'static str = "__start";
+
+static PSEUDO_INSTRUCTIONS: [&'static str; 11] = ["nop", "b", "inc", "dec", "li", "la", "neg",
+                                                  "sub", "beq", "blt", "bgt"];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SymbolKind {
+    Label,
+    Constant,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub span: Span,
+    pub kind: SymbolKind,
+    pub is_definition: bool,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Definition {
+    pub path: Option<String>,
+    pub span: Span,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CompletionKind {
+    Opcode,
+    Register,
+    Label,
+    Constant,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Completion {
+    pub text: String,
+    pub kind: CompletionKind,
+    pub detail: String,
+}
+
+// an open file, along with what the assembler and linter make of it. lines and columns are
+// one-based as in `Span`, and columns count characters
+pub struct Document {
+    pub path: String,
+    pub lines: Vec<String>,
+    pub diagnostics: Vec<Diagnostic>,
+    symbols: Vec<Symbol>,
+    external_labels: HashMap<String, Location>,
+    external_constants: HashMap<String, Location>,
+}
+
+impl Document {
+    pub fn new(path: &str, text: &str, include_paths: &[String]) -> Document {
+        let lines: Vec<String> = text.lines().map(|line| line.to_string()).collect();
+        let symbols = scan_symbols(&lines);
+
+        let mut document = Document {
+            path: path.to_string(),
+            lines: lines,
+            diagnostics: Vec::new(),
+            symbols: symbols,
+            external_labels: HashMap::new(),
+            external_constants: HashMap::new(),
+        };
+
+        document.analyze(text, include_paths);
+        document
+    }
+
+    // files without `__start` are checked as objects, since they're usually meant to be linked
+    // or included into something else
+    fn analyze(&mut self, text: &str, include_paths: &[String]) {
+        let read = |relocatable| {
+            let mut program = DecodedProgram::new();
+            program.set_relocatable(relocatable);
+            for dir in include_paths {
+                program.add_include_path(dir);
+            }
+
+            let result = program.read_str_as(&self.path, text);
+            (program, result)
+        };
+
+        let (mut program, mut result) = read(false);
+        let relocatable = !program.code.iter().any(|code_decl| match *code_decl {
+            CodeDecl::Label(ref label, _) => label == START_LABEL,
+            _ => false,
+        });
+
+        if relocatable {
+            let (relocatable_program, relocatable_result) = read(true);
+            program = relocatable_program;
+            result = relocatable_result;
+        }
+
+        self.find_external_definitions(&program);
+
+        if let Err(errors) = result {
+            for error in errors {
+                self.add_parse_error(error);
+            }
+
+            return;
+        }
+
+        for warning in lint(&program) {
+            // whole programs are range checked by the encoder, which reports it as an error
+            if let (LintWarning::BranchOutOfRange(_, _, _), false) = (&warning, relocatable) {
+                continue;
+            }
+
+            let message = warning.message();
+            self.add_diagnostic(warning.location(), Severity::Warning, message);
+        }
+
+        let vm = VM::new(program.size());
+        let mut encoder = EncodedProgram::new(vm.memory, vm.memory_size);
+        let errors = if relocatable {
+            encoder.encode_object(program).err()
+        } else {
+            encoder.encode(program).err()
+        };
+
+        for error in errors.unwrap_or_else(Vec::new) {
+            self.add_encode_error(error);
+        }
+
+        for warning in encoder.take_warnings() {
+            match warning {
+                EncodeWarning::UnusedLabel(label, location) => {
+                    let message = format!("label `{}` is never used", label);
+                    self.add_diagnostic(&location, Severity::Warning, message);
+                }
+            }
+        }
+    }
+
+    fn find_external_definitions(&mut self, program: &DecodedProgram) {
+        let code_labels = program.code.iter().filter_map(|code_decl| match *code_decl {
+            CodeDecl::Label(ref label, ref location) => Some((label, location)),
+            _ => None,
+        });
+
+        let data_labels = program.data
+            .iter()
+            .chain(program.rodata.iter())
+            .chain(program.bss.iter())
+            .filter_map(|data_decl| match *data_decl {
+                DataDecl::Label(ref label, ref location) => Some((label, location)),
+                _ => None,
+            });
+
+        for (label, location) in code_labels.chain(data_labels) {
+            if location.path.as_ref() != Some(&self.path) {
+                self.external_labels.insert(label.clone(), location.clone());
+            }
+        }
+
+        let names: HashSet<&String> = self.symbols.iter().map(|symbol| &symbol.name).collect();
+        for name in names {
+            if let Some(location) = program.constant_location(name) {
+                if location.path.as_ref() != Some(&self.path) {
+                    self.external_constants.insert(name.clone(), location.clone());
+                }
+            }
+        }
+
+        // a constant from an included file is only recognizable once the includes are read
+        for symbol in &mut self.symbols {
+            if self.external_constants.contains_key(&symbol.name) {
+                symbol.kind = SymbolKind::Constant;
+            }
+        }
+    }
+
+    fn add_parse_error(&mut self, error: ParseError) {
+        let message = error.kind.to_string();
+        let span = self.locate(error.path.as_ref(), error.span, error.expansion.as_ref());
+        self.push_diagnostic(span, Severity::Error, message, error.path.as_ref());
+    }
+
+    fn add_encode_error(&mut self, error: EncodeError) {
+        match error {
+            EncodeError::UndefinedLabel(ref label, ref location) => {
+                let message = format!("undefined label `{}`", label);
+                self.add_diagnostic(location, Severity::Error, message);
+            }
+
+            EncodeError::DuplicateLabel(ref label, ref location, _) => {
+                let message = format!("duplicate label `{}`", label);
+                self.add_diagnostic(location, Severity::Error, message);
+            }
+
+            EncodeError::IntOutOfRange(ref value, min, max, ref location) => {
+                let message = format!("{} is out of range ({} to {})", value, min, max);
+                self.add_diagnostic(location, Severity::Error, message);
+            }
+
+            EncodeError::BranchOutOfRange(ref label, offset, ref location) => {
+                // the linter words it the same way
+                let message = LintWarning::BranchOutOfRange(label.clone(), offset, location.clone())
+                    .message();
+                self.add_diagnostic(location, Severity::Error, message);
+            }
+
+            ref error => {
+                let span = Span::new(1, 1, 0);
+                self.diagnostics.push(Diagnostic {
+                    span: span,
+                    severity: Severity::Error,
+                    message: error.to_string(),
+                });
+            }
+        }
+    }
+
+    fn add_diagnostic(&mut self, location: &Location, severity: Severity, message: String) {
+        let span = self.locate(location.path.as_ref(),
+                               Some(location.span),
+                               location.expansion.as_ref());
+        self.push_diagnostic(span, severity, message, location.path.as_ref());
+    }
+
+    // problems in other files show up at the top of this one, naming where they are
+    fn push_diagnostic(&mut self,
+                       span: Option<Span>,
+                       severity: Severity,
+                       message: String,
+                       path: Option<&String>) {
+        let (span, message) = match span {
+            Some(span) => (span, message),
+            None => {
+                let path = path.map_or("<input>", |path| &path[..]);
+                (Span::new(1, 1, 0), format!("in `{}`: {}", path, message))
+            }
+        };
+
+        self.diagnostics.push(Diagnostic {
+            span: span,
+            severity: severity,
+            message: message,
+        });
+    }
+
+    // the outermost place in this file that led to a problem, so an error inside a macro is
+    // shown where the macro is used
+    fn locate(&self,
+              path: Option<&String>,
+              span: Option<Span>,
+              mut expansion: Option<&Box<Expansion>>)
+              -> Option<Span> {
+        let mut found = match span {
+            Some(span) if path == Some(&self.path) => Some(span),
+            _ => None,
+        };
+
+        while let Some(e) = expansion {
+            if e.call_site.path.as_ref() == Some(&self.path) {
+                found = Some(e.call_site.span);
+            }
+
+            expansion = e.call_site.expansion.as_ref();
+        }
+
+        found
+    }
+
+    pub fn symbol_at(&self, line: usize, column: usize) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| span_contains(symbol.span, line, column))
+    }
+
+    pub fn definition(&self, line: usize, column: usize) -> Option<Definition> {
+        let symbol = match self.symbol_at(line, column) {
+            Some(symbol) => symbol,
+            None => return None,
+        };
+
+        let local = self.symbols
+            .iter()
+            .find(|other| other.is_definition && other.name == symbol.name);
+
+        if let Some(definition) = local {
+            return Some(Definition {
+                path: Some(self.path.clone()),
+                span: definition.span,
+            });
+        }
+
+        self.external_definition(&symbol.name).map(|location| {
+            Definition {
+                path: location.path.clone(),
+                span: location.span,
+            }
+        })
+    }
+
+    fn external_definition(&self, name: &str) -> Option<&Location> {
+        self.external_labels.get(name).or_else(|| self.external_constants.get(name))
+    }
+
+    pub fn references(&self,
+                      line: usize,
+                      column: usize,
+                      include_definition: bool)
+                      -> Vec<Span> {
+        let name = match self.symbol_at(line, column) {
+            Some(symbol) => symbol.name.clone(),
+            None => return Vec::new(),
+        };
+
+        self.symbols
+            .iter()
+            .filter(|symbol| symbol.name == name && (include_definition || !symbol.is_definition))
+            .map(|symbol| symbol.span)
+            .collect()
+    }
+
+    pub fn hover(&self, line: usize, column: usize) -> Option<String> {
+        let tokens = match self.lines.get(line - 1).map(|text| tokenize(text, line)) {
+            Some(Ok(tokens)) => tokens,
+            _ => return None,
+        };
+
+        let index = match tokens.iter().position(|token| span_contains(token.span, line, column)) {
+            Some(index) => index,
+            None => return None,
+        };
+
+        match tokens[index].kind {
+            TokenKind::Register(ref name) => {
+                Register::from_str(&format!("${}", name)).ok().map(|r| {
+                    format!("`{}` (`{}`): {}", r.name(), r.index_name(), r.role())
+                })
+            }
+
+            TokenKind::Ident(ref name) if index == mnemonic_index(&tokens) => {
+                if Opcode::name_is_valid(name) {
+                    let opcode = Opcode::from(&name[..]);
+                    Some(format!("`{}`: {}", name, count(opcode.arity(), "operand")))
+                } else {
+                    pseudo_arity(name).map(|arity| {
+                        format!("`{}`: pseudo-instruction, {}", name, count(arity, "operand"))
+                    })
+                }
+            }
+
+            _ => self.symbol_at(line, column).map(|symbol| self.describe_symbol(symbol)),
+        }
+    }
+
+    fn describe_symbol(&self, symbol: &Symbol) -> String {
+        let noun = match symbol.kind {
+            SymbolKind::Label => "label",
+            SymbolKind::Constant => "constant",
+        };
+
+        let name = display_label(&symbol.name);
+        let local = self.symbols
+            .iter()
+            .find(|other| other.is_definition && other.name == symbol.name);
+
+        match local {
+            Some(definition) => {
+                format!("{} `{}`, defined on line {}", noun, name, definition.span.line)
+            }
+            None => {
+                match self.external_definition(&symbol.name) {
+                    Some(location) => format!("{} `{}`, defined at {}", noun, name, location),
+                    None => format!("{} `{}`", noun, name),
+                }
+            }
+        }
+    }
+
+    pub fn completions(&self, line: usize, column: usize) -> (Span, Vec<Completion>) {
+        let text: Vec<char> = self.lines.get(line - 1).map_or(Vec::new(), |s| s.chars().collect());
+        let end = (column - 1).min(text.len());
+        let mut start = end;
+        while start > 0 && (is_ident_char(text[start - 1]) || text[start - 1] == '.') {
+            start -= 1;
+        }
+
+        if start > 0 && text[start - 1] == '$' {
+            start -= 1;
+        }
+
+        let span = Span::new(line, start + 1, end - start);
+        let before: String = text[..start].iter().cloned().collect();
+
+        let completions = if text.get(start) == Some(&'$') {
+            register_completions()
+        } else {
+            match tokenize(&before, line) {
+                Ok(ref tokens) if mnemonic_index(tokens) == tokens.len() => opcode_completions(),
+                Ok(_) => self.symbol_completions(line),
+                Err(_) => Vec::new(),
+            }
+        };
+
+        (span, completions)
+    }
+
+    // labels and constants that make sense on the given line, with local labels written the way
+    // they'd be used in their own scope
+    fn symbol_completions(&self, line: usize) -> Vec<Completion> {
+        let scope = self.symbols
+            .iter()
+            .filter(|symbol| symbol.is_definition && symbol.span.line <= line)
+            .filter(|symbol| symbol.kind == SymbolKind::Label && is_scope_label(&symbol.name))
+            .last()
+            .map(|symbol| symbol.name.clone());
+
+        let mut completions = Vec::new();
+        let mut seen = HashSet::new();
+
+        let local = self.symbols
+            .iter()
+            .filter(|symbol| symbol.is_definition)
+            .map(|symbol| (&symbol.name, symbol.kind, "this file".to_string()));
+
+        let external = self.external_labels
+            .iter()
+            .map(|(name, location)| (name, SymbolKind::Label, location.to_string()))
+            .chain(self.external_constants
+                .iter()
+                .map(|(name, location)| (name, SymbolKind::Constant, location.to_string())));
+
+        for (name, kind, detail) in local.chain(external) {
+            let text = match scope {
+                Some(ref scope) if name.starts_with(&format!("{}.", scope)) => {
+                    name[scope.len()..].to_string()
+                }
+                _ if is_numeric_label(name) => continue,
+                _ => name.clone(),
+            };
+
+            if !seen.insert(text.clone()) {
+                continue;
+            }
+
+            let kind = match kind {
+                SymbolKind::Label => CompletionKind::Label,
+                SymbolKind::Constant => CompletionKind::Constant,
+            };
+
+            completions.push(Completion {
+                text: text,
+                kind: kind,
+                detail: detail,
+            });
+        }
+
+        completions.sort_by(|a, b| a.text.cmp(&b.text));
+        completions
+    }
+}
+
+fn opcode_completions() -> Vec<Completion> {
+    let opcodes = (0..).take_while(|&n| Opcode::index_is_valid(n)).map(|n| {
+        let opcode = Opcode::from(n);
+        Completion {
+            text: opcode.name().to_string(),
+            kind: CompletionKind::Opcode,
+            detail: count(opcode.arity(), "operand"),
+        }
+    });
+
+    let pseudo = PSEUDO_INSTRUCTIONS.iter().map(|&name| {
+        let arity = pseudo_arity(name).unwrap();
+        Completion {
+            text: name.to_string(),
+            kind: CompletionKind::Opcode,
+            detail: format!("pseudo-instruction, {}", count(arity, "operand")),
+        }
+    });
+
+    opcodes.chain(pseudo).collect()
+}
+
+fn register_completions() -> Vec<Completion> {
+    (0..REGISTER_COUNT as isize)
+        .map(|n| {
+            let r = Register::from(n);
+            Completion {
+                text: r.name().to_string(),
+                kind: CompletionKind::Register,
+                detail: r.role().to_string(),
+            }
+        })
+        .collect()
+}
+
+// the position of the instruction or directive after any labels at the start of a line
+fn mnemonic_index(tokens: &[Token]) -> usize {
+    let mut i = 0;
+    while i + 1 < tokens.len() && tokens[i + 1].kind == TokenKind::Colon {
+        i += 2;
+    }
+
+    if tokens.get(i).map(|token| &token.kind) == Some(&TokenKind::Percent) {
+        i += 1;
+    }
+
+    i
+}
+
+// labels and constants as the assembler names them, with local labels qualified by the label
+// before them and numeric labels numbered in order, so the same name means the same thing
+fn scan_symbols(lines: &[String]) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    let mut constants = HashSet::new();
+    let mut scope = String::new();
+    let mut numeric_labels: HashMap<String, usize> = HashMap::new();
+    let mut in_macro = false;
+
+    for (i, text) in lines.iter().enumerate() {
+        let tokens = match tokenize(text, i + 1) {
+            Ok(tokens) => tokens,
+            Err(_) => continue,
+        };
+
+        let mut pos = 0;
+        while pos + 1 < tokens.len() && tokens[pos + 1].kind == TokenKind::Colon {
+            let name = match tokens[pos].kind {
+                TokenKind::Ident(ref name) => {
+                    if !in_macro {
+                        scope = name.clone();
+                    }
+
+                    name.clone()
+                }
+                TokenKind::Directive(ref name) => format!("{}.{}", scope, name),
+                TokenKind::Decimal(ref digits) => {
+                    let count = numeric_labels.entry(digits.clone()).or_insert(0);
+                    *count += 1;
+                    format!("{}~{}", digits, *count - 1)
+                }
+                _ => break,
+            };
+
+            // labels in a macro body only exist once it's expanded
+            if !in_macro {
+                symbols.push(Symbol {
+                    name: name,
+                    span: tokens[pos].span,
+                    kind: SymbolKind::Label,
+                    is_definition: true,
+                });
+            }
+
+            pos += 2;
+        }
+
+        let rest = &tokens[pos..];
+        let operands = match rest.first().map(|token| &token.kind) {
+            Some(&TokenKind::Directive(ref name)) => {
+                match &name[..] {
+                    "macro" => {
+                        in_macro = true;
+                        continue;
+                    }
+                    "endm" => {
+                        in_macro = false;
+                        continue;
+                    }
+                    "include" | "include_once" => continue,
+                    "equ" => {
+                        if let Some(&TokenKind::Ident(ref name)) = rest.get(1).map(|t| &t.kind) {
+                            constants.insert(name.clone());
+                            symbols.push(Symbol {
+                                name: name.clone(),
+                                span: rest[1].span,
+                                kind: SymbolKind::Constant,
+                                is_definition: true,
+                            });
+                        }
+
+                        rest.get(2..).unwrap_or(&[])
+                    }
+                    _ => &rest[1..],
+                }
+            }
+            Some(&TokenKind::Percent) => rest.get(2..).unwrap_or(&[]),
+            Some(_) => &rest[1..],
+            None => continue,
+        };
+
+        for (j, token) in operands.iter().enumerate() {
+            let followed_by_paren = operands.get(j + 1).map(|t| &t.kind) ==
+                                    Some(&TokenKind::LParen);
+            let after_percent = j > 0 && operands[j - 1].kind == TokenKind::Percent;
+
+            let name = match token.kind {
+                TokenKind::Ident(_) if followed_by_paren || after_percent => continue,
+                TokenKind::Ident(ref name) if is_numeric_label_ref(name) => {
+                    let (digits, direction) = name.split_at(name.len() - 1);
+                    let count = numeric_labels.get(digits).cloned().unwrap_or(0);
+                    match direction {
+                        "f" => format!("{}~{}", digits, count),
+                        _ if count > 0 => format!("{}~{}", digits, count - 1),
+                        _ => name.clone(),
+                    }
+                }
+                TokenKind::Ident(ref name) => name.clone(),
+                TokenKind::Directive(ref name) => format!("{}.{}", scope, name),
+                _ => continue,
+            };
+
+            symbols.push(Symbol {
+                name: name,
+                span: token.span,
+                kind: SymbolKind::Label,
+                is_definition: false,
+            });
+        }
+    }
+
+    for symbol in &mut symbols {
+        if constants.contains(&symbol.name) {
+            symbol.kind = SymbolKind::Constant;
+        }
+    }
+
+    symbols
+}
+
+fn is_scope_label(name: &str) -> bool {
+    !name.contains('.') && !is_numeric_label(name)
+}
+
+fn span_contains(span: Span, line: usize, column: usize) -> bool {
+    span.line == line && span.column <= column && column <= span.column + span.len
+}
+
+fn count(n: usize, noun: &str) -> String {
+    let plural = if n == 1 { "" } else { "s" };
+    format!("{} {}{}", n, noun, plural)
+}
@@ -0,0 +1,114 @@
+use std::io;
+use std::io::prelude::*;
+
+use lsp::json::Json;
+
+pub const PARSE_ERROR: isize = -32700;
+pub const INVALID_REQUEST: isize = -32600;
+pub const METHOD_NOT_FOUND: isize = -32601;
+pub const INVALID_PARAMS: isize = -32602;
+
+// messages are framed by headers, of which only the content length matters
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+    let mut content_length = None;
+
+    loop {
+        let mut header = String::new();
+        if try!(reader.read_line(&mut header)) == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        let mut parts = header.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim();
+        let value = parts.next().unwrap_or("").trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.parse::<usize>().ok();
+        }
+    }
+
+    let len = match content_length {
+        Some(len) => len,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidData, "missing content length")),
+    };
+
+    let mut content = vec![0; len];
+    try!(reader.read_exact(&mut content));
+    String::from_utf8(content)
+        .map(Some)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "content isn't UTF-8"))
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Json) -> io::Result<()> {
+    let content = message.to_string();
+    try!(write!(writer, "Content-Length: {}\r\n\r\n{}", content.len(), content));
+    writer.flush()
+}
+
+pub fn response(id: Json, result: Json) -> Json {
+    Json::object(vec![("jsonrpc", Json::from("2.0")), ("id", id), ("result", result)])
+}
+
+pub fn error_response(id: Json, code: isize, message: &str) -> Json {
+    let error = Json::object(vec![("code", Json::Number(code as f64)),
+                                  ("message", Json::from(message))]);
+    Json::object(vec![("jsonrpc", Json::from("2.0")), ("id", id), ("error", error)])
+}
+
+pub fn notification(method: &str, params: Json) -> Json {
+    Json::object(vec![("jsonrpc", Json::from("2.0")),
+                      ("method", Json::from(method)),
+                      ("params", params)])
+}
+
+// file URIs are all the server deals with, and they map onto paths the assembler can include from
+pub fn uri_to_path(uri: &str) -> Option<String> {
+    if !uri.starts_with("file://") {
+        return None;
+    }
+
+    let encoded = uri["file://".len()..].as_bytes();
+    let mut bytes = Vec::new();
+    let mut i = 0;
+
+    while i < encoded.len() {
+        let escaped = if encoded[i] == b'%' && i + 2 < encoded.len() {
+            let hex = String::from_utf8_lossy(&encoded[i + 1..i + 3]).into_owned();
+            u8::from_str_radix(&hex, 16).ok()
+        } else {
+            None
+        };
+
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(encoded[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+pub fn path_to_uri(path: &str) -> String {
+    let mut uri = String::from("file://");
+
+    for &byte in path.as_bytes() {
+        let c = byte as char;
+        if c.is_ascii_alphanumeric() || "/-_.~".contains(c) {
+            uri.push(c);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    uri
+}
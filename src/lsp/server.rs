@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::prelude::*;
+
+use program::lexer::Span;
+use lsp::document::{Completion, CompletionKind, Document, Severity};
+use lsp::json::Json;
+use lsp::protocol::*;
+
+// LSP positions are zero-based and count UTF-16 code units, where spans are one-based and count
+// characters
+fn utf16_column(text: &str, column: usize) -> usize {
+    text.chars().take(column - 1).map(char::len_utf16).sum()
+}
+
+fn char_column(text: &str, utf16_column: usize) -> usize {
+    let mut units = 0;
+    let mut column = 1;
+    for c in text.chars() {
+        if units >= utf16_column {
+            break;
+        }
+
+        units += c.len_utf16();
+        column += 1;
+    }
+
+    column
+}
+
+pub struct Server<W: Write> {
+    writer: W,
+    include_paths: Vec<String>,
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+}
+
+impl<W: Write> Server<W> {
+    pub fn new(writer: W, include_paths: Vec<String>) -> Server<W> {
+        Server {
+            writer: writer,
+            include_paths: include_paths,
+            documents: HashMap::new(),
+            shutdown: false,
+        }
+    }
+
+    pub fn into_writer(self) -> W {
+        self.writer
+    }
+
+    // serves messages until `exit`, returning the exit code it asks for
+    pub fn run<R: BufRead>(&mut self, reader: &mut R) -> io::Result<i32> {
+        while let Some(content) = try!(read_message(reader)) {
+            let message = match Json::parse(&content) {
+                Ok(message) => message,
+                Err(e) => {
+                    let message = format!("invalid message: {}", e);
+                    try!(write_message(&mut self.writer,
+                                       &error_response(Json::Null, PARSE_ERROR, &message)));
+                    continue;
+                }
+            };
+
+            if message.get("method").and_then(Json::as_str) == Some("exit") {
+                return Ok(if self.shutdown { 0 } else { 1 });
+            }
+
+            try!(self.handle(&message));
+        }
+
+        Ok(1)
+    }
+
+    pub fn handle(&mut self, message: &Json) -> io::Result<()> {
+        let method = message.get("method").and_then(Json::as_str);
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => return self.handle_notification(method.unwrap_or(""), &params),
+        };
+
+        let method = match method {
+            Some(method) => method,
+            None => {
+                let error = error_response(id, INVALID_REQUEST, "missing method");
+                return write_message(&mut self.writer, &error);
+            }
+        };
+
+        let result = match method {
+            "initialize" => Some(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Some(Json::Null)
+            }
+            "textDocument/definition" => self.definition(&params),
+            "textDocument/references" => self.references(&params),
+            "textDocument/hover" => self.hover(&params),
+            "textDocument/completion" => self.completion(&params),
+            _ => {
+                let message = format!("unknown method `{}`", method);
+                let error = error_response(id, METHOD_NOT_FOUND, &message);
+                return write_message(&mut self.writer, &error);
+            }
+        };
+
+        let response = match result {
+            Some(result) => response(id, result),
+            None => error_response(id, INVALID_PARAMS, "invalid parameters"),
+        };
+
+        write_message(&mut self.writer, &response)
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Json) -> io::Result<()> {
+        let uri = match params.path(&["textDocument", "uri"]).and_then(Json::as_str) {
+            Some(uri) => uri.to_string(),
+            None => return Ok(()),
+        };
+
+        match method {
+            "textDocument/didOpen" => {
+                match params.path(&["textDocument", "text"]).and_then(Json::as_str) {
+                    Some(text) => self.update(uri, text),
+                    None => Ok(()),
+                }
+            }
+
+            // only whole documents are synchronized, so the last change has all of the text
+            "textDocument/didChange" => {
+                let text = params.get("contentChanges")
+                    .and_then(Json::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Json::as_str);
+
+                match text {
+                    Some(text) => self.update(uri, text),
+                    None => Ok(()),
+                }
+            }
+
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.publish_diagnostics(&uri, Vec::new())
+            }
+
+            _ => Ok(()),
+        }
+    }
+
+    fn update(&mut self, uri: String, text: &str) -> io::Result<()> {
+        let path = uri_to_path(&uri).unwrap_or_else(|| uri.clone());
+        let document = Document::new(&path, text, &self.include_paths);
+
+        let diagnostics = document.diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let severity = match diagnostic.severity {
+                    Severity::Error => 1,
+                    Severity::Warning => 2,
+                };
+
+                Json::object(vec![("range", range(&document, diagnostic.span)),
+                                  ("severity", Json::from(severity)),
+                                  ("source", Json::from("tern")),
+                                  ("message", Json::from(&diagnostic.message[..]))])
+            })
+            .collect();
+
+        self.documents.insert(uri.clone(), document);
+        self.publish_diagnostics(&uri, diagnostics)
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str, diagnostics: Vec<Json>) -> io::Result<()> {
+        let params = Json::object(vec![("uri", Json::from(uri)),
+                                       ("diagnostics", Json::Array(diagnostics))]);
+        write_message(&mut self.writer,
+                      &notification("textDocument/publishDiagnostics", params))
+    }
+
+    // the document a request is about and the one-based line and column it points at
+    fn position(&self, params: &Json) -> Option<(&Document, usize, usize)> {
+        let uri = params.path(&["textDocument", "uri"]).and_then(Json::as_str);
+        let line = params.path(&["position", "line"]).and_then(Json::as_usize);
+        let character = params.path(&["position", "character"]).and_then(Json::as_usize);
+
+        match (uri.and_then(|uri| self.documents.get(uri)), line, character) {
+            (Some(document), Some(line), Some(character)) => {
+                let text = document.lines.get(line).map_or("", |text| &text[..]);
+                Some((document, line + 1, char_column(text, character)))
+            }
+            _ => None,
+        }
+    }
+
+    fn definition(&self, params: &Json) -> Option<Json> {
+        let (document, line, column) = match self.position(params) {
+            Some(position) => position,
+            None => return None,
+        };
+
+        let definition = match document.definition(line, column) {
+            Some(definition) => definition,
+            None => return Some(Json::Null),
+        };
+
+        let path = definition.path.unwrap_or_else(|| document.path.clone());
+        let range = if path == document.path {
+            range(document, definition.span)
+        } else {
+            // columns in other files are only known in characters, which is exact for ASCII
+            let span = definition.span;
+            Json::object(vec![("start", position(span.line - 1, span.column - 1)),
+                              ("end", position(span.line - 1, span.column - 1 + span.len))])
+        };
+
+        Some(Json::object(vec![("uri", Json::from(path_to_uri(&path))), ("range", range)]))
+    }
+
+    fn references(&self, params: &Json) -> Option<Json> {
+        let (document, line, column) = match self.position(params) {
+            Some(position) => position,
+            None => return None,
+        };
+
+        let include_declaration = params.path(&["context", "includeDeclaration"])
+            .and_then(Json::as_bool)
+            .unwrap_or(true);
+
+        let uri = path_to_uri(&document.path);
+        let locations = document.references(line, column, include_declaration)
+            .into_iter()
+            .map(|span| {
+                Json::object(vec![("uri", Json::from(&uri[..])), ("range", range(document, span))])
+            })
+            .collect();
+
+        Some(Json::Array(locations))
+    }
+
+    fn hover(&self, params: &Json) -> Option<Json> {
+        let (document, line, column) = match self.position(params) {
+            Some(position) => position,
+            None => return None,
+        };
+
+        Some(document.hover(line, column).map_or(Json::Null, |text| {
+            let contents = Json::object(vec![("kind", Json::from("markdown")),
+                                             ("value", Json::from(text))]);
+            Json::object(vec![("contents", contents)])
+        }))
+    }
+
+    fn completion(&self, params: &Json) -> Option<Json> {
+        let (document, line, column) = match self.position(params) {
+            Some(position) => position,
+            None => return None,
+        };
+
+        let (span, completions) = document.completions(line, column);
+        let items = completions.into_iter()
+            .map(|completion| completion_item(document, span, completion))
+            .collect();
+
+        Some(Json::Array(items))
+    }
+}
+
+fn capabilities() -> Json {
+    let completion = Json::object(vec![("triggerCharacters",
+                                        Json::from(vec![Json::from("$"), Json::from(".")]))]);
+
+    let capabilities = Json::object(vec![("textDocumentSync", Json::from(1)),
+                                         ("definitionProvider", Json::from(true)),
+                                         ("referencesProvider", Json::from(true)),
+                                         ("hoverProvider", Json::from(true)),
+                                         ("completionProvider", completion)]);
+
+    let server_info = Json::object(vec![("name", Json::from("tern"))]);
+    Json::object(vec![("capabilities", capabilities), ("serverInfo", server_info)])
+}
+
+fn completion_item(document: &Document, span: Span, completion: Completion) -> Json {
+    // keyword, variable, function and constant
+    let kind = match completion.kind {
+        CompletionKind::Opcode => 14,
+        CompletionKind::Register => 6,
+        CompletionKind::Label => 3,
+        CompletionKind::Constant => 21,
+    };
+
+    let edit = Json::object(vec![("range", range(document, span)),
+                                 ("newText", Json::from(&completion.text[..]))]);
+
+    Json::object(vec![("label", Json::from(completion.text)),
+                      ("kind", Json::from(kind)),
+                      ("detail", Json::from(completion.detail)),
+                      ("textEdit", edit)])
+}
+
+fn position(line: usize, character: usize) -> Json {
+    Json::object(vec![("line", Json::from(line)), ("character", Json::from(character))])
+}
+
+fn range(document: &Document, span: Span) -> Json {
+    let text = document.lines.get(span.line - 1).map_or("", |text| &text[..]);
+    let start = utf16_column(text, span.column);
+    let end = utf16_column(text, span.column + span.len);
+
+    Json::object(vec![("start", position(span.line - 1, start)),
+                      ("end", position(span.line - 1, end))])
+}
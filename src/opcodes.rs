@@ -1,6 +1,8 @@
 use std::fmt;
 use std::mem::transmute;
 
+use types::*;
+
 #[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
 pub enum Opcode {
     Mov = 0, // mov REG, REG
@@ -168,6 +170,13 @@ impl Opcode {
             Opcode::Halt => 0,
         }
     }
+
+    pub fn size(&self) -> usize {
+        match *self {
+            Opcode::Movw | Opcode::Mova | Opcode::Jmp | Opcode::Call => WORD_SIZE * 2,
+            _ => WORD_SIZE,
+        }
+    }
 }
 
 impl From<isize> for Opcode {
@@ -0,0 +1,112 @@
+use std::fmt;
+
+use program::object::{FormatError, ObjectFile, Visibility};
+
+static ARCHIVE_HEADER: &'static str = "tern-archive";
+
+// members are stored whole, after an index of the global symbols each one defines. the index
+// refers to members by position, since their names needn't be unique
+#[derive(Clone, Debug)]
+pub struct Archive {
+    pub members: Vec<(String, ObjectFile)>,
+    pub index: Vec<(String, usize)>,
+}
+
+impl Archive {
+    pub fn new() -> Archive {
+        Archive {
+            members: Vec::new(),
+            index: Vec::new(),
+        }
+    }
+
+    pub fn add_member(&mut self, name: String, object: ObjectFile) {
+        let i = self.members.len();
+        for symbol in &object.symbols {
+            if symbol.visibility == Visibility::Global {
+                self.index.push((symbol.name.clone(), i));
+            }
+        }
+
+        self.members.push((name, object));
+    }
+
+    // the first member to define a symbol is the one that provides it
+    pub fn find_member(&self, symbol: &str) -> Option<usize> {
+        self.index.iter().find(|&&(ref name, _)| name == symbol).map(|&(_, i)| i)
+    }
+
+    pub fn is_archive(s: &str) -> bool {
+        s.lines().next() == Some(ARCHIVE_HEADER)
+    }
+
+    pub fn parse(s: &str) -> Result<Archive, FormatError> {
+        let mut archive = Archive::new();
+        let lines: Vec<&str> = s.lines().collect();
+
+        if lines.first() != Some(&ARCHIVE_HEADER) {
+            return Err(FormatError { line: 1 });
+        }
+
+        let mut index = Vec::new();
+        let mut i = 1;
+        while i < lines.len() {
+            let error = FormatError { line: i + 1 };
+            let fields: Vec<&str> = lines[i].split_whitespace().collect();
+            i += 1;
+
+            match fields.first().cloned() {
+                None => {}
+
+                Some("index") if fields.len() == 3 => {
+                    let member: usize =
+                        try!(fields[2].parse().map_err(|_| FormatError { line: error.line }));
+                    index.push((fields[1].to_string(), member, error));
+                }
+
+                Some("member") if fields.len() == 3 => {
+                    let len: usize = try!(fields[2].parse().map_err(|_| error));
+                    if i + len > lines.len() {
+                        return Err(FormatError { line: i });
+                    }
+
+                    let contents = lines[i..i + len].join("\n");
+                    let object = try!(ObjectFile::parse(&contents)
+                        .map_err(|e| FormatError { line: i + e.line }));
+                    archive.members.push((fields[1].to_string(), object));
+                    i += len;
+                }
+
+                _ => return Err(error),
+            }
+        }
+
+        for (symbol, member, error) in index {
+            if member >= archive.members.len() {
+                return Err(error);
+            }
+
+            archive.index.push((symbol, member));
+        }
+
+        Ok(archive)
+    }
+}
+
+impl fmt::Display for Archive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "{}", ARCHIVE_HEADER));
+
+        for &(ref symbol, i) in &self.index {
+            try!(writeln!(f, "index {} {}", symbol, i));
+        }
+
+        for &(ref name, ref object) in &self.members {
+            let contents = object.to_string();
+            try!(writeln!(f, "member {} {}", name, contents.lines().count()));
+            try!(write!(f, "{}", contents));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use registers::Register;
+use program::lexer::Span;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, span: Span) -> Spanned<T> {
+        Spanned {
+            node: node,
+            span: span,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Operand {
+    Register(Register),
+    Decimal(isize),
+    // a number too big for an isize, kept as written so it can be reported as out of range
+    Oversized(String),
+    Ternary(String),
+    Label(String),
+    String(String),
+    Data(Box<DataSpec>),
+    Expr(Box<Expr>),
+}
+
+impl Operand {
+    pub fn description(&self) -> &'static str {
+        match *self {
+            Operand::Register(_) => "register",
+            Operand::Decimal(_) => "decimal",
+            Operand::Oversized(_) => "number",
+            Operand::Ternary(_) => "ternary",
+            Operand::Label(_) => "label",
+            Operand::String(_) => "string",
+            Operand::Data(_) => "data",
+            Operand::Expr(_) => "expression",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Expr {
+    Neg(Spanned<Operand>),
+    Binary(BinOp, Spanned<Operand>, Spanned<Operand>),
+    Sizeof(Spanned<String>),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DataSpec {
+    pub type_name: Spanned<String>,
+    pub args: Vec<Spanned<Operand>>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Statement {
+    Label(Spanned<String>),
+    Instruction(Spanned<String>, Vec<Spanned<Operand>>),
+    Data(DataSpec),
+    Directive(Spanned<String>, Spanned<String>),
+    Constant(Spanned<String>, Spanned<Operand>),
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match *self {
+            Statement::Label(ref label) => label.span,
+            Statement::Instruction(ref opcode, ref args) => {
+                args.last().map_or(opcode.span, |arg| opcode.span.to(arg.span))
+            }
+            Statement::Data(ref data) => data.type_name.span,
+            Statement::Directive(ref name, _) => name.span,
+            Statement::Constant(ref name, ref value) => name.span.to(value.span),
+        }
+    }
+}
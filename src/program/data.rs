@@ -9,6 +9,9 @@ pub enum StaticData {
     Word(isize),
     String(String),
     Array(Box<StaticData>, usize),
+    List(Vec<StaticData>),
+    Space(usize),
+    Align(usize),
 }
 
 #[derive(Debug)]
@@ -24,6 +27,9 @@ impl StaticData {
             StaticData::Word(_) => WORD_SIZE,
             StaticData::String(ref s) => WORD_SIZE + s.len() * TRYTE_SIZE,
             StaticData::Array(ref data, count) => data.size() * count,
+            StaticData::List(ref items) => items.iter().map(|data| data.size()).sum(),
+            StaticData::Space(size) => size,
+            StaticData::Align(_) => 0,
         }
     }
 
@@ -31,10 +37,22 @@ impl StaticData {
         match *self {
             StaticData::Tryte(_) => TRYTE_SIZE,
             StaticData::Half(_) => HALF_SIZE,
+            StaticData::List(ref items) => items.first().map_or(TRYTE_SIZE, StaticData::alignment),
+            StaticData::Space(_) => TRYTE_SIZE,
+            StaticData::Align(alignment) => alignment,
             _ => WORD_SIZE,
         }
     }
 
+    // reserved space is left zeroed, so it can live outside the image
+    pub fn is_uninitialized(&self) -> bool {
+        match *self {
+            StaticData::Space(_) |
+            StaticData::Align(_) => true,
+            _ => false,
+        }
+    }
+
     pub unsafe fn write(&self, memory: *mut Trit) -> usize {
         match *self {
             StaticData::Tryte(i) => {
@@ -55,12 +73,29 @@ impl StaticData {
             StaticData::String(ref s) => text::encode_str(memory, &s[..]) * TRYTE_SIZE + WORD_SIZE,
 
             StaticData::Array(ref data, count) => {
-                for _ in 0..count {
-                    data.write(memory);
+                let size = data.size();
+                for i in 0..count {
+                    data.write(memory.offset((i * size) as isize));
                 }
 
-                data.size() * count
+                size * count
             }
+
+            StaticData::List(ref items) => {
+                let mut offset = 0;
+                for data in items {
+                    offset += data.write(memory.offset(offset as isize));
+                }
+
+                offset
+            }
+
+            StaticData::Space(size) => {
+                ternary::clear(memory, size as isize);
+                size
+            }
+
+            StaticData::Align(_) => 0,
         }
     }
 }
@@ -1,30 +1,112 @@
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io;
+use std::path::{Path, PathBuf};
 use std::io::prelude::*;
+use std::mem::replace;
 
 use types::*;
+use program::ast::Statement;
+use opcodes::Opcode;
+use program::ast::{DataSpec, Expr, Operand, Spanned};
+use program::lexer::{is_ident_char, is_ident_start, is_numeric_label_ref, Span};
+use program::location::{Expansion, Location};
+use program::macros::{span_text, Macro};
 use program::parser::*;
+use program::encode::EncodeError;
+use program::symbols::{numeric_label, SymbolTable};
 use util::next_aligned_addr;
 
-#[derive(Clone, Debug)]
-pub enum ReadMode {
+const MAX_MACRO_DEPTH: usize = 64;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Section {
     Code,
     Data,
+    ReadOnlyData,
+    Bss,
 }
 
-#[derive(Clone, Debug)]
+impl Section {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Section::Code => "code",
+            Section::Data => "data",
+            Section::ReadOnlyData => "rodata",
+            Section::Bss => "bss",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Section> {
+        match name {
+            "code" => Some(Section::Code),
+            "data" => Some(Section::Data),
+            "rodata" => Some(Section::ReadOnlyData),
+            "bss" => Some(Section::Bss),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct DecodedProgram {
-    read_mode: ReadMode,
+    section: Section,
+    path: Option<String>,
+    line_number: usize,
+    errors: Vec<ParseError>,
+    constants: Vec<(Spanned<String>, Spanned<Operand>, Location)>,
+    code_statements: Vec<(Statement, Location)>,
+    data_statements: Vec<(Statement, Location)>,
+    rodata_statements: Vec<(Statement, Location)>,
+    bss_statements: Vec<(Statement, Location)>,
+    macros: HashMap<String, Macro>,
+    recording: Option<Macro>,
+    expansion: Option<Box<Expansion>>,
+    expansion_count: usize,
+    label_scope: String,
+    numeric_labels: HashMap<String, usize>,
+    include_paths: Vec<PathBuf>,
+    include_stack: Vec<PathBuf>,
+    included: HashSet<PathBuf>,
+    relocatable: bool,
     pub code: Vec<CodeDecl>,
     pub data: Vec<DataDecl>,
+    pub rodata: Vec<DataDecl>,
+    pub bss: Vec<DataDecl>,
+    pub label_refs: HashSet<String>,
+    pub range_errors: Vec<EncodeError>,
+    pub globals: HashMap<String, Location>,
 }
 
 impl DecodedProgram {
     pub fn new() -> DecodedProgram {
         DecodedProgram {
-            read_mode: ReadMode::Code,
+            section: Section::Code,
+            path: None,
+            line_number: 0,
+            errors: Vec::new(),
+            constants: Vec::new(),
+            code_statements: Vec::new(),
+            data_statements: Vec::new(),
+            rodata_statements: Vec::new(),
+            bss_statements: Vec::new(),
+            macros: HashMap::new(),
+            recording: None,
+            expansion: None,
+            expansion_count: 0,
+            label_scope: String::new(),
+            numeric_labels: HashMap::new(),
+            include_paths: Vec::new(),
+            include_stack: Vec::new(),
+            included: HashSet::new(),
+            relocatable: false,
             code: Vec::new(),
             data: Vec::new(),
+            rodata: Vec::new(),
+            bss: Vec::new(),
+            label_refs: HashSet::new(),
+            range_errors: Vec::new(),
+            globals: HashMap::new(),
         }
     }
 
@@ -33,11 +115,11 @@ impl DecodedProgram {
 
         for ref code_decl in &self.code[..] {
             match **code_decl {
-                CodeDecl::Label(_) => {
+                CodeDecl::Label(_, _) => {
                     pc = next_aligned_addr(pc, WORD_SIZE);
                 }
 
-                CodeDecl::Instruction(ref instruction) => {
+                CodeDecl::Instruction(ref instruction, _, _) => {
                     pc += instruction.size();
                 }
             }
@@ -47,128 +129,733 @@ impl DecodedProgram {
     }
 
     pub fn data_size(&self) -> usize {
-        let mut pc = 0;
+        section_size(&self.data[..])
+    }
 
-        for data_decl in &self.data[..] {
-            match *data_decl {
-                DataDecl::Label(_) => {
-                    pc = next_aligned_addr(pc, WORD_SIZE);
-                }
+    pub fn rodata_size(&self) -> usize {
+        section_size(&self.rodata[..])
+    }
 
-                DataDecl::Data(ref data) => {
-                    pc = next_aligned_addr(pc, data.alignment());
-                    pc += data.size();
-                }
-            }
-        }
+    pub fn bss_size(&self) -> usize {
+        section_size(&self.bss[..])
+    }
 
-        pc
+    pub fn rodata_addr(&self) -> Addr {
+        next_aligned_addr(WORD_SIZE * 2 + self.data_size(), WORD_SIZE)
     }
 
-    pub fn size(&self) -> usize {
-        let mut pc = WORD_SIZE * 2;
-        pc += self.data_size();
+    // everything but .bss, which only needs zeroed memory once the program is loaded
+    pub fn image_size(&self) -> usize {
+        let mut pc = self.rodata_addr();
+        pc += self.rodata_size();
         pc = next_aligned_addr(pc, WORD_SIZE);
         pc += self.code_size();
         pc
     }
 
-    pub fn read_file<'a>(&mut self, path: &'a str) -> Result<(), ParseError> {
-        let file = try!(File::open(path).map_err(ParseError::IOError));
-        self.read(file)
+    pub fn size(&self) -> usize {
+        next_aligned_addr(self.image_size(), WORD_SIZE) + self.bss_size()
+    }
+
+    pub fn read_file<'a>(&mut self, path: &'a str) -> Result<(), Vec<ParseError>> {
+        let result = self.include_file(PathBuf::from(path), false);
+        self.finish_reading(result)
+    }
+
+    pub fn read<R: Read>(&mut self, reader: R) -> Result<(), Vec<ParseError>> {
+        let result = self.with_source(None, |program| program.read_lines(reader));
+        self.finish_reading(result)
+    }
+
+    pub fn read_str(&mut self, s: &str) -> Result<(), Vec<ParseError>> {
+        self.read_source(None, s)
+    }
+
+    // includes are looked up and errors reported as if `s` had been read from `path`
+    pub fn read_str_as(&mut self, path: &str, s: &str) -> Result<(), Vec<ParseError>> {
+        self.read_source(Some(path.to_string()), s)
+    }
+
+    fn read_source(&mut self, path: Option<String>, s: &str) -> Result<(), Vec<ParseError>> {
+        let result = self.with_source(path, |program| {
+            for raw_line in s.lines() {
+                program.read_line_or_record(raw_line);
+            }
+
+            Ok(())
+        });
+
+        self.finish_reading(result)
     }
 
-    pub fn read<R: Read>(&mut self, reader: R) -> Result<(), ParseError> {
+    fn finish_reading(&mut self, result: Result<(), ParseError>) -> Result<(), Vec<ParseError>> {
+        if let Err(e) = result {
+            self.errors.push(e);
+        }
+
+        self.resolve();
+
+        // reading and resolving are separate passes, so put the errors back in source order
+        self.errors.sort_by_key(|error| {
+            let position = error.position();
+            (position.is_none(), position)
+        });
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(replace(&mut self.errors, Vec::new()))
+        }
+    }
+
+    pub fn constant_location(&self, name: &str) -> Option<&Location> {
+        self.constants
+            .iter()
+            .find(|&&(ref constant, _, _)| constant.node == name)
+            .map(|&(_, _, ref location)| location)
+    }
+
+    // label addresses aren't known until link time, so expressions can only use the distance
+    // between two labels in the same section
+    pub fn set_relocatable(&mut self, relocatable: bool) {
+        self.relocatable = relocatable;
+    }
+
+    pub fn add_include_path<P: AsRef<Path>>(&mut self, path: P) {
+        self.include_paths.push(path.as_ref().to_path_buf());
+    }
+
+    // relative includes are looked up next to the including file first, then in the include
+    // paths in the order they were added
+    fn find_include(&self, name: &str) -> Result<PathBuf, ParseError> {
+        let path = Path::new(name);
+        if path.is_absolute() {
+            return Ok(path.to_path_buf());
+        }
+
+        let local = match self.path.as_ref().and_then(|current| Path::new(current).parent()) {
+            Some(dir) => dir.join(path),
+            None => path.to_path_buf(),
+        };
+
+        let not_found = ParseErrorKind::IncludeNotFound(name.to_string());
+        Some(local)
+            .into_iter()
+            .chain(self.include_paths.iter().map(|dir| dir.join(path)))
+            .find(|candidate| candidate.is_file())
+            .ok_or_else(|| ParseError::without_span(not_found))
+    }
+
+    fn include_file(&mut self, path: PathBuf, once: bool) -> Result<(), ParseError> {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if self.include_stack.contains(&canonical) {
+            let name = path.to_string_lossy().into_owned();
+            return Err(ParseError::without_span(ParseErrorKind::IncludeCycle(name)));
+        }
+
+        if once && self.included.contains(&canonical) {
+            return Ok(());
+        }
+
+        let file = try!(File::open(&path)
+            .map_err(|e| ParseError::without_span(ParseErrorKind::IOError(e))));
+
+        self.included.insert(canonical.clone());
+        self.include_stack.push(canonical);
+
+        let name = path.to_string_lossy().into_owned();
+        let result = self.with_source(Some(name), |program| program.read_lines(file));
+
+        self.include_stack.pop();
+        result
+    }
+
+    fn read_lines<R: Read>(&mut self, reader: R) -> Result<(), ParseError> {
         let buffer = io::BufReader::new(reader);
         for line_result in buffer.lines() {
-            let raw_line = try!(line_result.map_err(ParseError::IOError));
-            try!(self.read_line(&raw_line[..]));
+            let raw_line = try!(line_result.map_err(|e| {
+                let mut error = ParseError::without_span(ParseErrorKind::IOError(e));
+                error.path = self.path.clone();
+                error
+            }));
+
+            self.read_line_or_record(&raw_line[..]);
         }
 
         Ok(())
     }
 
-    pub fn read_str(&mut self, s: &str) -> Result<(), ParseError> {
-        for raw_line in s.lines() {
-            try!(self.read_line(raw_line));
+    // keep going past a bad line so every error in the file gets reported
+    fn read_line_or_record(&mut self, raw_line: &str) {
+        if let Err(e) = self.read_line(raw_line) {
+            self.errors.push(e);
         }
+    }
 
-        Ok(())
+    fn with_source<F>(&mut self, path: Option<String>, f: F) -> Result<(), ParseError>
+        where F: FnOnce(&mut DecodedProgram) -> Result<(), ParseError>
+    {
+        let old_path = replace(&mut self.path, path);
+        let old_line_number = replace(&mut self.line_number, 0);
+
+        let mut result = f(self);
+
+        if let Some(definition) = self.recording.take() {
+            let error = ParseError::new(ParseErrorKind::UnterminatedMacro(definition.name),
+                                        definition.location.span)
+                .with_location(&definition.location);
+            result = result.and(Err(error));
+        }
+
+        self.path = old_path;
+        self.line_number = old_line_number;
+        result
     }
 
     pub fn read_line(&mut self, raw_line: &str) -> Result<(), ParseError> {
-        let line = clean_line(raw_line);
-        let tokens: Vec<&str> = line.split_whitespace().collect();
+        self.line_number += 1;
 
-        if tokens.len() == 0 {
-            return Ok(());
+        if self.recording.is_some() {
+            return self.record_macro_line(raw_line);
         }
 
-        match tokens[0] {
-            ".include" => {
-                let paths = &tokens[1..];
-                for path in paths {
-                    try!(self.read_file(path));
+        self.read_source_line(raw_line)
+    }
+
+    fn read_source_line(&mut self, raw_line: &str) -> Result<(), ParseError> {
+        let result = parse_line(raw_line, self.line_number)
+            .and_then(|statements| {
+                for statement in statements {
+                    try!(self.read_statement(statement, raw_line));
                 }
-            }
 
-            ".code" => {
-                self.read_mode = ReadMode::Code;
+                Ok(())
+            });
+
+        let path = self.path.clone();
+        let expansion = self.expansion.clone();
+        result.map_err(|e| {
+            e.with_source(path.as_ref().map(|s| &s[..]), raw_line)
+                .with_expansion(expansion.as_ref())
+        })
+    }
+
+    fn record_macro_line(&mut self, raw_line: &str) -> Result<(), ParseError> {
+        let code = raw_line.split(';').next().unwrap_or("");
+        let directive = code.split_whitespace().next();
+        let column = code.find(|c: char| !c.is_whitespace()).unwrap_or(0) + 1;
+
+        match directive {
+            Some(".endm") => {
+                let definition = self.recording.take().unwrap();
+                self.macros.insert(definition.name.clone(), definition);
+                Ok(())
             }
 
-            ".data" => {
-                self.read_mode = ReadMode::Data;
+            Some(".macro") => {
+                let span = Span::new(self.line_number, column, ".macro".len());
+                let path = self.path.clone();
+                Err(ParseError::new(ParseErrorKind::NestedMacro, span)
+                    .with_source(path.as_ref().map(|s| &s[..]), raw_line))
             }
 
             _ => {
-                match self.read_mode {
-                    ReadMode::Data => {
-                        self.data.push(try!(parse_data_line(line)));
+                let line = (self.line_number, raw_line.to_string());
+                self.recording.as_mut().unwrap().body.push(line);
+                Ok(())
+            }
+        }
+    }
+
+    fn begin_macro(&mut self, args: &Spanned<String>, raw_line: &str) -> Result<(), ParseError> {
+        let mut words = args.node
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|word| !word.is_empty())
+            .map(|word| word.to_string());
+
+        let name = match words.next() {
+            Some(name) => name,
+            None => return Err(ParseError::new(ParseErrorKind::UnexpectedEnd, args.span)),
+        };
+
+        let params: Vec<String> = words.collect();
+
+        for word in Some(&name).into_iter().chain(params.iter()) {
+            let valid = word.starts_with(is_ident_start) && word.chars().all(is_ident_char);
+            if !valid || (*word == name && Opcode::name_is_valid(word)) {
+                return Err(ParseError::new(ParseErrorKind::InvalidMacroName(word.clone()),
+                                           args.span));
+            }
+        }
+
+        if self.macros.contains_key(&name) {
+            return Err(ParseError::new(ParseErrorKind::DuplicateMacro(name), args.span));
+        }
+
+        let mut location = Location::new(self.path.clone(), args.span, raw_line);
+        location.expansion = self.expansion.clone();
+        self.recording = Some(Macro::new(name, params, location));
+        Ok(())
+    }
+
+    fn expand_macro(&mut self,
+                    name: &Spanned<String>,
+                    args: &[Spanned<Operand>],
+                    call_site: Location)
+                    -> Result<(), ParseError> {
+        let definition = self.macros[&name.node].clone();
+
+        if args.len() != definition.params.len() {
+            let kind = ParseErrorKind::InvalidArity(name.node.clone(),
+                                                    definition.params.len(),
+                                                    args.len());
+            return Err(ParseError::new(kind, call_site.span));
+        }
+
+        let mut depth = 0;
+        let mut expansion = self.expansion.as_ref();
+        while let Some(e) = expansion {
+            depth += 1;
+            expansion = e.call_site.expansion.as_ref();
+        }
+
+        if depth >= MAX_MACRO_DEPTH {
+            return Err(ParseError::new(ParseErrorKind::MacroRecursion(name.node.clone()),
+                                       name.span));
+        }
+
+        let arg_texts: Vec<String> = args.iter()
+            .map(|arg| span_text(&call_site.source_line, arg.span))
+            .collect();
+
+        self.expansion_count += 1;
+        let lines = definition.expand(&arg_texts, self.expansion_count);
+
+        let expansion = Box::new(Expansion {
+            name: name.node.clone(),
+            call_site: call_site,
+        });
+
+        let old_path = replace(&mut self.path, definition.location.path.clone());
+        let old_line_number = self.line_number;
+        let old_expansion = replace(&mut self.expansion, Some(expansion));
+
+        for (line_number, text) in lines {
+            self.line_number = line_number;
+            if let Err(e) = self.read_source_line(&text) {
+                self.errors.push(e);
+            }
+        }
+
+        self.path = old_path;
+        self.line_number = old_line_number;
+        self.expansion = old_expansion;
+        Ok(())
+    }
+
+    fn read_statement(&mut self, statement: Statement, raw_line: &str) -> Result<(), ParseError> {
+        if let Statement::Directive(ref name, ref args) = statement {
+            match &name.node[..] {
+                "include" | "include_once" => {
+                    let once = name.node == "include_once";
+                    for word in args.node.split_whitespace() {
+                        let path = try!(self.find_include(word.trim_matches('"'))
+                            .map_err(|e| e.with_span(args.span)));
+                        try!(self.include_file(path, once).map_err(|e| e.with_span(args.span)));
                     }
+                }
+
+                "global" => {
+                    let mut column = args.span.column;
+                    for label in args.node.split(|c: char| c == ',' || c.is_whitespace()) {
+                        let len = label.chars().count();
+                        if !label.is_empty() {
+                            let span = Span::new(args.span.line, column, len);
+                            let location = Location::new(self.path.clone(), span, raw_line);
+                            self.globals.entry(label.to_string()).or_insert(location);
+                        }
 
-                    ReadMode::Code => {
-                        self.code.push(try!(parse_code_line(line)));
+                        column += len + 1;
                     }
                 }
+
+                "code" | "data" | "rodata" | "bss" => {
+                    self.section = Section::from_name(&name.node).unwrap();
+                }
+
+                "macro" => {
+                    try!(self.begin_macro(args, raw_line));
+                }
+
+                "endm" => {
+                    return Err(ParseError::new(ParseErrorKind::UnmatchedEndm, name.span));
+                }
+
+                _ => {
+                    return Err(ParseError::new(ParseErrorKind::InvalidDirective(name.node.clone()),
+                                               name.span));
+                }
+            }
+
+            return Ok(());
+        }
+
+        let mut location = Location::new(self.path.clone(), statement.span(), raw_line);
+        location.expansion = self.expansion.clone();
+
+        if let Statement::Instruction(ref name, ref args) = statement {
+            if self.macros.contains_key(&name.node) {
+                return self.expand_macro(name, args, location);
+            }
+        }
+
+        let statement = self.qualify_labels(statement);
+
+        match (statement, self.section) {
+            (Statement::Constant(name, value), _) => {
+                self.constants.push((name, value, location));
+            }
+
+            (statement @ Statement::Label(_), Section::Code) |
+            (statement @ Statement::Instruction(_, _), Section::Code) => {
+                self.code_statements.push((statement, location));
+            }
+
+            (statement, Section::Code) => {
+                return Err(ParseError::new(ParseErrorKind::InvalidCodeSection, statement.span()));
+            }
+
+            (statement @ Statement::Label(_), Section::Data) |
+            (statement @ Statement::Data(_), Section::Data) => {
+                self.data_statements.push((statement, location));
+            }
+
+            (statement @ Statement::Label(_), Section::ReadOnlyData) |
+            (statement @ Statement::Data(_), Section::ReadOnlyData) => {
+                self.rodata_statements.push((statement, location));
+            }
+
+            (statement @ Statement::Label(_), Section::Bss) |
+            (statement @ Statement::Data(_), Section::Bss) => {
+                self.bss_statements.push((statement, location));
+            }
+
+            (statement, section) => {
+                let kind = ParseErrorKind::InvalidDataSection(section.name());
+                return Err(ParseError::new(kind, statement.span()));
             }
         }
 
         Ok(())
     }
 
-    pub fn debug(&self) {
-        self.print_data();
-        println!("");
-        self.print_code();
+    // local and numeric labels get names that are unique across the program, so the
+    // encoder can treat every label the same way
+    fn qualify_labels(&mut self, statement: Statement) -> Statement {
+        match statement {
+            Statement::Label(label) => {
+                let name = self.define_label(label.node);
+                Statement::Label(Spanned::new(name, label.span))
+            }
+
+            Statement::Instruction(opcode, args) => {
+                let args = args.into_iter().map(|arg| self.qualify_operand(arg)).collect();
+                Statement::Instruction(opcode, args)
+            }
+
+            Statement::Data(spec) => Statement::Data(self.qualify_data_spec(spec)),
+
+            Statement::Constant(name, value) => {
+                let value = self.qualify_operand(value);
+                Statement::Constant(name, value)
+            }
+
+            statement => statement,
+        }
     }
 
-    fn print_data(&self) {
-        println!(".data");
-        for data_decl in self.data.iter().cloned() {
-            match data_decl {
-                DataDecl::Label(label) => {
-                    println!("{}:", label);
+    fn define_label(&mut self, name: String) -> String {
+        if name.starts_with('.') {
+            return format!("{}{}", self.label_scope, name);
+        }
+
+        if name.chars().all(|c| c.is_digit(10)) {
+            let count = self.numeric_labels.entry(name.clone()).or_insert(0);
+            *count += 1;
+            return numeric_label(&name, *count - 1);
+        }
+
+        // labels a macro makes for itself shouldn't end the scope of the code around it
+        if self.expansion.is_none() {
+            self.label_scope = name.clone();
+        }
+
+        name
+    }
+
+    fn qualify_label(&self, name: &str) -> String {
+        if name.starts_with('.') {
+            return format!("{}{}", self.label_scope, name);
+        }
+
+        if is_numeric_label_ref(name) {
+            let (digits, direction) = name.split_at(name.len() - 1);
+            let count = self.numeric_labels.get(digits).cloned().unwrap_or(0);
+
+            return match direction {
+                "f" => numeric_label(digits, count),
+                _ if count > 0 => numeric_label(digits, count - 1),
+                _ => name.to_string(),
+            };
+        }
+
+        name.to_string()
+    }
+
+    fn qualify_operand(&self, arg: Spanned<Operand>) -> Spanned<Operand> {
+        let operand = match arg.node {
+            Operand::Label(name) => Operand::Label(self.qualify_label(&name)),
+
+            Operand::Data(spec) => Operand::Data(Box::new(self.qualify_data_spec(*spec))),
+
+            Operand::Expr(expr) => {
+                let expr = match *expr {
+                    Expr::Neg(operand) => Expr::Neg(self.qualify_operand(operand)),
+                    Expr::Binary(op, lhs, rhs) => {
+                        Expr::Binary(op, self.qualify_operand(lhs), self.qualify_operand(rhs))
+                    }
+                    Expr::Sizeof(label) => {
+                        Expr::Sizeof(Spanned::new(self.qualify_label(&label.node), label.span))
+                    }
+                };
+
+                Operand::Expr(Box::new(expr))
+            }
+
+            operand => operand,
+        };
+
+        Spanned::new(operand, arg.span)
+    }
+
+    fn qualify_data_spec(&self, spec: DataSpec) -> DataSpec {
+        DataSpec {
+            type_name: spec.type_name,
+            args: spec.args.into_iter().map(|arg| self.qualify_operand(arg)).collect(),
+        }
+    }
+
+    // expressions can refer to labels anywhere in the program, so statements are only turned
+    // into declarations once everything has been read and the layout is known
+    fn resolve(&mut self) {
+        let mut symbols = SymbolTable::new();
+        symbols.relocatable = self.relocatable;
+
+        for &(ref name, ref value, ref location) in &self.constants {
+            if let Err(e) = symbols.insert_constant(name, value, location) {
+                self.errors.push(e.with_location(location));
+            }
+        }
+
+        symbols.layout = true;
+        self.lay_out(&mut symbols);
+        symbols.layout = false;
+
+        self.range_errors.clear();
+        self.data = resolve_data(&self.data_statements[..],
+                                 &symbols,
+                                 &mut self.errors,
+                                 &mut self.range_errors);
+        self.rodata = resolve_data(&self.rodata_statements[..],
+                                   &symbols,
+                                   &mut self.errors,
+                                   &mut self.range_errors);
+        self.bss = resolve_data(&self.bss_statements[..],
+                                &symbols,
+                                &mut self.errors,
+                                &mut self.range_errors);
+
+        for decl in &self.bss {
+            if let DataDecl::Data(ref data, ref location) = *decl {
+                if !data.is_uninitialized() {
+                    let error = ParseError::new(ParseErrorKind::InitializedBss, location.span);
+                    self.errors.push(error.with_location(location));
                 }
+            }
+        }
+
+        self.code.clear();
+        for &(ref statement, ref location) in &self.code_statements {
+            match code_decl(statement, location.clone(), &symbols) {
+                Ok(decl) => self.code.push(decl),
+                Err(e) => self.errors.push(e.with_location(location)),
+            }
+
+            add_range_errors(&symbols, location, &mut self.range_errors);
+        }
+
+        self.label_refs = symbols.referenced_labels();
+    }
 
-                DataDecl::Data(data) => {
-                    println!("  {:?}", data);
+    // mirrors the encoder, so label addresses here match the final image
+    fn lay_out(&self, symbols: &mut SymbolTable) {
+        let mut pc = WORD_SIZE * 2;
+        pc = lay_out_data(&self.data_statements[..], Section::Data, pc, symbols);
+
+        pc = next_aligned_addr(pc, WORD_SIZE);
+        pc = lay_out_data(&self.rodata_statements[..], Section::ReadOnlyData, pc, symbols);
+
+        pc = next_aligned_addr(pc, WORD_SIZE);
+
+        for &(ref statement, _) in &self.code_statements {
+            match *statement {
+                Statement::Label(ref label) => {
+                    pc = next_aligned_addr(pc, WORD_SIZE);
+                    symbols.insert_label(&label.node, pc, Section::Code);
                 }
+
+                Statement::Instruction(ref opcode, ref args) => {
+                    if let Ok(instruction) = instruction_from_parts(opcode, args, symbols) {
+                        pc += instruction.size();
+                    }
+                }
+
+                _ => {}
             }
         }
+
+        pc = next_aligned_addr(pc, WORD_SIZE);
+        lay_out_data(&self.bss_statements[..], Section::Bss, pc, symbols);
+    }
+
+    pub fn debug(&self) {
+        print_data("data", &self.data[..]);
+        println!("");
+        print_data("rodata", &self.rodata[..]);
+        println!("");
+        self.print_code();
+        println!("");
+        print_data("bss", &self.bss[..]);
     }
 
     fn print_code(&self) {
         println!(".code");
         for code_decl in self.code.iter().cloned() {
             match code_decl {
-                CodeDecl::Label(label) => {
+                CodeDecl::Label(label, _) => {
                     println!("{}:", label);
                 }
 
-                CodeDecl::Instruction(instruction) => {
+                CodeDecl::Instruction(instruction, _, _) => {
                     println!("  {:?}", instruction);
                 }
             }
         }
     }
 }
+
+fn print_data(section: &str, decls: &[DataDecl]) {
+    println!(".{}", section);
+    for data_decl in decls.iter().cloned() {
+        match data_decl {
+            DataDecl::Label(label, _) => {
+                println!("{}:", label);
+            }
+
+            DataDecl::Data(data, _) => {
+                println!("  {:?}", data);
+            }
+        }
+    }
+}
+
+fn section_size(decls: &[DataDecl]) -> usize {
+    let mut pc = 0;
+
+    for data_decl in decls {
+        match *data_decl {
+            DataDecl::Label(_, _) => {
+                pc = next_aligned_addr(pc, WORD_SIZE);
+            }
+
+            DataDecl::Data(ref data, _) => {
+                pc = next_aligned_addr(pc, data.alignment());
+                pc += data.size();
+            }
+        }
+    }
+
+    pc
+}
+
+fn resolve_data(statements: &[(Statement, Location)],
+                symbols: &SymbolTable,
+                errors: &mut Vec<ParseError>,
+                range_errors: &mut Vec<EncodeError>)
+                -> Vec<DataDecl> {
+    let mut decls = Vec::new();
+
+    for &(ref statement, ref location) in statements {
+        match data_decl(statement, location.clone(), symbols) {
+            Ok(decl) => decls.push(decl),
+            Err(e) => errors.push(e.with_location(location)),
+        }
+
+        add_range_errors(symbols, location, range_errors);
+    }
+
+    decls
+}
+
+fn add_range_errors(symbols: &SymbolTable,
+                    location: &Location,
+                    range_errors: &mut Vec<EncodeError>) {
+    for (value, min, max, span) in symbols.take_out_of_range() {
+        let location = Location { span: span, ..location.clone() };
+        range_errors.push(EncodeError::IntOutOfRange(value, min, max, location));
+    }
+}
+
+// labels take the size of the data that follows them, up to the next label after that data
+fn lay_out_data(statements: &[(Statement, Location)],
+                section: Section,
+                start: Addr,
+                symbols: &mut SymbolTable)
+                -> Addr {
+    let mut pc = start;
+    let mut sized_labels = Vec::new();
+    let mut after_data = false;
+
+    for &(ref statement, ref location) in statements {
+        match *statement {
+            Statement::Label(ref label) => {
+                pc = next_aligned_addr(pc, WORD_SIZE);
+                symbols.insert_label(&label.node, pc, section);
+
+                if after_data {
+                    sized_labels.clear();
+                    after_data = false;
+                }
+
+                sized_labels.push(label.node.clone());
+            }
+
+            _ => {
+                if let Ok(DataDecl::Data(data, _)) = data_decl(statement,
+                                                                location.clone(),
+                                                                symbols) {
+                    pc = next_aligned_addr(pc, data.alignment());
+                    pc += data.size();
+
+                    for label in &sized_labels {
+                        symbols.add_size(label, data.size());
+                    }
+
+                    after_data = true;
+                }
+            }
+        }
+    }
+
+    pc
+}
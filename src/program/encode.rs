@@ -1,4 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::mem::replace;
 
 use ternary;
 use types::*;
@@ -6,7 +8,13 @@ use opcodes::Opcode;
 use registers::Register;
 use program::instructions::Instruction;
 use program::DecodedProgram;
+use program::decode::Section;
+use program::lines::LineTable;
+use program::listing::{Listing, ListingLine, Symbol, SymbolMap};
+use program::object::{ObjectFile, ObjectSymbol, Relocation, RelocationKind, Visibility};
+use program::location::Location;
 use program::parser::{CodeDecl, DataDecl};
+use program::symbols::display_label;
 use util::next_aligned_addr;
 use vm::PROGRAM_MAGIC_NUMBER;
 
@@ -16,8 +24,109 @@ static START_LABEL: &'static str = "__start";
 pub enum EncodeError {
     InsufficientMemory(usize, usize),
     InvalidLabel(String),
+    UndefinedLabel(String, Location),
+    DuplicateLabel(String, Location, Location),
     MissingRequiredLabel(String),
-    IntOutOfRange(isize, isize, isize),
+    UnexpandedInstruction(Location),
+    IntOutOfRange(String, isize, isize, Location),
+    BranchOutOfRange(String, RelAddr, Location),
+}
+
+impl EncodeError {
+    pub fn location(&self) -> Option<&Location> {
+        match *self {
+            EncodeError::UndefinedLabel(_, ref location) |
+            EncodeError::DuplicateLabel(_, ref location, _) |
+            EncodeError::UnexpandedInstruction(ref location) |
+            EncodeError::IntOutOfRange(_, _, _, ref location) |
+            EncodeError::BranchOutOfRange(_, _, ref location) => Some(location),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EncodeError::InsufficientMemory(required, available) => {
+                write!(f,
+                       "program needs {} trits of memory but only {} are available",
+                       required,
+                       available)
+            }
+            EncodeError::InvalidLabel(ref label) => write!(f, "undefined label `{}`", label),
+            EncodeError::UndefinedLabel(ref label, ref location) => {
+                write!(f,
+                       "{}: undefined label `{}`\n{}{}",
+                       location,
+                       label,
+                       location.snippet(),
+                       location.notes())
+            }
+            EncodeError::DuplicateLabel(ref label, ref location, ref previous) => {
+                write!(f,
+                       "{}: duplicate label `{}`\n{}{}\n{}: first defined here\n{}{}",
+                       location,
+                       label,
+                       location.snippet(),
+                       location.notes(),
+                       previous,
+                       previous.snippet(),
+                       previous.notes())
+            }
+            EncodeError::MissingRequiredLabel(ref label) => {
+                write!(f, "missing required label `{}`", label)
+            }
+            EncodeError::UnexpandedInstruction(ref location) => {
+                write!(f,
+                       "{}: pseudo-instruction has no encoding of its own\n{}{}",
+                       location,
+                       location.snippet(),
+                       location.notes())
+            }
+            EncodeError::IntOutOfRange(ref value, min, max, ref location) => {
+                write!(f,
+                       "{}: {} is out of range ({} to {})\n{}{}",
+                       location,
+                       value,
+                       min,
+                       max,
+                       location.snippet(),
+                       location.notes())
+            }
+            EncodeError::BranchOutOfRange(ref label, offset, ref location) => {
+                write!(f,
+                       "{}: branch to `{}` is {} trits away, out of range ({} to {})\n{}{}",
+                       location,
+                       display_label(label),
+                       offset,
+                       HALF_MIN,
+                       HALF_MAX,
+                       location.snippet(),
+                       location.notes())
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum EncodeWarning {
+    UnusedLabel(String, Location),
+}
+
+impl fmt::Display for EncodeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EncodeWarning::UnusedLabel(ref label, ref location) => {
+                write!(f,
+                       "{}: label `{}` is never used\n{}{}",
+                       location,
+                       label,
+                       location.snippet(),
+                       location.notes())
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -33,13 +142,40 @@ impl Patch {
             Patch::Absolute(_) => WORD_ISIZE,
         }
     }
+
+    fn label(&self) -> &String {
+        match *self {
+            Patch::Relative(_, ref label) |
+            Patch::Absolute(ref label) => label,
+        }
+    }
+
+    // a relative offset has to fit in a half, where addresses get a whole word
+    fn fits(&self, value: isize) -> bool {
+        match *self {
+            Patch::Relative(_, _) => HALF_MIN <= value && value <= HALF_MAX,
+            Patch::Absolute(_) => true,
+        }
+    }
 }
 
 pub struct EncodedProgram {
     memory: *mut Trit,
     memory_size: usize,
     labels: HashMap<String, Addr>,
-    patches: HashMap<*mut Trit, Patch>,
+    label_locations: HashMap<String, Location>,
+    label_sections: HashMap<String, Section>,
+    label_refs: HashSet<String>,
+    globals: HashMap<String, Location>,
+    patches: BTreeMap<*mut Trit, (Patch, Location)>,
+    errors: Vec<EncodeError>,
+    warnings: Vec<EncodeWarning>,
+    read_only: (Addr, Addr),
+    image_size: usize,
+    section: Section,
+    section_ranges: Vec<(Section, Addr, Addr)>,
+    listed: Vec<(Addr, usize, Section, Location)>,
+    relocatable: bool,
     pc: Addr,
 }
 
@@ -51,18 +187,181 @@ impl EncodedProgram {
             memory: memory,
             memory_size: memory_size,
             labels: HashMap::new(),
-            patches: HashMap::new(),
+            label_locations: HashMap::new(),
+            label_sections: HashMap::new(),
+            label_refs: HashSet::new(),
+            globals: HashMap::new(),
+            patches: BTreeMap::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            read_only: (0, 0),
+            image_size: 0,
+            section: Section::Code,
+            section_ranges: Vec::new(),
+            listed: Vec::new(),
+            relocatable: false,
             pc: 0,
         }
     }
 
-    pub fn insert_label(&mut self, label: &String) {
+    pub fn insert_label(&mut self, label: &String, location: &Location) {
         self.pc = next_aligned_addr(self.pc, WORD_SIZE);
+
+        if let Some(previous) = self.label_locations.get(label) {
+            let error =
+                EncodeError::DuplicateLabel(label.clone(), location.clone(), previous.clone());
+            self.errors.push(error);
+            return;
+        }
+
         let addr = self.pc;
         self.labels.insert(label.clone(), addr);
+        self.label_locations.insert(label.clone(), location.clone());
+        self.label_sections.insert(label.clone(), self.section);
+        self.listed.push((addr, 0, self.section, location.clone()));
+    }
+
+    pub fn read_only_range(&self) -> Option<(Addr, Addr)> {
+        let (start, end) = self.read_only;
+        if start < end {
+            Some((start, end))
+        } else {
+            None
+        }
+    }
+
+    // copies the encoded trits, so the listing outlives the memory it was read from
+    pub fn listing(&self) -> Listing {
+        let lines = self.listed
+            .iter()
+            .map(|&(addr, size, section, ref location)| {
+                let trits = (addr..addr + size)
+                    .map(|i| unsafe { *self.memory.offset(i as isize) })
+                    .collect();
+                ListingLine::new(addr, trits, section, location.clone())
+            })
+            .collect();
+
+        Listing::new(lines)
+    }
+
+    pub fn line_table(&self) -> LineTable {
+        self.code_lines(0)
+    }
+
+    // labels take up no space, so only instructions end up in the table
+    fn code_lines(&self, base: Addr) -> LineTable {
+        let mut table = LineTable::new();
+        for &(addr, size, section, ref location) in &self.listed {
+            if section == Section::Code && size > 0 {
+                table.add(addr - base, size, location);
+            }
+        }
+
+        table
+    }
+
+    pub fn symbol_map(&self) -> SymbolMap {
+        let symbols = self.labels
+            .iter()
+            .map(|(name, &addr)| Symbol::new(name.clone(), addr, self.label_sections[name]))
+            .collect();
+
+        SymbolMap::new(symbols)
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<EncodeWarning> {
+        replace(&mut self.warnings, Vec::new())
+    }
+
+    pub fn encode(&mut self, mut program: DecodedProgram) -> Result<usize, Vec<EncodeError>> {
+        self.errors.extend(replace(&mut program.range_errors, Vec::new()));
+
+        if let Err(e) = self.encode_program(program) {
+            self.errors.push(e);
+        }
+
+        if self.errors.is_empty() {
+            Ok(self.image_size)
+        } else {
+            Err(replace(&mut self.errors, Vec::new()))
+        }
+    }
+
+    // leaves label references unpatched and records them as relocations instead, so the
+    // sections can be moved by the linker
+    pub fn encode_object(&mut self,
+                         program: DecodedProgram)
+                         -> Result<ObjectFile, Vec<EncodeError>> {
+        self.relocatable = true;
+        try!(self.encode(program));
+
+        let mut object = ObjectFile::new();
+        object.data = self.section_trits(Section::Data);
+        object.rodata = self.section_trits(Section::ReadOnlyData);
+        object.code = self.section_trits(Section::Code);
+        object.bss_size = self.section_range(Section::Bss).1 - self.section_range(Section::Bss).0;
+        object.lines = Some(self.code_lines(self.section_range(Section::Code).0));
+
+        let mut labels: Vec<(&String, &Addr)> = self.labels.iter().collect();
+        labels.sort_by_key(|&(label, &addr)| (addr, label.clone()));
+
+        for (label, &addr) in labels {
+            let section = self.label_sections[label];
+            let visibility = if label == START_LABEL || self.globals.contains_key(label) {
+                Visibility::Global
+            } else {
+                Visibility::Local
+            };
+
+            object.symbols.push(ObjectSymbol {
+                name: label.clone(),
+                section: section,
+                offset: addr - self.section_range(section).0,
+                visibility: visibility,
+            });
+        }
+
+        for (&ptr, &(ref patch, _)) in self.patches.iter() {
+            let addr = unsafe { ptr.offset_from(self.memory) } as Addr;
+            let section = self.section_at(addr);
+            let start = self.section_range(section).0;
+            let kind = match *patch {
+                Patch::Absolute(_) => RelocationKind::Absolute,
+                Patch::Relative(pc, _) => RelocationKind::Relative(pc - start),
+            };
+
+            object.relocations.push(Relocation {
+                section: section,
+                offset: addr - start,
+                kind: kind,
+                symbol: patch.label().clone(),
+            });
+        }
+
+        Ok(object)
     }
 
-    pub fn encode(&mut self, program: DecodedProgram) -> EncodeResult<usize> {
+    fn section_range(&self, section: Section) -> (Addr, Addr) {
+        self.section_ranges
+            .iter()
+            .find(|&&(s, _, _)| s == section)
+            .map_or((0, 0), |&(_, start, end)| (start, end))
+    }
+
+    fn section_at(&self, addr: Addr) -> Section {
+        self.section_ranges
+            .iter()
+            .find(|&&(_, start, end)| start <= addr && addr < end)
+            .map_or(Section::Code, |&(section, _, _)| section)
+    }
+
+    fn section_trits(&self, section: Section) -> Vec<Trit> {
+        let (start, end) = self.section_range(section);
+        (start..end).map(|i| unsafe { *self.memory.offset(i as isize) }).collect()
+    }
+
+    fn encode_program(&mut self, program: DecodedProgram) -> EncodeResult<()> {
         let required_size = program.size();
         if required_size > self.memory_size {
             return Err(EncodeError::InsufficientMemory(required_size, self.memory_size));
@@ -75,11 +374,31 @@ impl EncodedProgram {
         let pc_start_offset = self.pc;
         self.pc += WORD_SIZE;
 
+        self.label_refs = program.label_refs.clone();
+        self.globals = program.globals.clone();
+
+        self.begin_section(Section::Data);
         let _ = try!(self.encode_data_section(&program.data[..]));
 
-        self.pc = next_aligned_addr(self.pc, WORD_SIZE);
+        self.begin_section(Section::ReadOnlyData);
+        let _ = try!(self.encode_data_section(&program.rodata[..]));
 
+        self.begin_section(Section::Code);
         let _ = try!(self.encode_code_section(&program.code[..]));
+        self.image_size = self.pc;
+
+        self.begin_section(Section::Bss);
+        let _ = try!(self.encode_data_section(&program.bss[..]));
+        self.end_section();
+        self.read_only = self.section_range(Section::ReadOnlyData);
+
+        self.check_unused_labels();
+        self.check_globals();
+        if self.relocatable {
+            return Ok(());
+        }
+
+        self.patch_addrs();
 
         let pc_start = try!(self.labels
             .get(START_LABEL)
@@ -91,20 +410,80 @@ impl EncodedProgram {
             ternary::from_int(local_memory, pc_start as isize, WORD_ISIZE);
         }
 
-        self.patch_addrs();
+        Ok(())
+    }
 
-        Ok(self.pc)
+    fn begin_section(&mut self, section: Section) {
+        self.end_section();
+        self.pc = next_aligned_addr(self.pc, WORD_SIZE);
+        self.section = section;
+        self.section_ranges.push((section, self.pc, self.pc));
+    }
+
+    fn end_section(&mut self) {
+        if let Some(range) = self.section_ranges.last_mut() {
+            range.2 = self.pc;
+        }
     }
 
     fn patch_addrs(&mut self) {
-        for (&ptr, ref patch) in self.patches.iter() {
-            let addr = match **patch {
-                Patch::Absolute(ref label) => self.label_addr(label).unwrap() as isize,
-                Patch::Relative(pc, ref label) => self.relative_addr(pc, label).unwrap(),
+        let mut errors = Vec::new();
+
+        for (&ptr, &(ref patch, ref location)) in self.patches.iter() {
+            let result = match *patch {
+                Patch::Absolute(ref label) => self.label_addr(label).map(|addr| addr as isize),
+                Patch::Relative(pc, ref label) => self.relative_addr(pc, label),
             };
 
-            unsafe { ternary::from_int(ptr, addr, patch.size()) };
+            match result {
+                Ok(offset) if !patch.fits(offset) => {
+                    let label = patch.label().clone();
+                    errors.push(EncodeError::BranchOutOfRange(label, offset, location.clone()))
+                }
+                Ok(addr) => unsafe { ternary::from_int(ptr, addr, patch.size()) },
+                Err(EncodeError::InvalidLabel(label)) => {
+                    errors.push(EncodeError::UndefinedLabel(label, location.clone()))
+                }
+                Err(e) => errors.push(e),
+            }
         }
+
+        self.errors.extend(errors);
+    }
+
+    fn check_globals(&mut self) {
+        let mut undefined: Vec<(&String, &Location)> = self.globals
+            .iter()
+            .filter(|&(label, _)| !self.labels.contains_key(label))
+            .collect();
+        undefined.sort_by_key(|&(_, location)| (location.span.line, location.span.column));
+
+        let errors: Vec<EncodeError> = undefined.into_iter()
+            .map(|(label, location)| EncodeError::UndefinedLabel(label.clone(), location.clone()))
+            .collect();
+        self.errors.extend(errors);
+    }
+
+    fn check_unused_labels(&mut self) {
+        let mut used: HashSet<&String> = self.patches
+            .values()
+            .map(|&(ref patch, _)| patch.label())
+            .collect();
+        used.extend(self.label_refs.iter());
+        used.extend(self.globals.keys());
+
+        let mut unused: Vec<(&String, &Location)> = self.label_locations
+            .iter()
+            .filter(|&(label, _)| label != START_LABEL && !used.contains(label))
+            .collect();
+        unused.sort_by_key(|&(_, location)| {
+            (location.path.clone(), location.span.line, location.span.column)
+        });
+
+        let warnings: Vec<EncodeWarning> = unused.into_iter()
+            .map(|(label, location)| EncodeWarning::UnusedLabel(label.clone(), location.clone()))
+            .collect();
+        self.warnings.extend(warnings);
     }
 
     pub fn encode_data_section(&mut self, all_data: &[DataDecl]) -> EncodeResult<usize> {
@@ -112,11 +491,11 @@ impl EncodedProgram {
 
         for ref data_decl in all_data {
             match **data_decl {
-                DataDecl::Label(ref label) => {
-                    self.insert_label(label);
+                DataDecl::Label(ref label, ref location) => {
+                    self.insert_label(label, location);
                 }
 
-                DataDecl::Data(ref data) => {
+                DataDecl::Data(ref data, ref location) => {
                     self.pc = next_aligned_addr(self.pc, data.alignment());
 
                     let size = unsafe {
@@ -124,6 +503,8 @@ impl EncodedProgram {
                         data.write(local_memory)
                     };
 
+                    self.listed.push((self.pc, size, self.section, location.clone()));
+
                     total_size += size;
                     self.pc += size;
                 }
@@ -138,19 +519,33 @@ impl EncodedProgram {
 
         for ref code_decl in all_code {
             match **code_decl {
-                CodeDecl::Label(ref label) => {
-                    self.insert_label(label);
+                CodeDecl::Label(ref label, ref location) => {
+                    self.insert_label(label, location);
                 }
 
-                CodeDecl::Instruction(ref instruction) => {
+                CodeDecl::Instruction(ref instruction, ref location, label_span) => {
                     assert_eq!(self.pc % WORD_SIZE, 0);
 
-                    unsafe {
+                    // errors about the label point at it rather than the whole instruction
+                    let label_location = match label_span {
+                        Some(span) => Location { span: span, ..location.clone() },
+                        None => location.clone(),
+                    };
+
+                    let result = unsafe {
                         let local_memory = self.memory.offset(self.pc as isize);
-                        try!(self.encode_instruction(local_memory, instruction));
+                        self.encode_instruction(local_memory,
+                                                instruction,
+                                                location,
+                                                &label_location)
+                    };
+
+                    if let Err(e) = result {
+                        self.errors.push(e);
                     }
 
                     let size = instruction.size();
+                    self.listed.push((self.pc, size, self.section, location.clone()));
                     total_size += size;
                     self.pc += size;
                 }
@@ -162,8 +557,14 @@ impl EncodedProgram {
 
     unsafe fn encode_instruction(&mut self,
                                  memory: *mut Trit,
-                                 instruction: &Instruction)
+                                 instruction: &Instruction,
+                                 location: &Location,
+                                 label_location: &Location)
                                  -> EncodeResult<()> {
+        if let Some(instructions) = instruction.expand() {
+            return self.encode_expansion(memory, &instructions[..], location, label_location);
+        }
+
         ternary::clear(memory, instruction.size() as isize);
 
         match *instruction {
@@ -188,7 +589,7 @@ impl EncodedProgram {
             Instruction::Mova(r, ref label) => {
                 try!(self.encode_opcode(memory, Opcode::Mova));
                 try!(self.encode_register(tryte_offset!(memory, 1), r));
-                try!(self.encode_label(tryte_offset!(memory, 4), label));
+                try!(self.encode_label(tryte_offset!(memory, 4), label, label_location));
             }
 
             Instruction::Lt(r1, r2, offset) => {
@@ -312,48 +713,66 @@ impl EncodedProgram {
 
             Instruction::Jmp(ref label) => {
                 try!(self.encode_opcode(memory, Opcode::Jmp));
-                try!(self.encode_label(tryte_offset!(memory, 4), label));
+                try!(self.encode_label(tryte_offset!(memory, 4), label, label_location));
             }
 
             Instruction::JT(r, ref label) => {
                 try!(self.encode_opcode(memory, Opcode::JT));
                 try!(self.encode_register(tryte_offset!(memory, 1), r));
-                try!(self.encode_relative_label(tryte_offset!(memory, 2), instruction, label));
+                try!(self.encode_relative_label(tryte_offset!(memory, 2),
+                                                instruction,
+                                                label,
+                                                label_location));
             }
 
             Instruction::J0(r, ref label) => {
                 try!(self.encode_opcode(memory, Opcode::J0));
                 try!(self.encode_register(tryte_offset!(memory, 1), r));
-                try!(self.encode_relative_label(tryte_offset!(memory, 2), instruction, label));
+                try!(self.encode_relative_label(tryte_offset!(memory, 2),
+                                                instruction,
+                                                label,
+                                                label_location));
             }
 
             Instruction::J1(r, ref label) => {
                 try!(self.encode_opcode(memory, Opcode::J1));
                 try!(self.encode_register(tryte_offset!(memory, 1), r));
-                try!(self.encode_relative_label(tryte_offset!(memory, 2), instruction, label));
+                try!(self.encode_relative_label(tryte_offset!(memory, 2),
+                                                instruction,
+                                                label,
+                                                label_location));
             }
 
             Instruction::JT0(r, ref label) => {
                 try!(self.encode_opcode(memory, Opcode::JT0));
                 try!(self.encode_register(tryte_offset!(memory, 1), r));
-                try!(self.encode_relative_label(tryte_offset!(memory, 2), instruction, label));
+                try!(self.encode_relative_label(tryte_offset!(memory, 2),
+                                                instruction,
+                                                label,
+                                                label_location));
             }
 
             Instruction::JT1(r, ref label) => {
                 try!(self.encode_opcode(memory, Opcode::JT1));
                 try!(self.encode_register(tryte_offset!(memory, 1), r));
-                try!(self.encode_relative_label(tryte_offset!(memory, 2), instruction, label));
+                try!(self.encode_relative_label(tryte_offset!(memory, 2),
+                                                instruction,
+                                                label,
+                                                label_location));
             }
 
             Instruction::J01(r, ref label) => {
                 try!(self.encode_opcode(memory, Opcode::J01));
                 try!(self.encode_register(tryte_offset!(memory, 1), r));
-                try!(self.encode_relative_label(tryte_offset!(memory, 2), instruction, label));
+                try!(self.encode_relative_label(tryte_offset!(memory, 2),
+                                                instruction,
+                                                label,
+                                                label_location));
             }
 
             Instruction::Call(ref label) => {
                 try!(self.encode_opcode(memory, Opcode::Call));
-                try!(self.encode_label(tryte_offset!(memory, 4), label));
+                try!(self.encode_label(tryte_offset!(memory, 4), label, label_location));
             }
 
             Instruction::Ret => {
@@ -371,18 +790,55 @@ impl EncodedProgram {
             Instruction::Halt => {
                 try!(self.encode_opcode(memory, Opcode::Halt));
             }
+
+            // expand() covers these, so this only happens if the two fall out of step
+            Instruction::Sub(_, _, _) |
+            Instruction::Beq(_, _, _) |
+            Instruction::Blt(_, _, _) |
+            Instruction::Bgt(_, _, _) => {
+                return Err(EncodeError::UnexpandedInstruction(location.clone()));
+            }
         }
 
         Ok(())
     }
 
+    // relative branches are measured from the end of the expanded instruction they belong to,
+    // so pc has to track each part as it is written
+    unsafe fn encode_expansion(&mut self,
+                               memory: *mut Trit,
+                               instructions: &[Instruction],
+                               location: &Location,
+                               label_location: &Location)
+                               -> EncodeResult<()> {
+        let start_pc = self.pc;
+        let mut offset = 0;
+        let mut result = Ok(());
+
+        for instruction in instructions {
+            self.pc = start_pc + offset;
+            result = self.encode_instruction(memory.offset(offset as isize),
+                                             instruction,
+                                             location,
+                                             label_location);
+            if result.is_err() {
+                break;
+            }
+
+            offset += instruction.size();
+        }
+
+        self.pc = start_pc;
+        result
+    }
+
     unsafe fn encode_opcode(&self, memory: *mut Trit, opcode: Opcode) -> EncodeResult<()> {
-        ternary::from_int(memory, opcode as isize, WORD_ISIZE);
+        ternary::from_int(memory, opcode as isize, TRYTE_ISIZE);
         Ok(())
     }
 
     unsafe fn encode_register(&self, memory: *mut Trit, register: Register) -> EncodeResult<()> {
-        ternary::from_int(memory, register as isize, WORD_ISIZE);
+        ternary::from_int(memory, register as isize, TRYTE_ISIZE);
         Ok(())
     }
 
@@ -401,18 +857,23 @@ impl EncodedProgram {
         Ok(())
     }
 
-    unsafe fn encode_label(&mut self, memory: *mut Trit, label: &String) -> EncodeResult<()> {
-        self.patches.insert(memory, Patch::Absolute(label.clone()));
+    unsafe fn encode_label(&mut self,
+                           memory: *mut Trit,
+                           label: &String,
+                           location: &Location)
+                           -> EncodeResult<()> {
+        self.patches.insert(memory, (Patch::Absolute(label.clone()), location.clone()));
         Ok(())
     }
 
     unsafe fn encode_relative_label(&mut self,
                                     memory: *mut Trit,
                                     instruction: &Instruction,
-                                    label: &String)
+                                    label: &String,
+                                    location: &Location)
                                     -> EncodeResult<()> {
         let pc = self.pc + instruction.size();
-        self.patches.insert(memory, Patch::Relative(pc, label.clone()));
+        self.patches.insert(memory, (Patch::Relative(pc, label.clone()), location.clone()));
         Ok(())
     }
 
@@ -0,0 +1,392 @@
+use std::str::FromStr;
+
+use registers::Register;
+use program::lexer::{tokenize, Token, TokenKind};
+use program::parser::parse_line;
+
+const INDENT: usize = 4;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum LineKind {
+    Blank,
+    Comment,
+    Label,
+    Statement(String, String),
+    Directive(String),
+    Raw(String),
+}
+
+#[derive(Clone, Debug)]
+struct Line {
+    labels: Vec<String>,
+    kind: LineKind,
+    comment: Option<String>,
+}
+
+impl Line {
+    fn new(labels: Vec<String>, kind: LineKind, comment: Option<String>) -> Line {
+        Line {
+            labels: labels,
+            kind: kind,
+            comment: comment,
+        }
+    }
+
+    fn is_statement(&self) -> bool {
+        match self.kind {
+            LineKind::Statement(_, _) => true,
+            _ => false,
+        }
+    }
+
+    // a label that isn't numeric or local starts a new function or data object
+    fn starts_block(&self) -> bool {
+        self.labels
+            .first()
+            .map_or(false, |label| !label.starts_with('.') && !label.starts_with(char::is_numeric))
+    }
+
+    // the column a comment on the line before should line up with
+    fn indent(&self) -> Option<usize> {
+        match self.kind {
+            LineKind::Blank | LineKind::Comment => None,
+            LineKind::Statement(_, _) => Some(INDENT),
+            LineKind::Raw(_) if self.labels.is_empty() => Some(INDENT),
+            _ => Some(0),
+        }
+    }
+}
+
+// canonical layout for assembly source: labels and directives in the first column,
+// instructions and data indented under them with their operands and trailing comments lined up,
+// and a blank line before each function. lines that don't parse are kept as they are
+pub fn format_source(source: &str) -> String {
+    let mut lines = Vec::new();
+    let mut in_macro = false;
+
+    for raw_line in source.lines() {
+        let line = parse_source_line(raw_line, in_macro);
+        if let LineKind::Directive(ref text) = line.kind {
+            if text.starts_with(".macro") {
+                in_macro = true;
+            } else if text.starts_with(".endm") {
+                in_macro = false;
+            }
+        }
+
+        lines.extend(split_labels(line));
+    }
+
+    let lines = arrange_blank_lines(lines);
+    render_lines(&lines)
+}
+
+fn parse_source_line(raw_line: &str, in_macro: bool) -> Line {
+    let (code, comment) = split_comment(raw_line);
+    let code = code.trim();
+
+    if code.is_empty() {
+        return match comment {
+            Some(comment) => Line::new(Vec::new(), LineKind::Comment, Some(comment)),
+            None => Line::new(Vec::new(), LineKind::Blank, None),
+        };
+    }
+
+    // macro bodies can refer to parameters, which only parse once the macro is expanded
+    let raw = || {
+        let text = if in_macro {
+            raw_line.trim().to_string()
+        } else {
+            raw_line.trim_end().to_string()
+        };
+
+        Line::new(Vec::new(), LineKind::Raw(text), None)
+    };
+
+    let tokens = match tokenize(code, 1) {
+        Ok(tokens) => tokens,
+        Err(_) => return raw(),
+    };
+
+    let chars: Vec<char> = code.chars().collect();
+    let mut labels = Vec::new();
+    let mut i = 0;
+
+    while i + 1 < tokens.len() && tokens[i + 1].kind == TokenKind::Colon {
+        match tokens[i].kind {
+            TokenKind::Ident(_) |
+            TokenKind::Decimal(_) |
+            TokenKind::Directive(_) => labels.push(token_text(&chars, &tokens[i])),
+            _ => break,
+        }
+
+        i += 2;
+    }
+
+    let rest = &tokens[i..];
+    let kind = match rest.first().map(|token| &token.kind) {
+        None => LineKind::Label,
+
+        Some(&TokenKind::Directive(_)) => {
+            let start = rest[0].span.column - 1;
+            let text: String = chars[start..].iter().cloned().collect();
+            let mut words = text.splitn(2, char::is_whitespace);
+            let name = words.next().unwrap_or("");
+            match words.next().map(str::trim) {
+                Some(args) if !args.is_empty() => LineKind::Directive(format!("{} {}", name, args)),
+                _ => LineKind::Directive(name.to_string()),
+            }
+        }
+
+        Some(&TokenKind::Percent) if rest.len() > 1 => {
+            let mnemonic = format!("%{}", token_text(&chars, &rest[1]));
+            LineKind::Statement(mnemonic, render_operands(&chars, &rest[2..]))
+        }
+
+        Some(&TokenKind::Ident(_)) => {
+            LineKind::Statement(token_text(&chars, &rest[0]), render_operands(&chars, &rest[1..]))
+        }
+
+        _ => return raw(),
+    };
+
+    let line = Line::new(labels, kind, comment);
+    if parse_line(&render_code(&line, 0), 1).is_err() {
+        return raw();
+    }
+
+    line
+}
+
+// several labels on one line each get their own, and so does a label too long to sit in the
+// indentation in front of an instruction
+fn split_labels(line: Line) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut labels = line.labels;
+    let last = labels.pop();
+
+    for label in labels {
+        lines.push(Line::new(vec![label], LineKind::Label, None));
+    }
+
+    match last {
+        Some(label) => {
+            if line.kind != LineKind::Label && label.len() + 2 > INDENT {
+                lines.push(Line::new(vec![label], LineKind::Label, None));
+                lines.push(Line::new(Vec::new(), line.kind, line.comment));
+            } else {
+                lines.push(Line::new(vec![label], line.kind, line.comment));
+            }
+        }
+
+        None => lines.push(Line::new(Vec::new(), line.kind, line.comment)),
+    }
+
+    lines
+}
+
+// runs of blank lines become one, and a function gets one before it and any comments leading
+// into it, unless it directly follows a directive
+fn arrange_blank_lines(lines: Vec<Line>) -> Vec<Line> {
+    let mut result: Vec<Line> = Vec::new();
+
+    for line in lines {
+        if line.kind == LineKind::Blank {
+            let after_blank = result.last().map_or(true, |last| last.kind == LineKind::Blank);
+            if !after_blank {
+                result.push(line);
+            }
+
+            continue;
+        }
+
+        if line.starts_block() {
+            // comments and directives straight above the label stay with it, so the blank goes
+            // ahead of them
+            let attached = result.iter()
+                .rev()
+                .take_while(|l| match l.kind {
+                    LineKind::Comment | LineKind::Directive(_) => true,
+                    _ => false,
+                })
+                .count();
+            let start = result.len() - attached;
+            let needs_blank = start > 0 && result[start - 1].kind != LineKind::Blank;
+
+            if needs_blank {
+                result.insert(start, Line::new(Vec::new(), LineKind::Blank, None));
+            }
+        }
+
+        result.push(line);
+    }
+
+    while result.last().map_or(false, |last| last.kind == LineKind::Blank) {
+        result.pop();
+    }
+
+    result
+}
+
+fn render_lines(lines: &[Line]) -> String {
+    let mut mnemonic_widths = vec![0; lines.len()];
+    let mut i = 0;
+    while i < lines.len() {
+        let end = (i..lines.len()).find(|&j| !lines[j].is_statement()).unwrap_or(lines.len());
+        let width = lines[i..end]
+            .iter()
+            .map(|line| match line.kind {
+                LineKind::Statement(ref mnemonic, ref operands) if !operands.is_empty() => {
+                    mnemonic.chars().count()
+                }
+                _ => 0,
+            })
+            .max()
+            .unwrap_or(0);
+
+        for j in i..end {
+            mnemonic_widths[j] = width;
+        }
+
+        i = end + 1;
+    }
+
+    let code: Vec<String> = lines.iter()
+        .zip(mnemonic_widths)
+        .map(|(line, width)| render_code(line, width))
+        .collect();
+
+    // trailing comments on consecutive lines share a column
+    let mut comment_columns = vec![0; lines.len()];
+    let mut i = 0;
+    while i < lines.len() {
+        let has_trailing = |j: usize| lines[j].comment.is_some() && !code[j].is_empty();
+        let end = (i..lines.len()).find(|&j| !has_trailing(j)).unwrap_or(lines.len());
+        let column = code[i..end].iter().map(|s| s.chars().count() + 1).max().unwrap_or(0);
+
+        for j in i..end {
+            comment_columns[j] = column;
+        }
+
+        i = end + 1;
+    }
+
+    let mut output = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        let text = match line.comment {
+            Some(ref comment) if code[i].is_empty() => {
+                let indent = lines[i + 1..].iter().filter_map(Line::indent).next().unwrap_or(0);
+                format!("{}{}", " ".repeat(indent), comment)
+            }
+            Some(ref comment) => {
+                format!("{:<width$}{}", code[i], comment, width = comment_columns[i])
+            }
+            None => code[i].clone(),
+        };
+
+        output.push_str(&text);
+        output.push('\n');
+    }
+
+    output
+}
+
+fn render_code(line: &Line, mnemonic_width: usize) -> String {
+    let label = line.labels.first().map(|label| format!("{}:", label));
+
+    let code = match line.kind {
+        LineKind::Blank | LineKind::Comment | LineKind::Label => String::new(),
+        LineKind::Directive(ref text) | LineKind::Raw(ref text) => text.clone(),
+        LineKind::Statement(ref mnemonic, ref operands) if operands.is_empty() => mnemonic.clone(),
+        LineKind::Statement(ref mnemonic, ref operands) => {
+            format!("{:<width$} {}", mnemonic, operands, width = mnemonic_width)
+        }
+    };
+
+    match (label, &line.kind) {
+        (Some(label), &LineKind::Label) => label,
+        (Some(label), _) => format!("{:<width$}{}", label, code, width = INDENT),
+        (None, &LineKind::Statement(_, _)) => format!("{}{}", " ".repeat(INDENT), code),
+        (None, &LineKind::Raw(_)) if !code.starts_with(char::is_whitespace) => {
+            format!("{}{}", " ".repeat(INDENT), code)
+        }
+        (None, _) => code,
+    }
+}
+
+fn render_operands(chars: &[char], tokens: &[Token]) -> String {
+    let mut s = String::new();
+
+    for (i, token) in tokens.iter().enumerate() {
+        let previous = if i > 0 { Some(&tokens[i - 1].kind) } else { None };
+        let space = match (previous, &token.kind) {
+            (None, _) => false,
+            (_, &TokenKind::Comma) | (_, &TokenKind::RParen) => false,
+            (Some(&TokenKind::LParen), _) | (Some(&TokenKind::Percent), _) => false,
+            (Some(&TokenKind::Minus), _) => !is_unary_minus(tokens, i - 1),
+            _ => true,
+        };
+
+        if space {
+            s.push(' ');
+        }
+
+        s.push_str(&token_text(chars, token));
+    }
+
+    s
+}
+
+// a minus is negation unless it follows something that can be subtracted from, and the type in
+// a nested data spec like `%word -1` can't be
+fn is_unary_minus(tokens: &[Token], i: usize) -> bool {
+    match i.checked_sub(1).map(|j| &tokens[j].kind) {
+        None => true,
+        Some(kind) if is_operator(kind) => true,
+        Some(&TokenKind::Ident(_)) => i >= 2 && tokens[i - 2].kind == TokenKind::Percent,
+        _ => false,
+    }
+}
+
+fn is_operator(kind: &TokenKind) -> bool {
+    match *kind {
+        TokenKind::Comma |
+        TokenKind::LParen |
+        TokenKind::Percent |
+        TokenKind::Minus |
+        TokenKind::Plus |
+        TokenKind::Star => true,
+        _ => false,
+    }
+}
+
+fn token_text(chars: &[char], token: &Token) -> String {
+    if let TokenKind::Register(ref name) = token.kind {
+        if let Ok(register) = Register::from_str(&format!("${}", name)) {
+            return register.name().to_string();
+        }
+    }
+
+    let start = token.span.column - 1;
+    chars[start..start + token.span.len].iter().cloned().collect()
+}
+
+// the code and the comment after it, which starts at the first `;` outside a literal
+fn split_comment(line: &str) -> (&str, Option<String>) {
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == ';' => return (&line[..i], Some(line[i..].trim_end().to_string())),
+            None => {}
+        }
+    }
+
+    (line, None)
+}
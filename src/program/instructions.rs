@@ -38,10 +38,127 @@ pub enum Instruction {
     Syscall,
     Break,
     Halt,
+    Sub(Register, Register, Register),
+    Beq(Register, Register, String),
+    Blt(Register, Register, String),
+    Bgt(Register, Register, String),
 }
 
+// there's no assembler temporary, so conditional branches compare into $hi
+pub const BRANCH_SCRATCH: Register = Register::HI;
+
 impl Instruction {
+    pub fn expand(&self) -> Option<Vec<Instruction>> {
+        match *self {
+            Instruction::Sub(r_dest, r_lhs, r_rhs) => Some(expand_sub(r_dest, r_lhs, r_rhs)),
+
+            Instruction::Beq(r_lhs, r_rhs, ref label) => {
+                Some(vec![Instruction::Cmp(BRANCH_SCRATCH, r_lhs, r_rhs),
+                          Instruction::J0(BRANCH_SCRATCH, label.clone())])
+            }
+
+            Instruction::Blt(r_lhs, r_rhs, ref label) => {
+                Some(vec![Instruction::Cmp(BRANCH_SCRATCH, r_lhs, r_rhs),
+                          Instruction::JT(BRANCH_SCRATCH, label.clone())])
+            }
+
+            Instruction::Bgt(r_lhs, r_rhs, ref label) => {
+                Some(vec![Instruction::Cmp(BRANCH_SCRATCH, r_lhs, r_rhs),
+                          Instruction::J1(BRANCH_SCRATCH, label.clone())])
+            }
+
+            _ => None,
+        }
+    }
+
+    pub fn dest_register(&self) -> Option<Register> {
+        match *self {
+            Instruction::Mov(r, _) |
+            Instruction::Movi(r, _) |
+            Instruction::Movw(r, _) |
+            Instruction::Mova(r, _) |
+            Instruction::Lt(r, _, _) |
+            Instruction::Lh(r, _, _) |
+            Instruction::Lw(r, _, _) |
+            Instruction::Add(r, _, _) |
+            Instruction::Addi(r, _) |
+            Instruction::Not(r, _) |
+            Instruction::And(r, _, _) |
+            Instruction::Andi(r, _) |
+            Instruction::Or(r, _, _) |
+            Instruction::Ori(r, _) |
+            Instruction::Shf(r, _, _) |
+            Instruction::Shfi(r, _) |
+            Instruction::Cmp(r, _, _) |
+            Instruction::Sub(r, _, _) => Some(r),
+            _ => None,
+        }
+    }
+
+    pub fn source_registers(&self) -> Vec<Register> {
+        match *self {
+            Instruction::Mov(_, r) |
+            Instruction::Not(_, r) |
+            Instruction::Lt(_, r, _) |
+            Instruction::Lh(_, r, _) |
+            Instruction::Lw(_, r, _) |
+            Instruction::Addi(r, _) |
+            Instruction::Muli(r, _) |
+            Instruction::Andi(r, _) |
+            Instruction::Ori(r, _) |
+            Instruction::Shfi(r, _) |
+            Instruction::JT(r, _) |
+            Instruction::J0(r, _) |
+            Instruction::J1(r, _) |
+            Instruction::JT0(r, _) |
+            Instruction::JT1(r, _) |
+            Instruction::J01(r, _) => vec![r],
+            Instruction::St(r1, r2, _) |
+            Instruction::Sh(r1, r2, _) |
+            Instruction::Sw(r1, r2, _) |
+            Instruction::Add(_, r1, r2) |
+            Instruction::Mul(r1, r2) |
+            Instruction::And(_, r1, r2) |
+            Instruction::Or(_, r1, r2) |
+            Instruction::Shf(_, r1, r2) |
+            Instruction::Cmp(_, r1, r2) |
+            Instruction::Sub(_, r1, r2) |
+            Instruction::Beq(r1, r2, _) |
+            Instruction::Blt(r1, r2, _) |
+            Instruction::Bgt(r1, r2, _) => vec![r1, r2],
+            Instruction::Ret => vec![Register::RA],
+            Instruction::Syscall => vec![Register::T0, Register::A0],
+            _ => Vec::new(),
+        }
+    }
+
+    // registers written as a side effect rather than named as the destination
+    pub fn implicit_registers(&self) -> Vec<Register> {
+        match *self {
+            Instruction::Add(_, _, _) |
+            Instruction::Addi(_, _) |
+            Instruction::Sub(_, _, _) => vec![Register::HI],
+            Instruction::Mul(_, _) |
+            Instruction::Muli(_, _) |
+            Instruction::Shf(_, _, _) |
+            Instruction::Shfi(_, _) => vec![Register::LO, Register::HI],
+            Instruction::Beq(_, _, _) |
+            Instruction::Blt(_, _, _) |
+            Instruction::Bgt(_, _, _) => vec![BRANCH_SCRATCH],
+            Instruction::Call(_) => vec![Register::RA],
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn writes(&self, r: Register) -> bool {
+        self.dest_register() == Some(r) || self.implicit_registers().contains(&r)
+    }
+
     pub fn size(&self) -> usize {
+        if let Some(instructions) = self.expand() {
+            return instructions.iter().map(|inst| inst.size()).sum();
+        }
+
         match *self {
             Instruction::Movw(_, _) |
             Instruction::Mova(_, _) |
@@ -89,6 +206,31 @@ impl Into<Opcode> for Instruction {
             Instruction::Syscall => Opcode::Syscall,
             Instruction::Break => Opcode::Break,
             Instruction::Halt => Opcode::Halt,
+            // pseudo-instructions give the opcode their expansion starts with
+            Instruction::Sub(r_dest, r_lhs, r_rhs) => {
+                if r_dest == r_lhs && r_lhs == r_rhs {
+                    Opcode::Mov
+                } else {
+                    Opcode::Not
+                }
+            }
+            Instruction::Beq(_, _, _) |
+            Instruction::Blt(_, _, _) |
+            Instruction::Bgt(_, _, _) => Opcode::Cmp,
         }
     }
 }
+
+// negation is trit-wise, so a - b is a + not(b) as long as a survives until the add. `into`
+// picks the first opcode the same way, so keep the two in step
+fn expand_sub(r_dest: Register, r_lhs: Register, r_rhs: Register) -> Vec<Instruction> {
+    if r_dest != r_lhs {
+        vec![Instruction::Not(r_dest, r_rhs), Instruction::Add(r_dest, r_lhs, r_dest)]
+    } else if r_lhs != r_rhs {
+        vec![Instruction::Not(r_dest, r_dest),
+             Instruction::Add(r_dest, r_dest, r_rhs),
+             Instruction::Not(r_dest, r_dest)]
+    } else {
+        vec![Instruction::Mov(r_dest, Register::ZERO)]
+    }
+}
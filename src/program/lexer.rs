@@ -0,0 +1,397 @@
+use std::char;
+use std::fmt;
+
+use program::parser::{ParseError, ParseErrorKind, ParseResult};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize, len: usize) -> Span {
+        Span {
+            line: line,
+            column: column,
+            len: len,
+        }
+    }
+
+    pub fn to(self, other: Span) -> Span {
+        let end = other.column + other.len;
+        Span::new(self.line, self.column, end.saturating_sub(self.column))
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TokenKind {
+    Ident(String),
+    Directive(String),
+    Register(String),
+    Decimal(String),
+    Ternary(String),
+    Nonary(String),
+    Heptavigesimal(String),
+    Char(char),
+    String(String),
+    Comma,
+    Colon,
+    Percent,
+    Minus,
+    Plus,
+    Star,
+    LParen,
+    RParen,
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TokenKind::Ident(ref s) => write!(f, "`{}`", s),
+            TokenKind::Directive(ref s) => write!(f, "`.{}`", s),
+            TokenKind::Register(ref s) => write!(f, "`${}`", s),
+            TokenKind::Decimal(ref s) => write!(f, "`{}`", s),
+            TokenKind::Ternary(ref s) => write!(f, "`0t{}`", s),
+            TokenKind::Nonary(ref s) => write!(f, "`0n{}`", s),
+            TokenKind::Heptavigesimal(ref s) => write!(f, "`0h{}`", s),
+            TokenKind::Char(c) => write!(f, "`{:?}`", c),
+            TokenKind::String(ref s) => write!(f, "{:?}", s),
+            TokenKind::Comma => f.write_str("`,`"),
+            TokenKind::Colon => f.write_str("`:`"),
+            TokenKind::Percent => f.write_str("`%`"),
+            TokenKind::Minus => f.write_str("`-`"),
+            TokenKind::Plus => f.write_str("`+`"),
+            TokenKind::Star => f.write_str("`*`"),
+            TokenKind::LParen => f.write_str("`(`"),
+            TokenKind::RParen => f.write_str("`)`"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+pub struct Lexer {
+    chars: Vec<char>,
+    line: usize,
+    pos: usize,
+}
+
+impl Lexer {
+    pub fn new(text: &str, line: usize) -> Lexer {
+        Lexer {
+            chars: text.chars().collect(),
+            line: line,
+            pos: 0,
+        }
+    }
+
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(self.line, start + 1, self.pos - start)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).cloned()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == ';' {
+                self.pos = self.chars.len();
+            } else if c.is_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, f: F) -> String {
+        let mut s = String::new();
+        while let Some(c) = self.peek() {
+            if !f(c) {
+                break;
+            }
+
+            s.push(c);
+            self.pos += 1;
+        }
+
+        s
+    }
+
+    pub fn peek_char(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.peek()
+    }
+
+    pub fn is_done(&mut self) -> bool {
+        self.skip_whitespace();
+        self.pos >= self.chars.len()
+    }
+
+    // everything up to the comment, for directives that take raw arguments
+    pub fn rest(&mut self) -> (String, Span) {
+        self.skip_whitespace();
+        let start = self.pos;
+
+        let mut in_string = false;
+        while let Some(c) = self.peek() {
+            if c == '"' {
+                in_string = !in_string;
+            } else if c == ';' && !in_string {
+                break;
+            }
+
+            self.pos += 1;
+        }
+
+        let s: String = self.chars[start..self.pos].iter().cloned().collect();
+        let trimmed = s.trim_end().to_string();
+        let span = Span::new(self.line, start + 1, trimmed.chars().count());
+        (trimmed, span)
+    }
+
+    pub fn next_token(&mut self) -> ParseResult<Option<Token>> {
+        self.skip_whitespace();
+
+        let start = self.pos;
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+
+        let kind = if is_ident_start(c) {
+            let mut name = self.take_while(is_ident_char);
+
+            // `func.local` names a local label from outside its scope
+            while self.peek() == Some('.') && self.peek_at(1).map_or(false, is_ident_start) {
+                self.pos += 1;
+                name.push('.');
+                name.push_str(&self.take_while(is_ident_char));
+            }
+
+            TokenKind::Ident(name)
+        } else if c == '.' {
+            self.pos += 1;
+            let name = self.take_while(is_ident_char);
+            if name.is_empty() {
+                return Err(ParseError::new(ParseErrorKind::UnexpectedCharacter('.'),
+                                           self.span_from(start)));
+            }
+
+            TokenKind::Directive(name)
+        } else if c == '$' {
+            self.pos += 1;
+            TokenKind::Register(self.take_while(is_ident_char))
+        } else if c == '0' && self.peek_at(1) == Some('t') {
+            self.pos += 2;
+            let trits = self.take_while(is_ident_char);
+            if trits.is_empty() || !trits.chars().all(is_trit_char) {
+                return Err(ParseError::new(ParseErrorKind::InvalidNumber(format!("0t{}", trits)),
+                                           self.span_from(start)));
+            }
+
+            TokenKind::Ternary(trits)
+        } else if c == '0' && (self.peek_at(1) == Some('n') || self.peek_at(1) == Some('h')) {
+            let prefix = self.peek_at(1).unwrap();
+            let radix = if prefix == 'n' { 9 } else { 27 };
+            self.pos += 2;
+            let digits = self.take_while(is_ident_char);
+            if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+                let literal = format!("0{}{}", prefix, digits);
+                return Err(ParseError::new(ParseErrorKind::InvalidNumber(literal),
+                                           self.span_from(start)));
+            }
+
+            if radix == 9 {
+                TokenKind::Nonary(digits)
+            } else {
+                TokenKind::Heptavigesimal(digits)
+            }
+        } else if c.is_digit(10) {
+            let digits = self.take_while(is_ident_char);
+            if is_numeric_label_ref(&digits) {
+                TokenKind::Ident(digits)
+            } else if !digits.chars().all(|c| c.is_digit(10)) {
+                return Err(ParseError::new(ParseErrorKind::InvalidNumber(digits),
+                                           self.span_from(start)));
+            } else {
+                TokenKind::Decimal(digits)
+            }
+        } else if c == '"' {
+            self.pos += 1;
+            TokenKind::String(try!(self.lex_string(start)))
+        } else if c == '\'' {
+            self.pos += 1;
+            TokenKind::Char(try!(self.lex_char(start)))
+        } else {
+            self.pos += 1;
+            match c {
+                ',' => TokenKind::Comma,
+                ':' => TokenKind::Colon,
+                '%' => TokenKind::Percent,
+                '-' => TokenKind::Minus,
+                '+' => TokenKind::Plus,
+                '*' => TokenKind::Star,
+                '(' => TokenKind::LParen,
+                ')' => TokenKind::RParen,
+                _ => {
+                    return Err(ParseError::new(ParseErrorKind::UnexpectedCharacter(c),
+                                               self.span_from(start)));
+                }
+            }
+        };
+
+        Ok(Some(Token {
+            kind: kind,
+            span: self.span_from(start),
+        }))
+    }
+
+    fn lex_string(&mut self, start: usize) -> ParseResult<String> {
+        let mut result = String::new();
+
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => {
+                    return Err(ParseError::new(ParseErrorKind::UnterminatedString,
+                                               self.span_from(start)));
+                }
+            };
+
+            self.pos += 1;
+
+            match c {
+                '"' => return Ok(result),
+                '\\' => {
+                    let escape_start = self.pos - 1;
+                    let unescaped = try!(self.unescape_char()
+                        .map_err(|kind| ParseError::new(kind, self.span_from(escape_start))));
+                    result.push(unescaped);
+                }
+                _ => result.push(c),
+            }
+        }
+    }
+
+    fn lex_char(&mut self, start: usize) -> ParseResult<char> {
+        let c = match self.peek() {
+            Some('\'') | None => None,
+            Some('\\') => {
+                self.pos += 1;
+                let escape_start = self.pos - 1;
+                Some(try!(self.unescape_char()
+                    .map_err(|kind| ParseError::new(kind, self.span_from(escape_start)))))
+            }
+            Some(c) => {
+                self.pos += 1;
+                Some(c)
+            }
+        };
+
+        match (c, self.peek()) {
+            (Some(c), Some('\'')) => {
+                self.pos += 1;
+                Ok(c)
+            }
+            _ => {
+                while let Some(c) = self.peek() {
+                    self.pos += 1;
+                    if c == '\'' {
+                        break;
+                    }
+                }
+
+                let literal: String = self.chars[start..self.pos].iter().cloned().collect();
+                Err(ParseError::new(ParseErrorKind::InvalidChar(literal), self.span_from(start)))
+            }
+        }
+    }
+
+    fn unescape_char(&mut self) -> Result<char, ParseErrorKind> {
+        let c = self.peek();
+        if c.is_some() {
+            self.pos += 1;
+        }
+
+        match c {
+            Some('u') => {
+                let mut seq = String::new();
+                while seq.len() < 4 {
+                    match self.peek() {
+                        Some(c) if c.is_digit(16) => {
+                            seq.push(c);
+                            self.pos += 1;
+                        }
+                        _ => break,
+                    }
+                }
+
+                if seq.len() < 4 {
+                    return Err(ParseErrorKind::InvalidEscapeSequence(format!("\\u{}", seq)));
+                }
+
+                let code = u32::from_str_radix(&seq, 16).unwrap();
+                char::from_u32(code)
+                    .ok_or_else(|| ParseErrorKind::InvalidEscapeSequence(format!("\\u{}", seq)))
+            }
+            Some('b') => Ok('\x08'),
+            Some('f') => Ok('\x0c'),
+            Some('n') => Ok('\n'),
+            Some('r') => Ok('\r'),
+            Some('t') => Ok('\t'),
+            Some('\\') => Ok('\\'),
+            Some('"') => Ok('"'),
+            Some('\'') => Ok('\''),
+            Some(c) => Err(ParseErrorKind::InvalidEscapeSequence(format!("\\{}", c))),
+            None => Err(ParseErrorKind::InvalidEscapeSequence("\\".to_string())),
+        }
+    }
+}
+
+pub fn is_ident_start(c: char) -> bool {
+    c == '_' || c.is_ascii_alphabetic()
+}
+
+pub fn is_ident_char(c: char) -> bool {
+    c == '_' || c.is_ascii_alphanumeric()
+}
+
+// `1f` and `1b` refer to the next and previous definitions of the numeric label `1:`
+pub fn is_numeric_label_ref(s: &str) -> bool {
+    let len = s.len();
+    len > 1 && (s.ends_with('f') || s.ends_with('b')) &&
+    s[..len - 1].chars().all(|c| c.is_digit(10))
+}
+
+fn is_trit_char(c: char) -> bool {
+    c == 'T' || c == '0' || c == '1'
+}
+
+pub fn tokenize(text: &str, line: usize) -> ParseResult<Vec<Token>> {
+    let mut lexer = Lexer::new(text, line);
+    let mut tokens = Vec::new();
+
+    while let Some(token) = try!(lexer.next_token()) {
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
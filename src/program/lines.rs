@@ -0,0 +1,200 @@
+use std::fmt;
+
+use types::*;
+use program::location::Location;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SourcePosition {
+    pub file: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+// `expansions` are the macro call sites the instruction came from, innermost first
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LineEntry {
+    pub addr: Addr,
+    pub size: usize,
+    pub position: SourcePosition,
+    pub expansions: Vec<SourcePosition>,
+}
+
+// maps encoded instructions back to where they were written, ordered by address
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LineTable {
+    pub files: Vec<String>,
+    pub entries: Vec<LineEntry>,
+}
+
+impl LineTable {
+    pub fn new() -> LineTable {
+        LineTable {
+            files: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn add(&mut self, addr: Addr, size: usize, location: &Location) {
+        let position = self.position(location);
+        let mut expansions = Vec::new();
+        let mut expansion = location.expansion.as_ref();
+        while let Some(e) = expansion {
+            expansions.push(self.position(&e.call_site));
+            expansion = e.call_site.expansion.as_ref();
+        }
+
+        self.insert(LineEntry {
+            addr: addr,
+            size: size,
+            position: position,
+            expansions: expansions,
+        });
+    }
+
+    // copies another table's entries in, moved along by `offset`
+    pub fn extend(&mut self, other: &LineTable, offset: Addr) {
+        for entry in &other.entries {
+            let remap = |table: &mut LineTable, position: &SourcePosition| {
+                SourcePosition {
+                    file: table.file_index(&other.files[position.file]),
+                    line: position.line,
+                    column: position.column,
+                }
+            };
+
+            let position = remap(self, &entry.position);
+            let expansions = entry.expansions.iter().map(|p| remap(self, p)).collect();
+            self.insert(LineEntry {
+                addr: entry.addr + offset,
+                size: entry.size,
+                position: position,
+                expansions: expansions,
+            });
+        }
+    }
+
+    // the entry for the instruction that covers `addr`, if there is one
+    pub fn lookup(&self, addr: Addr) -> Option<&LineEntry> {
+        let i = match self.entries.binary_search_by_key(&addr, |entry| entry.addr) {
+            Ok(i) => i,
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+
+        let entry = &self.entries[i];
+        if addr < entry.addr + entry.size {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    pub fn path(&self, position: &SourcePosition) -> &str {
+        self.files.get(position.file).map_or("<input>", |s| &s[..])
+    }
+
+    pub fn describe(&self, position: &SourcePosition) -> String {
+        format!("{}:{}:{}", self.path(position), position.line, position.column)
+    }
+
+    // accepts the `file` and `line` lines written by `write`, returning false for anything else
+    pub fn parse_line(&mut self, line: &str) -> bool {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+
+        match fields.first().cloned() {
+            Some("file") if fields.len() >= 3 => {
+                // the path is everything after the index, so it can contain spaces
+                let rest = line.trim_start()["file".len()..].trim_start();
+                let path = rest[fields[1].len()..].trim_start();
+                if fields[1].parse() != Ok(self.files.len()) {
+                    return false;
+                }
+
+                self.files.push(path.to_string());
+                true
+            }
+
+            Some("line") if fields.len() >= 6 => {
+                let numbers: Vec<usize> =
+                    fields[1..].iter().filter_map(|s| s.parse().ok()).collect();
+                if numbers.len() != fields.len() - 1 ||
+                   numbers[2..].chunks(3).any(|n| n.len() != 3) {
+                    return false;
+                }
+
+                let mut positions: Vec<SourcePosition> = numbers[2..]
+                    .chunks(3)
+                    .map(|n| {
+                        SourcePosition {
+                            file: n[0],
+                            line: n[1],
+                            column: n[2],
+                        }
+                    })
+                    .collect();
+
+                if positions.iter().any(|position| position.file >= self.files.len()) {
+                    return false;
+                }
+
+                let position = positions.remove(0);
+                self.insert(LineEntry {
+                    addr: numbers[0],
+                    size: numbers[1],
+                    position: position,
+                    expansions: positions,
+                });
+                true
+            }
+
+            _ => false,
+        }
+    }
+
+    pub fn write(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, path) in self.files.iter().enumerate() {
+            try!(writeln!(f, "file {} {}", i, path));
+        }
+
+        for entry in &self.entries {
+            try!(write!(f, "line {} {}", entry.addr, entry.size));
+            for position in Some(&entry.position).into_iter().chain(&entry.expansions) {
+                try!(write!(f, " {} {} {}", position.file, position.line, position.column));
+            }
+            try!(writeln!(f, ""));
+        }
+
+        Ok(())
+    }
+
+    fn position(&mut self, location: &Location) -> SourcePosition {
+        let path = location.path.as_ref().map_or("<input>", |s| &s[..]);
+        SourcePosition {
+            file: self.file_index(path),
+            line: location.span.line,
+            column: location.span.column,
+        }
+    }
+
+    fn file_index(&mut self, path: &str) -> usize {
+        match self.files.iter().position(|file| file == path) {
+            Some(i) => i,
+            None => {
+                self.files.push(path.to_string());
+                self.files.len() - 1
+            }
+        }
+    }
+
+    fn insert(&mut self, entry: LineEntry) {
+        let i = match self.entries.binary_search_by_key(&entry.addr, |e| e.addr) {
+            Ok(i) | Err(i) => i,
+        };
+
+        self.entries.insert(i, entry);
+    }
+}
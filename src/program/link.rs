@@ -0,0 +1,255 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use ternary;
+use types::*;
+use program::archive::Archive;
+use program::decode::Section;
+use program::lines::LineTable;
+use program::object::{Image, ObjectFile, RelocationKind, Visibility};
+use program::symbols::display_label;
+use util::next_aligned_addr;
+use vm::PROGRAM_MAGIC_NUMBER;
+
+static START_SYMBOL: &'static str = "__start";
+
+static SECTION_ORDER: [Section; 4] =
+    [Section::Data, Section::ReadOnlyData, Section::Code, Section::Bss];
+
+#[derive(Debug)]
+pub enum LinkError {
+    UndefinedSymbol(String, String),
+    DuplicateSymbol(String, String, String),
+    MissingStart,
+    BranchOutOfRange(String, RelAddr, String),
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LinkError::UndefinedSymbol(ref symbol, ref object) => {
+                write!(f, "{}: undefined symbol `{}`", object, symbol)
+            }
+            LinkError::DuplicateSymbol(ref symbol, ref object, ref previous) => {
+                write!(f,
+                       "{}: duplicate symbol `{}` (first defined in {})",
+                       object,
+                       symbol,
+                       previous)
+            }
+            LinkError::MissingStart => write!(f, "no object defines `{}`", START_SYMBOL),
+            LinkError::BranchOutOfRange(ref symbol, offset, ref object) => {
+                write!(f,
+                       "{}: branch to `{}` is {} trits away, out of range ({} to {})",
+                       object,
+                       display_label(symbol),
+                       offset,
+                       HALF_MIN,
+                       HALF_MAX)
+            }
+        }
+    }
+}
+
+pub struct Linker {
+    objects: Vec<(String, ObjectFile)>,
+    archives: Vec<(String, Archive)>,
+}
+
+impl Linker {
+    pub fn new() -> Linker {
+        Linker {
+            objects: Vec::new(),
+            archives: Vec::new(),
+        }
+    }
+
+    pub fn add_object(&mut self, name: String, object: ObjectFile) {
+        self.objects.push((name, object));
+    }
+
+    pub fn add_archive(&mut self, name: String, archive: Archive) {
+        self.archives.push((name, archive));
+    }
+
+    // archive members are only pulled in to define symbols that are still undefined, which can
+    // in turn make other members necessary
+    fn select_objects(&self) -> Vec<(String, &ObjectFile)> {
+        let mut objects: Vec<(String, &ObjectFile)> = self.objects
+            .iter()
+            .map(|&(ref name, ref object)| (name.clone(), object))
+            .collect();
+        let mut pulled = HashSet::new();
+
+        loop {
+            let defined: HashSet<&String> = objects.iter()
+                .flat_map(|&(_, object)| object.symbols.iter())
+                .filter(|symbol| symbol.visibility == Visibility::Global)
+                .map(|symbol| &symbol.name)
+                .collect();
+
+            let undefined: Vec<&String> = objects.iter()
+                .flat_map(|&(_, object)| {
+                    object.relocations
+                        .iter()
+                        .map(|relocation| &relocation.symbol)
+                        .filter(move |symbol| object.symbol(symbol).is_none())
+                })
+                .filter(|symbol| !defined.contains(symbol))
+                .collect();
+
+            let mut needed = Vec::new();
+            for symbol in undefined {
+                for (i, &(_, ref archive)) in self.archives.iter().enumerate() {
+                    if let Some(j) = archive.find_member(symbol) {
+                        if pulled.insert((i, j)) {
+                            needed.push((i, j));
+                        }
+                        break;
+                    }
+                }
+            }
+
+            if needed.is_empty() {
+                return objects;
+            }
+
+            for (i, j) in needed {
+                let (ref archive_name, ref archive) = self.archives[i];
+                let (ref member_name, ref member) = archive.members[j];
+                objects.push((format!("{}({})", archive_name, member_name), member));
+            }
+        }
+    }
+
+    // sections of the same kind are laid out back to back, in the order the objects were added,
+    // matching the layout the encoder gives a single program
+    pub fn link(&self) -> Result<Image, Vec<LinkError>> {
+        let objects = self.select_objects();
+
+        let mut errors = Vec::new();
+        let mut bases: Vec<HashMap<Section, Addr>> = vec![HashMap::new(); objects.len()];
+        let mut section_starts = HashMap::new();
+        let mut pc = WORD_SIZE * 2;
+        let mut image_size = 0;
+
+        for &section in SECTION_ORDER.iter() {
+            if section == Section::Bss {
+                image_size = pc;
+            }
+
+            section_starts.insert(section, next_aligned_addr(pc, WORD_SIZE));
+            for (i, &(_, object)) in objects.iter().enumerate() {
+                pc = next_aligned_addr(pc, WORD_SIZE);
+                bases[i].insert(section, pc);
+                pc += object.section_size(section);
+            }
+        }
+
+        let memory_size = pc;
+
+        let mut globals: HashMap<&String, (Addr, &String)> = HashMap::new();
+        for (i, &(ref name, object)) in objects.iter().enumerate() {
+            for symbol in &object.symbols {
+                if symbol.visibility != Visibility::Global {
+                    continue;
+                }
+
+                let addr = bases[i][&symbol.section] + symbol.offset;
+                if let Some(&(_, previous)) = globals.get(&symbol.name) {
+                    errors.push(LinkError::DuplicateSymbol(symbol.name.clone(),
+                                                           name.clone(),
+                                                           previous.clone()));
+                    continue;
+                }
+
+                globals.insert(&symbol.name, (addr, name));
+            }
+        }
+
+        let mut trits = vec![Trit::Zero; image_size];
+        for (i, &(ref name, object)) in objects.iter().enumerate() {
+            for &(section, ref contents) in &[(Section::Data, &object.data),
+                                              (Section::ReadOnlyData, &object.rodata),
+                                              (Section::Code, &object.code)] {
+                let base = bases[i][&section];
+                trits[base..base + contents.len()].copy_from_slice(contents);
+            }
+
+            let mut undefined = Vec::new();
+            for relocation in &object.relocations {
+                // an object's own symbols shadow globals from other objects
+                let addr = match object.symbol(&relocation.symbol) {
+                    Some(symbol) => bases[i][&symbol.section] + symbol.offset,
+                    None => {
+                        match globals.get(&relocation.symbol) {
+                            Some(&(addr, _)) => addr,
+                            None => {
+                                if !undefined.contains(&relocation.symbol) {
+                                    undefined.push(relocation.symbol.clone());
+                                }
+                                continue;
+                            }
+                        }
+                    }
+                };
+
+                let base = bases[i][&relocation.section];
+                let (value, len) = match relocation.kind {
+                    RelocationKind::Absolute => (addr as isize, WORD_ISIZE),
+                    RelocationKind::Relative(pc) => {
+                        (addr as isize - (base + pc) as isize, HALF_ISIZE)
+                    }
+                };
+
+                if len == HALF_ISIZE && (value < HALF_MIN || value > HALF_MAX) {
+                    let symbol = relocation.symbol.clone();
+                    errors.push(LinkError::BranchOutOfRange(symbol, value, name.clone()));
+                    continue;
+                }
+
+                let dest = &mut trits[base + relocation.offset..];
+                unsafe { ternary::from_int(dest.as_mut_ptr(), value, len) };
+            }
+
+            errors.extend(undefined.into_iter()
+                .map(|symbol| LinkError::UndefinedSymbol(symbol, name.clone())));
+        }
+
+        match globals.get(&START_SYMBOL.to_string()) {
+            Some(&(addr, _)) => unsafe {
+                ternary::from_int(trits.as_mut_ptr(), PROGRAM_MAGIC_NUMBER, WORD_ISIZE);
+                ternary::from_int(trits[WORD_SIZE..].as_mut_ptr(), addr as isize, WORD_ISIZE);
+            },
+            None => errors.push(LinkError::MissingStart),
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let read_only_start = section_starts[&Section::ReadOnlyData];
+        let read_only_end = section_starts[&Section::Code];
+        let read_only = if objects.iter().any(|&(_, object)| !object.rodata.is_empty()) {
+            Some((read_only_start, read_only_end))
+        } else {
+            None
+        };
+
+        // objects assembled without a line table just leave gaps in it
+        let mut lines = None;
+        for (i, &(_, object)) in objects.iter().enumerate() {
+            if let Some(ref object_lines) = object.lines {
+                let base = bases[i][&Section::Code];
+                lines.get_or_insert_with(LineTable::new).extend(object_lines, base);
+            }
+        }
+
+        Ok(Image {
+            trits: trits,
+            memory_size: memory_size,
+            read_only: read_only,
+            lines: lines,
+        })
+    }
+}
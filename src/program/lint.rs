@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use types::*;
+use registers::Register;
+use program::DecodedProgram;
+use program::instructions::Instruction;
+use program::location::Location;
+use program::parser::CodeDecl;
+use program::symbols::{display_label, is_numeric_label};
+use util::next_aligned_addr;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum LintWarning {
+    ZeroWrite(Location),
+    UnsetRegister(Register, Location),
+    BranchClobbersHi(Location),
+    UnsavedReturnAddress(Location),
+    UnreachableCode(Location),
+    BranchOutOfRange(String, RelAddr, Location),
+}
+
+impl LintWarning {
+    pub fn location(&self) -> &Location {
+        match *self {
+            LintWarning::ZeroWrite(ref location) |
+            LintWarning::UnsetRegister(_, ref location) |
+            LintWarning::BranchClobbersHi(ref location) |
+            LintWarning::UnsavedReturnAddress(ref location) |
+            LintWarning::UnreachableCode(ref location) |
+            LintWarning::BranchOutOfRange(_, _, ref location) => location,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match *self {
+            LintWarning::ZeroWrite(_) => "value written to `$zero` is discarded".to_string(),
+            LintWarning::UnsetRegister(r, _) => {
+                format!("`{}` is read after an instruction that doesn't set it", r.name())
+            }
+            LintWarning::BranchClobbersHi(_) => {
+                "`$hi` is read after a branch pseudo-instruction compared into it".to_string()
+            }
+            LintWarning::UnsavedReturnAddress(_) => {
+                "`call` overwrites `$ra` before it has been saved".to_string()
+            }
+            LintWarning::UnreachableCode(_) => "unreachable code".to_string(),
+            LintWarning::BranchOutOfRange(ref label, offset, _) => {
+                format!("branch to `{}` is {} trits away, out of range ({} to {})",
+                        display_label(label),
+                        offset,
+                        HALF_MIN,
+                        HALF_MAX)
+            }
+        }
+    }
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let location = self.location();
+        write!(f,
+               "{}: {}\n{}{}",
+               location,
+               self.message(),
+               location.snippet(),
+               location.notes())
+    }
+}
+
+// instructions keep the one they were expanded from, which is themselves if they weren't
+enum Entry<'a> {
+    Label(&'a str, &'a Location),
+    Instruction(Instruction, &'a Location, Addr, &'a Instruction),
+}
+
+pub fn lint(program: &DecodedProgram) -> Vec<LintWarning> {
+    let (entries, labels) = flatten(&program.code[..]);
+
+    let mut warnings = Vec::new();
+    check_zero_writes(&entries, &mut warnings);
+    check_lo_hi_reads(&entries, &mut warnings);
+    check_branch_clobbers(&entries, &mut warnings);
+    check_return_addresses(&entries, &mut warnings);
+    check_unreachable_code(&entries, &mut warnings);
+    check_branch_ranges(&entries, &labels, &mut warnings);
+
+    // a pseudo-instruction can trip the same check with each instruction it expands to
+    warnings.sort_by_key(|warning| {
+        let location = warning.location();
+        (location.path.clone(), location.span.line, location.span.column)
+    });
+    warnings.dedup();
+    warnings
+}
+
+// pseudo-instructions are checked as what they expand to, at the addresses the encoder gives them
+fn flatten(code: &[CodeDecl]) -> (Vec<Entry>, HashMap<&str, Addr>) {
+    let mut entries = Vec::new();
+    let mut labels = HashMap::new();
+    let mut pc = 0;
+
+    for code_decl in code {
+        match *code_decl {
+            CodeDecl::Label(ref label, ref location) => {
+                pc = next_aligned_addr(pc, WORD_SIZE);
+                labels.insert(&label[..], pc);
+                entries.push(Entry::Label(label, location));
+            }
+
+            CodeDecl::Instruction(ref instruction, ref location, _) => {
+                let parts = instruction.expand().unwrap_or_else(|| vec![instruction.clone()]);
+                for part in parts {
+                    let size = part.size();
+                    entries.push(Entry::Instruction(part, location, pc, instruction));
+                    pc += size;
+                }
+            }
+        }
+    }
+
+    (entries, labels)
+}
+
+fn check_zero_writes(entries: &[Entry], warnings: &mut Vec<LintWarning>) {
+    for entry in entries {
+        if let Entry::Instruction(ref instruction, location, _, _) = *entry {
+            // `nop` is `mov $zero, $zero`
+            let is_nop = *instruction == Instruction::Mov(Register::ZERO, Register::ZERO);
+            if instruction.dest_register() == Some(Register::ZERO) && !is_nop {
+                warnings.push(LintWarning::ZeroWrite(location.clone()));
+            }
+        }
+    }
+}
+
+// a read of $lo or $hi should follow whatever set it in the same run of code; at the start of a
+// block there's no telling what jumped there, so a read right after a label is left alone
+fn check_lo_hi_reads(entries: &[Entry], warnings: &mut Vec<LintWarning>) {
+    for (i, entry) in entries.iter().enumerate() {
+        let (instruction, location) = match *entry {
+            Entry::Instruction(ref instruction, location, _, _) => (instruction, location),
+            _ => continue,
+        };
+
+        for &r in &[Register::LO, Register::HI] {
+            if instruction.source_registers().contains(&r) && !is_set_before(entries, i, r) {
+                warnings.push(LintWarning::UnsetRegister(r, location.clone()));
+            }
+        }
+    }
+}
+
+fn is_set_before(entries: &[Entry], i: usize, r: Register) -> bool {
+    let mut after_instruction = false;
+
+    for entry in entries[..i].iter().rev() {
+        match *entry {
+            Entry::Label(_, _) => return !after_instruction,
+            Entry::Instruction(ref instruction, _, _, _) => {
+                match *instruction {
+                    // whatever was called or performed may have left a result there
+                    Instruction::Call(_) | Instruction::Syscall => return true,
+                    _ if instruction.writes(r) => return true,
+                    _ => after_instruction = true,
+                }
+            }
+        }
+    }
+
+    !after_instruction
+}
+
+// there's no assembler temporary, so `beq`, `blt` and `bgt` compare into $hi and anything the
+// program had there is lost by the time the branch falls through
+fn check_branch_clobbers(entries: &[Entry], warnings: &mut Vec<LintWarning>) {
+    for (i, entry) in entries.iter().enumerate() {
+        let (instruction, location, source) = match *entry {
+            Entry::Instruction(ref instruction, location, _, source) => {
+                (instruction, location, source)
+            }
+            _ => continue,
+        };
+
+        let reads_hi = instruction.source_registers().contains(&Register::HI);
+        if reads_hi && !is_branch_pseudo(source) && is_clobbered_by_branch(entries, i) {
+            warnings.push(LintWarning::BranchClobbersHi(location.clone()));
+        }
+    }
+}
+
+fn is_clobbered_by_branch(entries: &[Entry], i: usize) -> bool {
+    for entry in entries[..i].iter().rev() {
+        match *entry {
+            Entry::Label(_, _) => return false,
+            Entry::Instruction(ref instruction, _, _, source) => {
+                match *instruction {
+                    Instruction::Call(_) | Instruction::Syscall => return false,
+                    _ if instruction.writes(Register::HI) => return is_branch_pseudo(source),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    false
+}
+
+fn is_branch_pseudo(instruction: &Instruction) -> bool {
+    match *instruction {
+        Instruction::Beq(_, _, _) | Instruction::Blt(_, _, _) | Instruction::Bgt(_, _, _) => true,
+        _ => false,
+    }
+}
+
+// a function that returns through $ra has to save it before calling anything else
+fn check_return_addresses(entries: &[Entry], warnings: &mut Vec<LintWarning>) {
+    let mut start = 0;
+
+    while start < entries.len() {
+        let end = (start + 1..entries.len())
+            .find(|&i| starts_function(&entries[i]))
+            .unwrap_or(entries.len());
+
+        let mut saved = false;
+        let mut unsaved_call = None;
+        let mut returns = false;
+
+        for entry in &entries[start..end] {
+            if let Entry::Instruction(ref instruction, location, _, _) = *entry {
+                match *instruction {
+                    Instruction::Mov(_, Register::RA) |
+                    Instruction::St(_, Register::RA, _) |
+                    Instruction::Sh(_, Register::RA, _) |
+                    Instruction::Sw(_, Register::RA, _) => saved = true,
+                    Instruction::Call(_) if !saved && unsaved_call.is_none() => {
+                        unsaved_call = Some(location);
+                    }
+                    Instruction::Ret => returns = true,
+                    _ => {}
+                }
+            }
+        }
+
+        if let (Some(location), true) = (unsaved_call, returns) {
+            warnings.push(LintWarning::UnsavedReturnAddress(location.clone()));
+        }
+
+        start = end;
+    }
+}
+
+// the same labels that start a new scope for local labels
+fn starts_function(entry: &Entry) -> bool {
+    match *entry {
+        Entry::Label(label, location) => {
+            !label.contains('.') && !is_numeric_label(label) && location.expansion.is_none()
+        }
+        _ => false,
+    }
+}
+
+fn check_unreachable_code(entries: &[Entry], warnings: &mut Vec<LintWarning>) {
+    let mut reachable = true;
+
+    for entry in entries {
+        match *entry {
+            Entry::Label(_, _) => reachable = true,
+            Entry::Instruction(ref instruction, location, _, _) => {
+                if !reachable {
+                    warnings.push(LintWarning::UnreachableCode(location.clone()));
+                    reachable = true;
+                }
+
+                if is_unconditional(instruction) {
+                    reachable = false;
+                }
+            }
+        }
+    }
+}
+
+fn is_unconditional(instruction: &Instruction) -> bool {
+    match *instruction {
+        Instruction::Jmp(_) |
+        Instruction::Ret |
+        Instruction::Halt |
+        Instruction::J0(Register::ZERO, _) |
+        Instruction::JT0(Register::ZERO, _) |
+        Instruction::J01(Register::ZERO, _) => true,
+        _ => false,
+    }
+}
+
+fn check_branch_ranges(entries: &[Entry],
+                       labels: &HashMap<&str, Addr>,
+                       warnings: &mut Vec<LintWarning>) {
+    for entry in entries {
+        let (instruction, location, addr) = match *entry {
+            Entry::Instruction(ref instruction, location, addr, _) => {
+                (instruction, location, addr)
+            }
+            _ => continue,
+        };
+
+        let label = match *instruction {
+            Instruction::JT(_, ref label) |
+            Instruction::J0(_, ref label) |
+            Instruction::J1(_, ref label) |
+            Instruction::JT0(_, ref label) |
+            Instruction::JT1(_, ref label) |
+            Instruction::J01(_, ref label) => label,
+            _ => continue,
+        };
+
+        // labels outside the code are left for the encoder or linker to complain about
+        if let Some(&target) = labels.get(&label[..]) {
+            let offset = target as RelAddr - (addr + instruction.size()) as RelAddr;
+            if offset < HALF_MIN || offset > HALF_MAX {
+                let label = label.clone();
+                warnings.push(LintWarning::BranchOutOfRange(label, offset, location.clone()));
+            }
+        }
+    }
+}
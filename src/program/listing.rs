@@ -0,0 +1,175 @@
+use std::fmt;
+
+use ternary;
+use trit::Trit;
+use types::*;
+use program::decode::Section;
+use program::location::Location;
+
+const TRIT_COLUMN_WIDTH: usize = WORD_SIZE + WORD_SIZE / TRYTE_SIZE - 1;
+
+#[derive(Clone, Debug)]
+pub struct ListingLine {
+    pub addr: Addr,
+    pub trits: Vec<Trit>,
+    pub section: Section,
+    pub location: Location,
+}
+
+impl ListingLine {
+    pub fn new(addr: Addr, trits: Vec<Trit>, section: Section, location: Location) -> ListingLine {
+        ListingLine {
+            addr: addr,
+            trits: trits,
+            section: section,
+            location: location,
+        }
+    }
+
+    fn same_source_line(&self, other: &ListingLine) -> bool {
+        self.location.path == other.location.path &&
+        self.location.span.line == other.location.span.line &&
+        self.location.expansion == other.location.expansion
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Listing {
+    pub lines: Vec<ListingLine>,
+}
+
+impl Listing {
+    pub fn new(lines: Vec<ListingLine>) -> Listing {
+        Listing { lines: lines }
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut section = None;
+        let mut path = None;
+
+        for (i, line) in self.lines.iter().enumerate() {
+            if section != Some(line.section) {
+                section = Some(line.section);
+                try!(writeln!(f, ".{}", line.section.name()));
+            }
+
+            if path != Some(&line.location.path) {
+                path = Some(&line.location.path);
+                let name = line.location.path.as_ref().map_or("<input>", |s| &s[..]);
+                try!(writeln!(f, "; {}", name));
+            }
+
+            // a label sharing its line with data or an instruction is listed with them
+            let next = self.lines.get(i + 1);
+            if line.trits.is_empty() && next.map_or(false, |next| line.same_source_line(next)) {
+                continue;
+            }
+
+            let words: Vec<String> = line.trits.chunks(WORD_SIZE).map(format_word).collect();
+            try!(writeln!(f,
+                          "{:>8}  {:<width$}  {}",
+                          line.addr,
+                          words.first().map_or("", |s| &s[..]),
+                          line.location.source_line.trim_end(),
+                          width = TRIT_COLUMN_WIDTH));
+
+            for (j, word) in words.iter().enumerate().skip(1) {
+                try!(writeln!(f, "{:>8}  {}", line.addr + j * WORD_SIZE, word));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn format_word(trits: &[Trit]) -> String {
+    let trytes: Vec<String> = trits.chunks(TRYTE_SIZE)
+        .map(|tryte| unsafe { ternary::to_str(tryte.as_ptr(), tryte.len() as isize) })
+        .collect();
+    trytes.join(" ")
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: Addr,
+    pub section: Section,
+}
+
+impl Symbol {
+    pub fn new(name: String, addr: Addr, section: Section) -> Symbol {
+        Symbol {
+            name: name,
+            addr: addr,
+            section: section,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SymbolMapError {
+    pub line: usize,
+}
+
+impl fmt::Display for SymbolMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid symbol map entry on line {}", self.line)
+    }
+}
+
+// one `addr section name` entry per line, ordered by address
+#[derive(Clone, Debug)]
+pub struct SymbolMap {
+    pub symbols: Vec<Symbol>,
+}
+
+impl SymbolMap {
+    pub fn new(symbols: Vec<Symbol>) -> SymbolMap {
+        let mut symbols = symbols;
+        symbols.sort_by(|a, b| (a.addr, &a.name).cmp(&(b.addr, &b.name)));
+        SymbolMap { symbols: symbols }
+    }
+
+    pub fn parse(s: &str) -> Result<SymbolMap, SymbolMapError> {
+        let mut symbols = Vec::new();
+
+        for (i, line) in s.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() {
+                continue;
+            }
+
+            let error = SymbolMapError { line: i + 1 };
+            if fields.len() != 3 {
+                return Err(error);
+            }
+
+            let addr = try!(fields[0].parse().map_err(|_| SymbolMapError { line: i + 1 }));
+            let section = try!(Section::from_name(fields[1]).ok_or(error));
+            symbols.push(Symbol::new(fields[2].to_string(), addr, section));
+        }
+
+        Ok(SymbolMap::new(symbols))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    // the closest symbol at or before `addr`, for naming the function or object it falls in
+    pub fn symbol_at(&self, addr: Addr) -> Option<&Symbol> {
+        self.symbols.iter().take_while(|symbol| symbol.addr <= addr).last()
+    }
+}
+
+impl fmt::Display for SymbolMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for symbol in &self.symbols {
+            try!(writeln!(f, "{} {} {}", symbol.addr, symbol.section.name(), symbol.name));
+        }
+
+        Ok(())
+    }
+}
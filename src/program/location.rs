@@ -0,0 +1,70 @@
+use std::fmt;
+
+use program::lexer::Span;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Location {
+    pub path: Option<String>,
+    pub span: Span,
+    pub source_line: String,
+    pub expansion: Option<Box<Expansion>>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Expansion {
+    pub name: String,
+    pub call_site: Location,
+}
+
+impl Location {
+    pub fn new(path: Option<String>, span: Span, source_line: &str) -> Location {
+        Location {
+            path: path,
+            span: span,
+            source_line: source_line.to_string(),
+            expansion: None,
+        }
+    }
+
+    pub fn snippet(&self) -> String {
+        snippet(&self.source_line, self.span)
+    }
+
+    pub fn notes(&self) -> String {
+        expansion_notes(self.expansion.as_ref())
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = self.path.as_ref().map_or("<input>", |s| &s[..]);
+        write!(f, "{}:{}", path, self.span)
+    }
+}
+
+pub fn snippet(source_line: &str, span: Span) -> String {
+    let mut caret = String::new();
+    for c in source_line.chars().take(span.column.saturating_sub(1)) {
+        caret.push(if c == '\t' { '\t' } else { ' ' });
+    }
+
+    for _ in 0..span.len.max(1) {
+        caret.push('^');
+    }
+
+    format!("{}\n{}", source_line, caret)
+}
+
+pub fn expansion_notes(mut expansion: Option<&Box<Expansion>>) -> String {
+    let mut notes = String::new();
+
+    while let Some(e) = expansion {
+        notes.push_str(&format!("\nnote: in expansion of macro `{}` at {}\n{}",
+                                e.name,
+                                e.call_site,
+                                e.call_site.snippet()));
+        expansion = e.call_site.expansion.as_ref();
+    }
+
+    notes
+}
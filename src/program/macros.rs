@@ -0,0 +1,138 @@
+use std::collections::HashSet;
+
+use program::lexer::{is_ident_char, tokenize, Span, Token, TokenKind};
+use program::location::Location;
+
+#[derive(Clone, Debug)]
+pub struct Macro {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Vec<(usize, String)>,
+    pub location: Location,
+}
+
+impl Macro {
+    pub fn new(name: String, params: Vec<String>, location: Location) -> Macro {
+        Macro {
+            name: name,
+            params: params,
+            body: Vec::new(),
+            location: location,
+        }
+    }
+
+    // labels defined in the body get a suffix unique to the expansion, so a macro can be
+    // invoked more than once without its labels colliding
+    pub fn expand(&self, args: &[String], id: usize) -> Vec<(usize, String)> {
+        let substituted: Vec<(usize, String)> = self.body
+            .iter()
+            .map(|&(line, ref text)| (line, self.substitute(text, args, id)))
+            .collect();
+
+        let labels = defined_labels(&substituted);
+
+        substituted.into_iter()
+            .map(|(line, text)| (line, rename_labels(&text, line, &labels, id)))
+            .collect()
+    }
+
+    fn substitute(&self, text: &str, args: &[String], id: usize) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut result = String::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            if chars[i] == '\\' {
+                if chars.get(i + 1) == Some(&'@') {
+                    result.push_str(&id.to_string());
+                    i += 2;
+                    continue;
+                }
+
+                let name: String = chars[i + 1..]
+                    .iter()
+                    .cloned()
+                    .take_while(|&c| is_ident_char(c))
+                    .collect();
+
+                if let Some(index) = self.params.iter().position(|param| *param == name) {
+                    result.push_str(&args[index]);
+                    i += 1 + name.chars().count();
+                    continue;
+                }
+            }
+
+            result.push(chars[i]);
+            i += 1;
+        }
+
+        result
+    }
+}
+
+pub fn span_text(text: &str, span: Span) -> String {
+    text.chars().skip(span.column - 1).take(span.len).collect()
+}
+
+fn label_name(kind: &TokenKind) -> Option<String> {
+    match *kind {
+        TokenKind::Ident(ref name) => Some(name.clone()),
+        TokenKind::Directive(ref name) => Some(format!(".{}", name)),
+        _ => None,
+    }
+}
+
+fn leading_labels(tokens: &[Token]) -> usize {
+    let mut count = 0;
+
+    loop {
+        match (tokens.get(count * 2), tokens.get(count * 2 + 1)) {
+            (Some(&Token { kind: TokenKind::Ident(_), .. }),
+             Some(&Token { kind: TokenKind::Colon, .. })) |
+            (Some(&Token { kind: TokenKind::Directive(_), .. }),
+             Some(&Token { kind: TokenKind::Colon, .. })) |
+            (Some(&Token { kind: TokenKind::Decimal(_), .. }),
+             Some(&Token { kind: TokenKind::Colon, .. })) => count += 1,
+            _ => return count,
+        }
+    }
+}
+
+fn defined_labels(lines: &[(usize, String)]) -> HashSet<String> {
+    let mut labels = HashSet::new();
+
+    for &(line, ref text) in lines {
+        if let Ok(tokens) = tokenize(text, line) {
+            for token in &tokens[..leading_labels(&tokens) * 2] {
+                if let Some(name) = label_name(&token.kind) {
+                    labels.insert(name);
+                }
+            }
+        }
+    }
+
+    labels
+}
+
+fn rename_labels(text: &str, line: usize, labels: &HashSet<String>, id: usize) -> String {
+    let tokens = match tokenize(text, line) {
+        Ok(tokens) => tokens,
+        Err(_) => return text.to_string(),
+    };
+
+    // the identifier after any labels names the opcode or macro, not a label
+    let head = leading_labels(&tokens) * 2;
+
+    let mut chars: Vec<char> = text.chars().collect();
+    for (i, token) in tokens.iter().enumerate().rev() {
+        if let Some(name) = label_name(&token.kind) {
+            if i != head && labels.contains(&name) {
+                let start = token.span.column - 1;
+                let renamed: Vec<char> = format!("{}__{}", name, id).chars().collect();
+                chars.splice(start..start + token.span.len, renamed);
+            }
+        }
+    }
+
+    chars.into_iter().collect()
+}
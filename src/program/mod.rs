@@ -1,8 +1,21 @@
 pub mod instructions;
+pub mod lexer;
+pub mod ast;
+pub mod location;
+pub mod macros;
 pub mod parser;
+pub mod symbols;
 pub mod data;
 pub mod decode;
 pub mod encode;
+pub mod listing;
+pub mod lines;
+pub mod object;
+pub mod link;
+pub mod archive;
+pub mod format;
+pub mod lint;
+pub mod report;
 
 pub use self::decode::DecodedProgram;
 pub use self::encode::EncodedProgram;
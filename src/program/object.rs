@@ -0,0 +1,335 @@
+use std::fmt;
+
+use ternary;
+use types::*;
+use program::decode::Section;
+use program::lines::LineTable;
+
+static OBJECT_HEADER: &'static str = "tern-object";
+static IMAGE_HEADER: &'static str = "tern-image";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Visibility {
+    Local,
+    Global,
+}
+
+impl Visibility {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Visibility::Local => "local",
+            Visibility::Global => "global",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Visibility> {
+        match name {
+            "local" => Some(Visibility::Local),
+            "global" => Some(Visibility::Global),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ObjectSymbol {
+    pub name: String,
+    pub section: Section,
+    pub offset: Addr,
+    pub visibility: Visibility,
+}
+
+// the same two kinds of patch the encoder applies when it sees the whole program at once
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RelocationKind {
+    Absolute,
+    Relative(Addr),
+}
+
+impl RelocationKind {
+    pub fn size(&self) -> usize {
+        match *self {
+            RelocationKind::Absolute => WORD_SIZE,
+            RelocationKind::Relative(_) => HALF_SIZE,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Relocation {
+    pub section: Section,
+    pub offset: Addr,
+    pub kind: RelocationKind,
+    pub symbol: String,
+}
+
+#[derive(Debug)]
+pub struct FormatError {
+    pub line: usize,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "malformed file on line {}", self.line)
+    }
+}
+
+// offsets are relative to the start of the section they belong to
+#[derive(Clone, Debug)]
+pub struct ObjectFile {
+    pub data: Vec<Trit>,
+    pub rodata: Vec<Trit>,
+    pub code: Vec<Trit>,
+    pub bss_size: usize,
+    pub symbols: Vec<ObjectSymbol>,
+    pub relocations: Vec<Relocation>,
+    pub lines: Option<LineTable>,
+}
+
+impl ObjectFile {
+    pub fn new() -> ObjectFile {
+        ObjectFile {
+            data: Vec::new(),
+            rodata: Vec::new(),
+            code: Vec::new(),
+            bss_size: 0,
+            symbols: Vec::new(),
+            relocations: Vec::new(),
+            lines: None,
+        }
+    }
+
+    pub fn section_size(&self, section: Section) -> usize {
+        match section {
+            Section::Data => self.data.len(),
+            Section::ReadOnlyData => self.rodata.len(),
+            Section::Code => self.code.len(),
+            Section::Bss => self.bss_size,
+        }
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<&ObjectSymbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    pub fn parse(s: &str) -> Result<ObjectFile, FormatError> {
+        let mut object = ObjectFile::new();
+        let mut lines = s.lines().enumerate();
+
+        match lines.next() {
+            Some((_, line)) if line == OBJECT_HEADER => {}
+            _ => return Err(FormatError { line: 1 }),
+        }
+
+        let mut section = None;
+        for (i, line) in lines {
+            let error = || FormatError { line: i + 1 };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+
+            match fields.first().cloned() {
+                None => {}
+
+                Some("section") if fields.len() == 2 => {
+                    section = Some(try!(Section::from_name(fields[1]).ok_or_else(&error)));
+                }
+
+                Some("bss") if fields.len() == 2 => {
+                    object.bss_size = try!(fields[1].parse().map_err(|_| error()));
+                }
+
+                Some("symbol") if fields.len() == 5 => {
+                    object.symbols.push(ObjectSymbol {
+                        visibility: try!(Visibility::from_name(fields[1]).ok_or_else(&error)),
+                        section: try!(Section::from_name(fields[2]).ok_or_else(&error)),
+                        offset: try!(fields[3].parse().map_err(|_| error())),
+                        name: fields[4].to_string(),
+                    });
+                }
+
+                Some("reloc") if fields.len() == 5 || fields.len() == 6 => {
+                    let kind = match (fields[3], fields.len()) {
+                        ("absolute", 5) => RelocationKind::Absolute,
+                        ("relative", 6) => {
+                            RelocationKind::Relative(try!(fields[4].parse().map_err(|_| error())))
+                        }
+                        _ => return Err(error()),
+                    };
+
+                    object.relocations.push(Relocation {
+                        section: try!(Section::from_name(fields[1]).ok_or_else(&error)),
+                        offset: try!(fields[2].parse().map_err(|_| error())),
+                        kind: kind,
+                        symbol: fields[fields.len() - 1].to_string(),
+                    });
+                }
+
+                Some("file") | Some("line") => {
+                    let lines = object.lines.get_or_insert_with(LineTable::new);
+                    if !lines.parse_line(line) {
+                        return Err(error());
+                    }
+                }
+
+                Some(word) if is_trit_str(word) && fields.len() == 1 => {
+                    let trits = match section {
+                        Some(Section::Data) => &mut object.data,
+                        Some(Section::ReadOnlyData) => &mut object.rodata,
+                        Some(Section::Code) => &mut object.code,
+                        _ => return Err(error()),
+                    };
+
+                    push_trit_str(trits, word);
+                }
+
+                _ => return Err(error()),
+            }
+        }
+
+        Ok(object)
+    }
+}
+
+impl fmt::Display for ObjectFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "{}", OBJECT_HEADER));
+
+        for &(section, ref trits) in &[(Section::Data, &self.data),
+                                       (Section::ReadOnlyData, &self.rodata),
+                                       (Section::Code, &self.code)] {
+            try!(writeln!(f, "section {}", section.name()));
+            try!(write_trits(f, trits));
+        }
+
+        try!(writeln!(f, "bss {}", self.bss_size));
+
+        for symbol in &self.symbols {
+            try!(writeln!(f,
+                          "symbol {} {} {} {}",
+                          symbol.visibility.name(),
+                          symbol.section.name(),
+                          symbol.offset,
+                          symbol.name));
+        }
+
+        for relocation in &self.relocations {
+            try!(write!(f, "reloc {} {} ", relocation.section.name(), relocation.offset));
+            try!(match relocation.kind {
+                RelocationKind::Absolute => write!(f, "absolute "),
+                RelocationKind::Relative(pc) => write!(f, "relative {} ", pc),
+            });
+            try!(writeln!(f, "{}", relocation.symbol));
+        }
+
+        if let Some(ref lines) = self.lines {
+            try!(lines.write(f));
+        }
+
+        Ok(())
+    }
+}
+
+// a linked program: everything the VM needs in memory, minus the zeroed .bss at the end
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub trits: Vec<Trit>,
+    pub memory_size: usize,
+    pub read_only: Option<(Addr, Addr)>,
+    pub lines: Option<LineTable>,
+}
+
+impl Image {
+    pub fn is_image(s: &str) -> bool {
+        s.lines().next() == Some(IMAGE_HEADER)
+    }
+
+    pub fn parse(s: &str) -> Result<Image, FormatError> {
+        let mut image = Image {
+            trits: Vec::new(),
+            memory_size: 0,
+            read_only: None,
+            lines: None,
+        };
+
+        let mut lines = s.lines().enumerate();
+        match lines.next() {
+            Some((_, line)) if line == IMAGE_HEADER => {}
+            _ => return Err(FormatError { line: 1 }),
+        }
+
+        for (i, line) in lines {
+            let error = || FormatError { line: i + 1 };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+
+            match fields.first().cloned() {
+                None => {}
+
+                Some("memory") if fields.len() == 2 => {
+                    image.memory_size = try!(fields[1].parse().map_err(|_| error()));
+                }
+
+                Some("readonly") if fields.len() == 3 => {
+                    let start = try!(fields[1].parse().map_err(|_| error()));
+                    let end = try!(fields[2].parse().map_err(|_| error()));
+                    image.read_only = Some((start, end));
+                }
+
+                Some("file") | Some("line") => {
+                    let lines = image.lines.get_or_insert_with(LineTable::new);
+                    if !lines.parse_line(line) {
+                        return Err(error());
+                    }
+                }
+
+                Some(word) if is_trit_str(word) && fields.len() == 1 => {
+                    push_trit_str(&mut image.trits, word);
+                }
+
+                _ => return Err(error()),
+            }
+        }
+
+        if image.trits.len() > image.memory_size {
+            return Err(FormatError { line: 2 });
+        }
+
+        Ok(image)
+    }
+}
+
+impl fmt::Display for Image {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(writeln!(f, "{}", IMAGE_HEADER));
+        try!(writeln!(f, "memory {}", self.memory_size));
+
+        if let Some((start, end)) = self.read_only {
+            try!(writeln!(f, "readonly {} {}", start, end));
+        }
+
+        try!(write_trits(f, &self.trits));
+
+        match self.lines {
+            Some(ref lines) => lines.write(f),
+            None => Ok(()),
+        }
+    }
+}
+
+fn is_trit_str(s: &str) -> bool {
+    s.chars().all(|c| c == 'T' || c == '0' || c == '1')
+}
+
+// one word per line, most significant trit first like everywhere else trits are printed
+fn write_trits(f: &mut fmt::Formatter, trits: &[Trit]) -> fmt::Result {
+    for word in trits.chunks(WORD_SIZE) {
+        let s = unsafe { ternary::to_str(word.as_ptr(), word.len() as isize) };
+        try!(writeln!(f, "{}", s));
+    }
+
+    Ok(())
+}
+
+fn push_trit_str(trits: &mut Vec<Trit>, s: &str) {
+    let start = trits.len();
+    trits.resize(start + s.len(), Trit::Zero);
+    unsafe { ternary::from_str(trits[start..].as_mut_ptr(), s) };
+}
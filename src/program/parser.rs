@@ -1,480 +1,1107 @@
-use regex;
-use std::char;
+use std::collections::VecDeque;
+use std::fmt;
 use std::io;
 
 use ternary;
+use text;
 use types::*;
 use opcodes::Opcode;
 use registers::Register;
 use program::instructions::Instruction;
 use program::data::StaticData;
-
-mod patterns {
-    pub static COMMA: &'static str = r",\s*";
-    pub static TERNARY: &'static str = r"0t([10T]+)";
-    pub static LABEL: &'static str = r"([_a-zA-Z][_a-zA-Z0-9]*):";
-    pub static STATEMENT: &'static str = r"([_a-zA-Z][_a-zA-Z0-9]*)(\s+(.*))?";
-    pub static STRING: &'static str = r#"^\s*"(.+)"\s*$"#;
-}
+use program::ast::*;
+use program::lexer::{Lexer, Span, Token, TokenKind};
+use program::location::{expansion_notes, snippet, Expansion, Location};
+use program::symbols::SymbolTable;
 
 #[derive(Clone, Debug)]
 pub enum CodeDecl {
-    Label(String),
-    Instruction(Instruction),
+    Label(String, Location),
+    // the span is the instruction's label operand, if it has one
+    Instruction(Instruction, Location, Option<Span>),
 }
 
 impl CodeDecl {
     pub fn size(&self) -> usize {
         match *self {
-            CodeDecl::Instruction(ref inst) => inst.size(),
+            CodeDecl::Instruction(ref inst, _, _) => inst.size(),
             _ => 0,
         }
     }
+
+    pub fn location(&self) -> &Location {
+        match *self {
+            CodeDecl::Label(_, ref location) |
+            CodeDecl::Instruction(_, ref location, _) => location,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
 pub enum DataDecl {
-    Label(String),
-    Data(StaticData),
+    Label(String, Location),
+    Data(StaticData, Location),
 }
 
 impl DataDecl {
     pub fn size(&self) -> usize {
         match *self {
-            DataDecl::Data(ref data) => data.size(),
+            DataDecl::Data(ref data, _) => data.size(),
             _ => 0,
         }
     }
+
+    pub fn location(&self) -> &Location {
+        match *self {
+            DataDecl::Label(_, ref location) |
+            DataDecl::Data(_, ref location) => location,
+        }
+    }
 }
 
 #[derive(Debug)]
-pub enum ParseError {
-    Unknown,
-    RegexMatchFailure,
-    RegexMissingCapture,
+pub enum ParseErrorKind {
     InvalidCodeSection,
-    InvalidDataSection,
+    InvalidDataSection(&'static str),
+    InitializedBss,
     InvalidEscapeSequence(String),
-    InvalidLabel(String),
     InvalidDataType(String),
     InvalidDataSpec(String),
     InvalidArity(String, usize, usize),
     InvalidOpcode(String),
+    InvalidDirective(String),
+    InvalidOperand(&'static str, &'static str),
     InvalidTernary(String, usize),
-    InvalidDecimal(String),
+    InvalidNumber(String),
+    InvalidChar(String),
     InvalidRegister(String),
-    RegexError(regex::Error),
+    IntOverflow,
+    UndefinedSymbol(String),
+    DuplicateConstant(String),
+    RecursiveConstant(String),
+    NotConstant(String),
+    NotRelocatable(String),
+    InvalidMacroName(String),
+    DuplicateMacro(String),
+    NestedMacro,
+    UnterminatedMacro(String),
+    UnmatchedEndm,
+    MacroRecursion(String),
+    IncludeNotFound(String),
+    IncludeCycle(String),
+    UnexpectedCharacter(char),
+    UnexpectedToken(String),
+    UnexpectedEnd,
+    UnterminatedString,
     IOError(io::Error),
 }
 
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseErrorKind::InvalidCodeSection => f.write_str("data is not allowed in .code"),
+            ParseErrorKind::InvalidDataSection(section) => {
+                write!(f, "instructions are not allowed in .{}", section)
+            }
+            ParseErrorKind::InitializedBss => {
+                f.write_str("only `%space` and `%align` are allowed in .bss")
+            }
+            ParseErrorKind::InvalidEscapeSequence(ref s) => {
+                write!(f, "invalid escape sequence `{}`", s)
+            }
+            ParseErrorKind::InvalidDataType(ref s) => write!(f, "unknown data type `%{}`", s),
+            ParseErrorKind::InvalidDataSpec(ref s) => write!(f, "invalid `%{}` data", s),
+            ParseErrorKind::InvalidArity(ref name, expected, actual) => {
+                write!(f,
+                       "`{}` takes {} operand(s) but {} were given",
+                       name,
+                       expected,
+                       actual)
+            }
+            ParseErrorKind::InvalidOpcode(ref s) => write!(f, "unknown opcode `{}`", s),
+            ParseErrorKind::InvalidDirective(ref s) => write!(f, "unknown directive `.{}`", s),
+            ParseErrorKind::InvalidOperand(expected, found) => {
+                write!(f, "expected {} operand, found {}", expected, found)
+            }
+            ParseErrorKind::InvalidTernary(ref s, size) => {
+                write!(f, "ternary literal `{}` does not fit in {} trits", s, size)
+            }
+            ParseErrorKind::InvalidNumber(ref s) => write!(f, "invalid number `{}`", s),
+            ParseErrorKind::InvalidChar(ref s) => write!(f, "invalid character literal `{}`", s),
+            ParseErrorKind::InvalidRegister(ref s) => write!(f, "unknown register `{}`", s),
+            ParseErrorKind::IntOverflow => f.write_str("expression overflows"),
+            ParseErrorKind::UndefinedSymbol(ref s) => write!(f, "undefined symbol `{}`", s),
+            ParseErrorKind::DuplicateConstant(ref s) => {
+                write!(f, "constant `{}` is already defined", s)
+            }
+            ParseErrorKind::RecursiveConstant(ref s) => {
+                write!(f, "constant `{}` is defined in terms of itself", s)
+            }
+            ParseErrorKind::NotConstant(ref s) => {
+                write!(f, "`{}` depends on the program layout and can't be used here", s)
+            }
+            ParseErrorKind::NotRelocatable(ref s) => {
+                write!(f, "the address of `{}` isn't known until link time", s)
+            }
+            ParseErrorKind::InvalidMacroName(ref s) => write!(f, "invalid macro name `{}`", s),
+            ParseErrorKind::DuplicateMacro(ref s) => write!(f, "macro `{}` is already defined", s),
+            ParseErrorKind::NestedMacro => {
+                f.write_str("macros can't be defined inside another macro")
+            }
+            ParseErrorKind::UnterminatedMacro(ref s) => {
+                write!(f, "macro `{}` is missing `.endm`", s)
+            }
+            ParseErrorKind::UnmatchedEndm => f.write_str("`.endm` without `.macro`"),
+            ParseErrorKind::MacroRecursion(ref s) => {
+                write!(f, "macro `{}` expands too deeply", s)
+            }
+            ParseErrorKind::IncludeNotFound(ref s) => write!(f, "can't find included file `{}`", s),
+            ParseErrorKind::IncludeCycle(ref s) => write!(f, "`{}` includes itself", s),
+            ParseErrorKind::UnexpectedCharacter(c) => write!(f, "unexpected character `{}`", c),
+            ParseErrorKind::UnexpectedToken(ref s) => write!(f, "unexpected {}", s),
+            ParseErrorKind::UnexpectedEnd => f.write_str("unexpected end of line"),
+            ParseErrorKind::UnterminatedString => f.write_str("unterminated string"),
+            ParseErrorKind::IOError(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Option<Span>,
+    pub path: Option<String>,
+    pub source_line: Option<String>,
+    pub expansion: Option<Box<Expansion>>,
+}
+
 pub type ParseResult<T> = Result<T, ParseError>;
 
-pub fn clean_line<'a>(raw_line: &'a str) -> &'a str {
-    let line = raw_line.trim();
-    let mut end = line.len();
-    for (i, c) in line.chars().enumerate() {
-        if c == ';' {
-            end = i;
-            break;
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, span: Span) -> ParseError {
+        ParseError {
+            kind: kind,
+            span: Some(span),
+            path: None,
+            source_line: None,
+            expansion: None,
         }
     }
 
-    &line[..end].trim()
-}
+    pub fn without_span(kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            kind: kind,
+            span: None,
+            path: None,
+            source_line: None,
+            expansion: None,
+        }
+    }
 
-pub fn line_is_label(line: &str) -> bool {
-    line.chars().rev().next().unwrap() == ':'
-}
+    pub fn with_span(mut self, span: Span) -> ParseError {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
 
-pub fn line_is_data(line: &str) -> bool {
-    line.chars().next().unwrap() == '%'
-}
+        self
+    }
 
-pub fn parse_code_line(line: &str) -> ParseResult<CodeDecl> {
-    if line_is_label(line) {
-        parse_label_line(line).map(CodeDecl::Label)
-    } else {
-        parse_instruction(line).map(CodeDecl::Instruction)
+    pub fn with_source(mut self, path: Option<&str>, source_line: &str) -> ParseError {
+        if self.source_line.is_none() {
+            self.path = path.map(|s| s.to_string());
+            self.source_line = Some(source_line.to_string());
+        }
+
+        self
     }
-}
 
-pub fn parse_data_line(line: &str) -> ParseResult<DataDecl> {
-    if line_is_label(line) {
-        parse_label_line(line).map(DataDecl::Label)
-    } else if line_is_data(line) {
-        parse_data(&line[1..]).map(DataDecl::Data)
-    } else {
-        Err(ParseError::InvalidDataSection)
+    pub fn with_expansion(mut self, expansion: Option<&Box<Expansion>>) -> ParseError {
+        if self.expansion.is_none() {
+            self.expansion = expansion.cloned();
+        }
+
+        self
     }
-}
 
-fn compile_regex(pattern: &str) -> ParseResult<regex::Regex> {
-    regex::Regex::new(pattern).map_err(ParseError::RegexError)
-}
+    pub fn with_location(self, location: &Location) -> ParseError {
+        let has_source = self.source_line.is_some();
+        let error = self.with_source(location.path.as_ref().map(|s| &s[..]),
+                                     &location.source_line);
 
-fn get_capture<'a>(captures: &regex::Captures<'a>, i: usize) -> ParseResult<&'a str> {
-    captures.at(i).ok_or(ParseError::RegexMissingCapture)
-}
+        // an error that already has its own source line also has its own expansion, if any
+        if has_source {
+            error
+        } else {
+            error.with_expansion(location.expansion.as_ref())
+        }
+    }
 
-fn with_regex_captures<T, F>(pattern: &str, s: &str, mut f: F) -> ParseResult<T>
-    where F: FnMut(&regex::Captures) -> ParseResult<T>
-{
-    let re = try!(compile_regex(pattern));
-    let captures = try!(re.captures(s).ok_or(ParseError::RegexMatchFailure));
-    f(&captures)
+    pub fn position(&self) -> Option<(Option<String>, usize, usize)> {
+        self.span.map(|span| (self.path.clone(), span.line, span.column))
+    }
+
+    pub fn snippet(&self) -> Option<String> {
+        let span = match self.span {
+            Some(span) => span,
+            None => return None,
+        };
+
+        self.source_line.as_ref().map(|line| snippet(line, span))
+    }
 }
 
-pub fn parse_label_line(line: &str) -> ParseResult<String> {
-    with_regex_captures(patterns::LABEL, line, |ref captures| {
-        if let Some(label) = captures.at(1) {
-            Ok(label.to_string())
-        } else {
-            let label = &line[..line.len() - 1];
-            Err(ParseError::InvalidLabel(label.to_string()))
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = self.path.as_ref().map_or("<input>", |s| &s[..]);
+
+        match self.span {
+            Some(span) => try!(write!(f, "{}:{}: {}", path, span, self.kind)),
+            None => try!(write!(f, "{}: {}", path, self.kind)),
         }
-    })
+
+        if let Some(snippet) = self.snippet() {
+            try!(write!(f, "\n{}", snippet));
+        }
+
+        f.write_str(&expansion_notes(self.expansion.as_ref()))
+    }
 }
 
-fn parse_data(line: &str) -> ParseResult<StaticData> {
-    with_regex_captures(patterns::STATEMENT, line, |ref captures| {
-        let type_name = try!(get_capture(captures, 1));
-        let rest = try!(get_capture(captures, 3));
-        data_from_parts(type_name, rest)
-    })
+struct Parser {
+    lexer: Lexer,
+    peeked: VecDeque<Token>,
+    end_span: Span,
 }
 
-fn parse_instruction(line: &str) -> ParseResult<Instruction> {
-    with_regex_captures(patterns::STATEMENT, line, |ref captures| {
-        let opcode_name = try!(get_capture(captures, 1));
-        let args = if let Some(args_str) = captures.at(3) {
-            let comma_re = try!(compile_regex(patterns::COMMA));
-            comma_re.split(args_str).collect()
+impl Parser {
+    fn new(text: &str, line: usize) -> Parser {
+        Parser {
+            lexer: Lexer::new(text, line),
+            peeked: VecDeque::new(),
+            end_span: Span::new(line, text.trim_end().chars().count() + 1, 1),
+        }
+    }
+
+    fn fill(&mut self, n: usize) -> ParseResult<()> {
+        while self.peeked.len() < n {
+            match try!(self.lexer.next_token()) {
+                Some(token) => self.peeked.push_back(token),
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn peek(&mut self, n: usize) -> ParseResult<Option<&TokenKind>> {
+        try!(self.fill(n + 1));
+        Ok(self.peeked.get(n).map(|token| &token.kind))
+    }
+
+    fn next(&mut self) -> ParseResult<Option<Token>> {
+        try!(self.fill(1));
+        Ok(self.peeked.pop_front())
+    }
+
+    fn expect_next(&mut self) -> ParseResult<Token> {
+        let end_span = self.end_span;
+        try!(self.next()).ok_or_else(|| ParseError::new(ParseErrorKind::UnexpectedEnd, end_span))
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> ParseResult<Token> {
+        let token = try!(self.expect_next());
+        if token.kind == kind {
+            Ok(token)
         } else {
-            Vec::new()
+            Err(unexpected(&token))
+        }
+    }
+
+    fn expect_ident(&mut self) -> ParseResult<Spanned<String>> {
+        let token = try!(self.expect_next());
+        match token.kind {
+            TokenKind::Ident(name) => Ok(Spanned::new(name, token.span)),
+            _ => Err(unexpected(&token)),
+        }
+    }
+
+    fn expect_label(&mut self) -> ParseResult<Spanned<String>> {
+        let token = try!(self.expect_next());
+        match token.kind {
+            TokenKind::Ident(name) => Ok(Spanned::new(name, token.span)),
+            TokenKind::Directive(name) => Ok(Spanned::new(format!(".{}", name), token.span)),
+            _ => Err(unexpected(&token)),
+        }
+    }
+
+    fn expect_end(&mut self) -> ParseResult<()> {
+        match try!(self.next()) {
+            Some(token) => Err(unexpected(&token)),
+            None => Ok(()),
+        }
+    }
+
+    fn parse_line(&mut self) -> ParseResult<Vec<Statement>> {
+        let mut statements = Vec::new();
+
+        loop {
+            // only look past a directive when a colon follows, so directives can still take
+            // their raw arguments
+            try!(self.fill(1));
+            let lookahead = match self.peeked.get(0) {
+                Some(&Token { kind: TokenKind::Ident(_), .. }) |
+                Some(&Token { kind: TokenKind::Decimal(_), .. }) => true,
+                Some(&Token { kind: TokenKind::Directive(_), .. }) => {
+                    self.peeked.len() == 1 && self.lexer.peek_char() == Some(':')
+                }
+                _ => false,
+            };
+
+            if lookahead {
+                try!(self.fill(2));
+            }
+
+            let label = match (self.peeked.get(0), self.peeked.get(1)) {
+                (Some(&Token { ref kind, span }), Some(&Token { kind: TokenKind::Colon, .. })) => {
+                    match *kind {
+                        TokenKind::Ident(ref name) => Spanned::new(name.clone(), span),
+                        TokenKind::Directive(ref name) => Spanned::new(format!(".{}", name), span),
+                        TokenKind::Decimal(ref digits) => Spanned::new(digits.clone(), span),
+                        _ => break,
+                    }
+                }
+                _ => break,
+            };
+
+            try!(self.next());
+            try!(self.next());
+            statements.push(Statement::Label(label));
+        }
+
+        let token = match try!(self.next()) {
+            Some(token) => token,
+            None => return Ok(statements),
         };
 
-        instruction_from_parts(opcode_name, &args[..])
-    })
-}
+        let statement = match token.kind {
+            TokenKind::Directive(ref name) if name == "equ" => {
+                let name = try!(self.expect_ident());
+                if let Some(&TokenKind::Comma) = try!(self.peek(0)) {
+                    try!(self.next());
+                }
 
-fn parse_label(s: &str) -> ParseResult<String> {
-    Ok(s.to_string())
-}
+                Statement::Constant(name, try!(self.parse_expr()))
+            }
 
-fn parse_register(s: &str) -> ParseResult<Register> {
-    s.parse().map_err(|name| ParseError::InvalidRegister(name))
-}
+            TokenKind::Directive(name) => {
+                let (rest, rest_span) = self.lexer.rest();
+                Statement::Directive(Spanned::new(name, token.span),
+                                     Spanned::new(rest, rest_span))
+            }
 
-fn parse_decimal(s: &str) -> ParseResult<isize> {
-    s.parse().map_err(|_| ParseError::InvalidDecimal(s.to_string()))
-}
+            TokenKind::Percent => Statement::Data(try!(self.parse_data_spec(false))),
 
-fn parse_tryte(s: &str) -> ParseResult<Tryte> {
-    let mut tryte = EMPTY_TRYTE;
+            TokenKind::Ident(name) => {
+                let opcode = Spanned::new(name, token.span);
+                let operands = try!(self.parse_operands());
+                Statement::Instruction(opcode, operands)
+            }
+
+            _ => return Err(unexpected(&token)),
+        };
+
+        try!(self.expect_end());
+        statements.push(statement);
+        Ok(statements)
+    }
+
+    fn parse_operands(&mut self) -> ParseResult<Vec<Spanned<Operand>>> {
+        let mut operands = Vec::new();
+
+        if try!(self.peek(0)).is_none() {
+            return Ok(operands);
+        }
+
+        operands.push(try!(self.parse_operand()));
+
+        while let Some(&TokenKind::Comma) = try!(self.peek(0)) {
+            try!(self.next());
+            operands.push(try!(self.parse_operand()));
+        }
+
+        Ok(operands)
+    }
+
+    fn parse_operand(&mut self) -> ParseResult<Spanned<Operand>> {
+        let token = match try!(self.peek(0)) {
+            Some(&TokenKind::Register(_)) |
+            Some(&TokenKind::String(_)) |
+            Some(&TokenKind::Percent) => try!(self.expect_next()),
+            _ => return self.parse_expr(),
+        };
+
+        let operand = match token.kind {
+            TokenKind::Register(ref name) => {
+                let name = format!("${}", name);
+                let register = try!(name.parse()
+                    .map_err(|name| ParseError::new(ParseErrorKind::InvalidRegister(name),
+                                                    token.span)));
+                Operand::Register(register)
+            }
+
+            TokenKind::String(ref s) => Operand::String(s.clone()),
+
+            TokenKind::Percent => {
+                let spec = try!(self.parse_data_spec(true));
+                let end = spec.args.last().map_or(spec.type_name.span, |arg| arg.span);
+                let span = token.span.to(end);
+                return Ok(Spanned::new(Operand::Data(Box::new(spec)), span));
+            }
 
-    if let Ok(int) = s.parse() {
-        assert!(TRYTE_MIN <= int && int <= TRYTE_MAX);
-        unsafe { ternary::from_int(mut_ptr!(tryte), int, TRYTE_ISIZE) };
-        return Ok(tryte);
+            _ => return Err(unexpected(&token)),
+        };
+
+        Ok(Spanned::new(operand, token.span))
+    }
+
+    fn parse_expr(&mut self) -> ParseResult<Spanned<Operand>> {
+        let mut lhs = try!(self.parse_term());
+
+        loop {
+            let op = match try!(self.peek(0)) {
+                Some(&TokenKind::Plus) => BinOp::Add,
+                Some(&TokenKind::Minus) => BinOp::Sub,
+                _ => return Ok(lhs),
+            };
+
+            try!(self.next());
+            let rhs = try!(self.parse_term());
+            lhs = binary(op, lhs, rhs);
+        }
     }
 
-    with_regex_captures(patterns::TERNARY, s, |ref captures| {
-        if let Some(trit_str) = captures.at(1) {
-            assert!(trit_str.len() <= TRYTE_SIZE);
-            unsafe { ternary::from_str(mut_ptr!(tryte), trit_str) };
-            Ok(tryte)
+    fn parse_term(&mut self) -> ParseResult<Spanned<Operand>> {
+        let mut lhs = try!(self.parse_unary());
+
+        while let Some(&TokenKind::Star) = try!(self.peek(0)) {
+            try!(self.next());
+            let rhs = try!(self.parse_unary());
+            lhs = binary(BinOp::Mul, lhs, rhs);
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> ParseResult<Spanned<Operand>> {
+        if let Some(&TokenKind::Minus) = try!(self.peek(0)) {
+            let minus = try!(self.expect_next());
+            let operand = try!(self.parse_unary());
+            let span = minus.span.to(operand.span);
+
+            // fold negative literals so they keep their literal form
+            let negated = match operand.node {
+                Operand::Decimal(n) => Operand::Decimal(-n),
+                Operand::Oversized(ref s) => Operand::Oversized(format!("-{}", s)),
+                Operand::Ternary(ref trits) => Operand::Ternary(negate_trit_str(trits)),
+                _ => Operand::Expr(Box::new(Expr::Neg(operand.clone()))),
+            };
+
+            return Ok(Spanned::new(negated, span));
+        }
+
+        self.parse_atom()
+    }
+
+    fn parse_atom(&mut self) -> ParseResult<Spanned<Operand>> {
+        let token = try!(self.expect_next());
+
+        let operand = match token.kind {
+            TokenKind::Decimal(ref digits) => {
+                parse_radix(digits, 10).map_or_else(|| Operand::Oversized(digits.clone()),
+                                                    Operand::Decimal)
+            }
+
+            TokenKind::Ternary(ref trits) => Operand::Ternary(trits.clone()),
+
+            TokenKind::Nonary(ref digits) => {
+                parse_radix(digits, 9).map_or_else(|| Operand::Oversized(format!("0n{}", digits)),
+                                                   Operand::Decimal)
+            }
+
+            TokenKind::Heptavigesimal(ref digits) => {
+                parse_radix(digits, 27).map_or_else(|| Operand::Oversized(format!("0h{}", digits)),
+                                                    Operand::Decimal)
+            }
+
+            TokenKind::Char(c) => {
+                let value = try!(text::char_value(c).ok_or_else(|| {
+                    ParseError::new(ParseErrorKind::InvalidChar(format!("{:?}", c)), token.span)
+                }));
+                Operand::Decimal(value)
+            }
+
+            TokenKind::Ident(ref name) if name == "sizeof" => {
+                let parenthesized = try!(self.peek(0)) == Some(&TokenKind::LParen);
+                if parenthesized {
+                    try!(self.next());
+                }
+
+                let label = try!(self.expect_label());
+                let mut span = token.span.to(label.span);
+                if parenthesized {
+                    span = token.span.to(try!(self.expect(TokenKind::RParen)).span);
+                }
+
+                return Ok(Spanned::new(Operand::Expr(Box::new(Expr::Sizeof(label))), span));
+            }
+
+            TokenKind::Ident(ref name) => Operand::Label(name.clone()),
+
+            TokenKind::Directive(ref name) => Operand::Label(format!(".{}", name)),
+
+            TokenKind::LParen => {
+                let inner = try!(self.parse_expr());
+                let close = try!(self.expect(TokenKind::RParen));
+                return Ok(Spanned::new(inner.node, token.span.to(close.span)));
+            }
+
+            _ => return Err(unexpected(&token)),
+        };
+
+        Ok(Spanned::new(operand, token.span))
+    }
+
+    fn parse_data_spec(&mut self, nested: bool) -> ParseResult<DataSpec> {
+        let type_name = try!(self.expect_ident());
+        let mut args = Vec::new();
+
+        if type_name.node == "array" {
+            try!(self.expect(TokenKind::Percent));
+            let element = try!(self.parse_data_spec(true));
+            let element_end = element.args.last().map_or(element.type_name.span, |arg| arg.span);
+            let element_span = element.type_name.span.to(element_end);
+            args.push(Spanned::new(Operand::Data(Box::new(element)), element_span));
+
+            let x = try!(self.expect_ident());
+            if x.node != "x" {
+                return Err(ParseError::new(ParseErrorKind::UnexpectedToken(format!("`{}`", x.node)),
+                                           x.span));
+            }
+
+            args.push(try!(self.parse_operand()));
         } else {
-            Err(ParseError::InvalidTernary(s.to_string(), TRYTE_SIZE))
+            let at_end = match try!(self.peek(0)) {
+                None => true,
+                Some(&TokenKind::Ident(ref s)) => nested && s == "x",
+                _ => false,
+            };
+
+            if !at_end {
+                args.push(try!(self.parse_operand()));
+
+                while let Some(&TokenKind::Comma) = try!(self.peek(0)) {
+                    try!(self.next());
+                    args.push(try!(self.parse_operand()));
+                }
+            }
         }
+
+        Ok(DataSpec {
+            type_name: type_name,
+            args: args,
+        })
+    }
+}
+
+fn binary(op: BinOp, lhs: Spanned<Operand>, rhs: Spanned<Operand>) -> Spanned<Operand> {
+    let span = lhs.span.to(rhs.span);
+    Spanned::new(Operand::Expr(Box::new(Expr::Binary(op, lhs, rhs))), span)
+}
+
+fn unexpected(token: &Token) -> ParseError {
+    ParseError::new(ParseErrorKind::UnexpectedToken(token.kind.to_string()), token.span)
+}
+
+// the lexer has already checked the digits, so the only way to fail is to overflow
+fn parse_radix(digits: &str, radix: u32) -> Option<isize> {
+    digits.chars().fold(Some(0), |n, c| {
+        let digit = c.to_digit(radix).unwrap() as isize;
+        n.and_then(|n| n.checked_mul(radix as isize)).and_then(|n| n.checked_add(digit))
     })
 }
 
-fn parse_half(s: &str) -> ParseResult<Half> {
-    let mut half = EMPTY_HALF;
+fn negate_trit_str(trits: &str) -> String {
+    trits.chars()
+        .map(|c| match c {
+            '1' => 'T',
+            'T' => '1',
+            c => c,
+        })
+        .collect()
+}
+
+pub fn parse_line(text: &str, line: usize) -> ParseResult<Vec<Statement>> {
+    Parser::new(text, line).parse_line()
+}
 
-    if let Ok(int) = s.parse() {
-        assert!(HALF_MIN <= int && int <= HALF_MAX);
-        unsafe { ternary::from_int(mut_ptr!(half), int, HALF_ISIZE) };
-        return Ok(half);
+pub fn code_decl(statement: &Statement,
+                 location: Location,
+                 symbols: &SymbolTable)
+                 -> ParseResult<CodeDecl> {
+    match *statement {
+        Statement::Label(ref label) => Ok(CodeDecl::Label(label.node.clone(), location)),
+        Statement::Instruction(ref opcode, ref args) => {
+            let instruction = try!(instruction_from_parts(opcode, &args[..], symbols));
+            let label_span = args.iter()
+                .find(|arg| match arg.node {
+                    Operand::Label(_) => true,
+                    _ => false,
+                })
+                .map(|arg| arg.span);
+            Ok(CodeDecl::Instruction(instruction, location, label_span))
+        }
+        _ => Err(ParseError::new(ParseErrorKind::InvalidCodeSection, statement.span())),
     }
+}
 
-    with_regex_captures(patterns::TERNARY, s, |ref captures| {
-        if let Some(trit_str) = captures.at(1) {
-            assert!(trit_str.len() <= HALF_SIZE);
-            unsafe { ternary::from_str(mut_ptr!(half), trit_str) };
-            Ok(half)
-        } else {
-            Err(ParseError::InvalidTernary(s.to_string(), HALF_SIZE))
+pub fn data_decl(statement: &Statement,
+                 location: Location,
+                 symbols: &SymbolTable)
+                 -> ParseResult<DataDecl> {
+    match *statement {
+        Statement::Label(ref label) => Ok(DataDecl::Label(label.node.clone(), location)),
+        Statement::Data(ref spec) => {
+            let data = try!(data_from_spec(spec, symbols));
+            Ok(DataDecl::Data(data, location))
         }
-    })
+        _ => Err(ParseError::new(ParseErrorKind::InvalidDataSection("data"), statement.span())),
+    }
 }
 
-fn parse_word(s: &str) -> ParseResult<Word> {
-    let mut word = EMPTY_WORD;
+fn invalid_operand(expected: &'static str, arg: &Spanned<Operand>) -> ParseError {
+    ParseError::new(ParseErrorKind::InvalidOperand(expected, arg.node.description()),
+                    arg.span)
+}
+
+fn parse_label(arg: &Spanned<Operand>) -> ParseResult<String> {
+    match arg.node {
+        Operand::Label(ref label) => Ok(label.clone()),
+        _ => Err(invalid_operand("label", arg)),
+    }
+}
 
-    if let Ok(int) = s.parse() {
-        assert!(WORD_MIN <= int && int <= WORD_MAX);
-        unsafe { ternary::from_int(mut_ptr!(word), int, WORD_ISIZE) };
-        return Ok(word);
+fn parse_register(arg: &Spanned<Operand>) -> ParseResult<Register> {
+    match arg.node {
+        Operand::Register(r) => Ok(r),
+        _ => Err(invalid_operand("register", arg)),
     }
+}
 
-    with_regex_captures(patterns::TERNARY, s, |ref captures| {
-        if let Some(trit_str) = captures.at(1) {
-            assert!(trit_str.len() <= WORD_SIZE);
-            unsafe { ternary::from_str(mut_ptr!(word), trit_str) };
-            Ok(word)
-        } else {
-            Err(ParseError::InvalidTernary(s.to_string(), WORD_SIZE))
+fn int_range(size: usize) -> (isize, isize) {
+    let max = (3isize.pow(size as u32) - 1) / 2;
+    (-max, max)
+}
+
+fn parse_int(arg: &Spanned<Operand>, size: usize, symbols: &SymbolTable) -> ParseResult<isize> {
+    let (min, max) = int_range(size);
+
+    if let Operand::Ternary(ref trit_str) = arg.node {
+        if trit_str.trim_start_matches('0').len() > size {
+            let literal = format!("0t{}", trit_str);
+            return Err(ParseError::new(ParseErrorKind::InvalidTernary(literal, size), arg.span));
         }
-    })
+    }
+
+    if let Operand::Oversized(ref literal) = arg.node {
+        symbols.add_out_of_range(literal.clone(), min, max, arg.span);
+        return Ok(0);
+    }
+
+    let n = try!(symbols.eval(arg));
+    if n < min || n > max {
+        symbols.add_out_of_range(n.to_string(), min, max, arg.span);
+    }
+
+    Ok(n)
 }
 
-fn parse_string(s: &str) -> ParseResult<String> {
-    with_regex_captures(patterns::STRING, s, |ref captures| {
-        let string = try!(get_capture(captures, 1));
-        let unescaped_string = try!(unescape_string(string));
-        Ok(unescaped_string)
-    })
+fn parse_tryte(arg: &Spanned<Operand>, symbols: &SymbolTable) -> ParseResult<Tryte> {
+    let mut tryte = EMPTY_TRYTE;
+    let n = try!(parse_int(arg, TRYTE_SIZE, symbols));
+    unsafe { ternary::from_int(mut_ptr!(tryte), n, TRYTE_ISIZE) };
+    Ok(tryte)
 }
 
-fn unescape_string(s: &str) -> ParseResult<String> {
-    let mut result = String::new();
-    let mut chars = s.chars();
+fn parse_half(arg: &Spanned<Operand>, symbols: &SymbolTable) -> ParseResult<Half> {
+    let mut half = EMPTY_HALF;
+    let n = try!(parse_int(arg, HALF_SIZE, symbols));
+    unsafe { ternary::from_int(mut_ptr!(half), n, HALF_ISIZE) };
+    Ok(half)
+}
 
-    while let Some(c) = chars.next() {
-        let unescaped = if c == '\\' {
-            try!(unescape_chars(&mut chars))
-        } else {
-            c
-        };
+fn parse_word(arg: &Spanned<Operand>, symbols: &SymbolTable) -> ParseResult<Word> {
+    let mut word = EMPTY_WORD;
+    let n = try!(parse_int(arg, WORD_SIZE, symbols));
+    unsafe { ternary::from_int(mut_ptr!(word), n, WORD_ISIZE) };
+    Ok(word)
+}
 
-        result.push(unescaped);
+fn parse_string(arg: &Spanned<Operand>) -> ParseResult<String> {
+    match arg.node {
+        Operand::String(ref s) => Ok(s.clone()),
+        _ => Err(invalid_operand("string", arg)),
     }
+}
 
-    Ok(result)
+fn data_arity(spec: &DataSpec, arity: usize) -> ParseResult<()> {
+    if spec.args.len() == arity {
+        Ok(())
+    } else {
+        Err(ParseError::new(ParseErrorKind::InvalidDataSpec(spec.type_name.node.clone()),
+                            spec.type_name.span))
+    }
 }
 
-fn unescape_chars<I>(chars: &mut I) -> ParseResult<char> where I: Iterator<Item = char> {
-    match chars.next() {
-        Some('u') => {
-            let seq: String = chars.take(4).collect();
+fn int_data<F>(spec: &DataSpec,
+               size: usize,
+               make_data: F,
+               symbols: &SymbolTable)
+               -> ParseResult<StaticData>
+    where F: Fn(isize) -> StaticData
+{
+    if spec.args.is_empty() {
+        return Err(ParseError::new(ParseErrorKind::InvalidDataSpec(spec.type_name.node.clone()),
+                                   spec.type_name.span));
+    }
 
-            let mut code = 0;
-            for c in seq.chars() {
-                let n = try!(c.to_digit(16)
-                    .ok_or_else(|| ParseError::InvalidEscapeSequence(seq.clone())));
-                code = code * 16 + n;
-            }
+    let mut items = Vec::new();
+    for arg in &spec.args {
+        items.push(make_data(try!(parse_int(arg, size, symbols))));
+    }
 
-            char::from_u32(code).ok_or_else(|| ParseError::InvalidEscapeSequence(seq.clone()))
+    if items.len() == 1 {
+        Ok(items.remove(0))
+    } else {
+        Ok(StaticData::List(items))
+    }
+}
+
+// sizes change the layout, so they have to be known before it is
+fn parse_count(arg: &Spanned<Operand>, min: isize, symbols: &SymbolTable) -> ParseResult<usize> {
+    let max = isize::max_value();
+    if let Operand::Oversized(ref literal) = arg.node {
+        symbols.add_out_of_range(literal.clone(), min, max, arg.span);
+        return Ok(min as usize);
+    }
+
+    match try!(symbols.eval_constant(arg)) {
+        n if n >= min => Ok(n as usize),
+        n => {
+            symbols.add_out_of_range(n.to_string(), min, max, arg.span);
+            Ok(min as usize)
         }
-        Some('b') => Ok('\x08'),
-        Some('f') => Ok('\x0c'),
-        Some('n') => Ok('\n'),
-        Some('r') => Ok('\r'),
-        Some('t') => Ok('\t'),
-        Some('\\') => Ok('\\'),
-        Some('"') => Ok('"'),
-        _ => Err(ParseError::InvalidEscapeSequence("\\".to_string())),
     }
 }
 
-fn data_from_parts<'a>(type_name: &'a str, rest: &'a str) -> ParseResult<StaticData> {
-    match type_name {
-        "tryte" => {
-            let tryte = try!(parse_tryte(rest));
-            let i = unsafe { ternary::to_int(ptr!(tryte), TRYTE_ISIZE) };
-            Ok(StaticData::Tryte(i))
+pub fn data_from_spec(spec: &DataSpec, symbols: &SymbolTable) -> ParseResult<StaticData> {
+    let args = &spec.args[..];
+
+    match &spec.type_name.node[..] {
+        "tryte" => int_data(spec, TRYTE_SIZE, StaticData::Tryte, symbols),
+        "half" => int_data(spec, HALF_SIZE, StaticData::Half, symbols),
+        "word" => int_data(spec, WORD_SIZE, StaticData::Word, symbols),
+
+        "string" => {
+            try!(data_arity(spec, 1));
+            Ok(StaticData::String(try!(parse_string(&args[0]))))
         }
 
-        "half" => {
-            let half = try!(parse_half(rest));
-            let i = unsafe { ternary::to_int(ptr!(half), HALF_ISIZE) };
-            Ok(StaticData::Half(i))
+        "array" => {
+            try!(data_arity(spec, 2));
+
+            let data = match args[0].node {
+                Operand::Data(ref element) => try!(data_from_spec(element, symbols)),
+                _ => return Err(invalid_operand("data", &args[0])),
+            };
+
+            let count = try!(parse_count(&args[1], 1, symbols));
+
+            Ok(StaticData::Array(Box::new(data), count))
         }
 
-        "word" => {
-            let word = try!(parse_word(rest));
-            let i = unsafe { ternary::to_int(ptr!(word), WORD_ISIZE) };
-            Ok(StaticData::Word(i))
+        "space" => {
+            try!(data_arity(spec, 1));
+            let size = try!(parse_count(&args[0], 0, symbols));
+            Ok(StaticData::Space(size))
         }
 
-        "string" => {
-            let string = try!(parse_string(rest));
-            Ok(StaticData::String(string))
+        "align" => {
+            try!(data_arity(spec, 1));
+            let alignment = try!(parse_count(&args[0], 1, symbols));
+            Ok(StaticData::Align(alignment))
         }
 
-        "array" => {
-            let mut parts = rest.split(" x ").map(|s| s.trim());
-            let data_str = try!(parts.next()
-                .ok_or_else(|| ParseError::InvalidDataSpec(rest.to_string())));
-            let count_str = try!(parts.next()
-                .ok_or_else(|| ParseError::InvalidDataSpec(rest.to_string())));
-
-            if let DataDecl::Data(data) = try!(parse_data_line(data_str)) {
-                let count = try!(parse_decimal(count_str));
-                assert!(count > 0);
-                Ok(StaticData::Array(Box::new(data), count as usize))
-            } else {
-                Err(ParseError::InvalidDataSpec(data_str.to_string()))
-            }
+        _ => {
+            Err(ParseError::new(ParseErrorKind::InvalidDataType(spec.type_name.node.clone()),
+                                spec.type_name.span))
         }
+    }
+}
 
-        _ => Err(ParseError::InvalidDataType(type_name.to_string())),
+fn check_arity(opcode_name: &Spanned<String>,
+               args: &[Spanned<Operand>],
+               expected_arity: usize)
+               -> ParseResult<()> {
+    let actual_arity = args.len();
+    if expected_arity == actual_arity {
+        return Ok(());
     }
+
+    let span = args.last().map_or(opcode_name.span, |arg| opcode_name.span.to(arg.span));
+    Err(ParseError::new(ParseErrorKind::InvalidArity(opcode_name.node.clone(),
+                                                     expected_arity,
+                                                     actual_arity),
+                        span))
 }
 
-fn instruction_from_parts<'a>(opcode_name: &'a str, args: &[&'a str]) -> ParseResult<Instruction> {
-    if !Opcode::name_is_valid(opcode_name) {
-        return Err(ParseError::InvalidOpcode(opcode_name.to_string()));
+pub fn pseudo_arity(name: &str) -> Option<usize> {
+    match name {
+        "nop" => Some(0),
+        "b" | "inc" | "dec" => Some(1),
+        "li" | "la" | "neg" => Some(2),
+        "sub" | "beq" | "blt" | "bgt" => Some(3),
+        _ => None,
     }
+}
 
-    let opcode = Opcode::from(opcode_name);
+fn half_from_int(n: isize) -> Half {
+    let mut half = EMPTY_HALF;
+    unsafe { ternary::from_int(mut_ptr!(half), n, HALF_ISIZE) };
+    half
+}
 
-    let expected_arity = opcode.arity();
-    let actual_arity = args.len();
-    if expected_arity != actual_arity {
-        return Err(ParseError::InvalidArity(opcode_name.to_string(), expected_arity, actual_arity));
+fn pseudo_from_parts(opcode_name: &Spanned<String>,
+                     args: &[Spanned<Operand>],
+                     symbols: &SymbolTable)
+                     -> ParseResult<Instruction> {
+    match &opcode_name.node[..] {
+        "nop" => Ok(Instruction::Mov(Register::ZERO, Register::ZERO)),
+
+        // only a value known before layout can pick the short form, so both passes agree
+        "li" => {
+            let r = try!(parse_register(&args[0]));
+            let (min, max) = int_range(HALF_SIZE);
+            match symbols.eval_constant(&args[1]) {
+                Ok(n) if min <= n && n <= max => Ok(Instruction::Movi(r, half_from_int(n))),
+                _ => Ok(Instruction::Movw(r, try!(parse_word(&args[1], symbols)))),
+            }
+        }
+
+        "la" => Ok(Instruction::Mova(try!(parse_register(&args[0])), try!(parse_label(&args[1])))),
+
+        "neg" => {
+            Ok(Instruction::Not(try!(parse_register(&args[0])), try!(parse_register(&args[1]))))
+        }
+
+        "sub" => {
+            Ok(Instruction::Sub(try!(parse_register(&args[0])),
+                                try!(parse_register(&args[1])),
+                                try!(parse_register(&args[2]))))
+        }
+
+        "b" => Ok(Instruction::J0(Register::ZERO, try!(parse_label(&args[0])))),
+
+        "beq" => {
+            Ok(Instruction::Beq(try!(parse_register(&args[0])),
+                                try!(parse_register(&args[1])),
+                                try!(parse_label(&args[2]))))
+        }
+
+        "blt" => {
+            Ok(Instruction::Blt(try!(parse_register(&args[0])),
+                                try!(parse_register(&args[1])),
+                                try!(parse_label(&args[2]))))
+        }
+
+        "bgt" => {
+            Ok(Instruction::Bgt(try!(parse_register(&args[0])),
+                                try!(parse_register(&args[1])),
+                                try!(parse_label(&args[2]))))
+        }
+
+        "inc" => Ok(Instruction::Addi(try!(parse_register(&args[0])), half_from_int(1))),
+
+        "dec" => Ok(Instruction::Addi(try!(parse_register(&args[0])), half_from_int(-1))),
+
+        _ => {
+            Err(ParseError::new(ParseErrorKind::InvalidOpcode(opcode_name.node.clone()),
+                                opcode_name.span))
+        }
+    }
+}
+
+pub fn instruction_from_parts(opcode_name: &Spanned<String>,
+                              args: &[Spanned<Operand>],
+                              symbols: &SymbolTable)
+                              -> ParseResult<Instruction> {
+    if let Some(arity) = pseudo_arity(&opcode_name.node) {
+        try!(check_arity(opcode_name, args, arity));
+        return pseudo_from_parts(opcode_name, args, symbols);
+    }
+
+    if !Opcode::name_is_valid(&opcode_name.node) {
+        return Err(ParseError::new(ParseErrorKind::InvalidOpcode(opcode_name.node.clone()),
+                                   opcode_name.span));
     }
 
+    let opcode = Opcode::from(&opcode_name.node[..]);
+    try!(check_arity(opcode_name, args, opcode.arity()));
+
     match opcode {
         Opcode::Mov => {
-            Ok(Instruction::Mov(try!(parse_register(args[0])), try!(parse_register(args[1]))))
+            Ok(Instruction::Mov(try!(parse_register(&args[0])), try!(parse_register(&args[1]))))
         }
 
         Opcode::Movi => {
-            Ok(Instruction::Movi(try!(parse_register(args[0])), try!(parse_half(args[1]))))
+            Ok(Instruction::Movi(try!(parse_register(&args[0])),
+                                 try!(parse_half(&args[1], symbols))))
         }
 
         Opcode::Movw => {
-            Ok(Instruction::Movw(try!(parse_register(args[0])), try!(parse_word(args[1]))))
+            Ok(Instruction::Movw(try!(parse_register(&args[0])),
+                                 try!(parse_word(&args[1], symbols))))
         }
 
         Opcode::Mova => {
-            Ok(Instruction::Mova(try!(parse_register(args[0])), try!(parse_label(args[1]))))
+            Ok(Instruction::Mova(try!(parse_register(&args[0])), try!(parse_label(&args[1]))))
         }
 
         Opcode::Lt => {
-            Ok(Instruction::Lt(try!(parse_register(args[0])),
-                               try!(parse_register(args[1])),
-                               try!(parse_tryte(args[2]))))
+            Ok(Instruction::Lt(try!(parse_register(&args[0])),
+                               try!(parse_register(&args[1])),
+                               try!(parse_tryte(&args[2], symbols))))
         }
 
         Opcode::Lh => {
-            Ok(Instruction::Lh(try!(parse_register(args[0])),
-                               try!(parse_register(args[1])),
-                               try!(parse_tryte(args[2]))))
+            Ok(Instruction::Lh(try!(parse_register(&args[0])),
+                               try!(parse_register(&args[1])),
+                               try!(parse_tryte(&args[2], symbols))))
         }
 
         Opcode::Lw => {
-            Ok(Instruction::Lw(try!(parse_register(args[0])),
-                               try!(parse_register(args[1])),
-                               try!(parse_tryte(args[2]))))
+            Ok(Instruction::Lw(try!(parse_register(&args[0])),
+                               try!(parse_register(&args[1])),
+                               try!(parse_tryte(&args[2], symbols))))
         }
 
         Opcode::St => {
-            Ok(Instruction::St(try!(parse_register(args[0])),
-                               try!(parse_register(args[1])),
-                               try!(parse_tryte(args[2]))))
+            Ok(Instruction::St(try!(parse_register(&args[0])),
+                               try!(parse_register(&args[1])),
+                               try!(parse_tryte(&args[2], symbols))))
         }
 
         Opcode::Sh => {
-            Ok(Instruction::Sh(try!(parse_register(args[0])),
-                               try!(parse_register(args[1])),
-                               try!(parse_tryte(args[2]))))
+            Ok(Instruction::Sh(try!(parse_register(&args[0])),
+                               try!(parse_register(&args[1])),
+                               try!(parse_tryte(&args[2], symbols))))
         }
 
         Opcode::Sw => {
-            Ok(Instruction::Sw(try!(parse_register(args[0])),
-                               try!(parse_register(args[1])),
-                               try!(parse_tryte(args[2]))))
+            Ok(Instruction::Sw(try!(parse_register(&args[0])),
+                               try!(parse_register(&args[1])),
+                               try!(parse_tryte(&args[2], symbols))))
         }
 
         Opcode::Add => {
-            Ok(Instruction::Add(try!(parse_register(args[0])),
-                                try!(parse_register(args[1])),
-                                try!(parse_register(args[2]))))
+            Ok(Instruction::Add(try!(parse_register(&args[0])),
+                                try!(parse_register(&args[1])),
+                                try!(parse_register(&args[2]))))
         }
 
         Opcode::Addi => {
-            Ok(Instruction::Addi(try!(parse_register(args[0])), try!(parse_half(args[1]))))
+            Ok(Instruction::Addi(try!(parse_register(&args[0])),
+                                 try!(parse_half(&args[1], symbols))))
         }
 
         Opcode::Mul => {
-            Ok(Instruction::Mul(try!(parse_register(args[0])), try!(parse_register(args[1]))))
+            Ok(Instruction::Mul(try!(parse_register(&args[0])), try!(parse_register(&args[1]))))
         }
 
         Opcode::Muli => {
-            Ok(Instruction::Muli(try!(parse_register(args[0])), try!(parse_half(args[1]))))
+            Ok(Instruction::Muli(try!(parse_register(&args[0])),
+                                 try!(parse_half(&args[1], symbols))))
         }
 
         Opcode::Not => {
-            Ok(Instruction::Not(try!(parse_register(args[0])), try!(parse_register(args[1]))))
+            Ok(Instruction::Not(try!(parse_register(&args[0])), try!(parse_register(&args[1]))))
         }
 
         Opcode::And => {
-            Ok(Instruction::And(try!(parse_register(args[0])),
-                                try!(parse_register(args[1])),
-                                try!(parse_register(args[2]))))
+            Ok(Instruction::And(try!(parse_register(&args[0])),
+                                try!(parse_register(&args[1])),
+                                try!(parse_register(&args[2]))))
         }
 
         Opcode::Andi => {
-            Ok(Instruction::Andi(try!(parse_register(args[0])), try!(parse_half(args[1]))))
+            Ok(Instruction::Andi(try!(parse_register(&args[0])),
+                                 try!(parse_half(&args[1], symbols))))
         }
 
         Opcode::Or => {
-            Ok(Instruction::Or(try!(parse_register(args[0])),
-                               try!(parse_register(args[1])),
-                               try!(parse_register(args[2]))))
+            Ok(Instruction::Or(try!(parse_register(&args[0])),
+                               try!(parse_register(&args[1])),
+                               try!(parse_register(&args[2]))))
         }
 
         Opcode::Ori => {
-            Ok(Instruction::Ori(try!(parse_register(args[0])), try!(parse_half(args[1]))))
+            Ok(Instruction::Ori(try!(parse_register(&args[0])),
+                                try!(parse_half(&args[1], symbols))))
         }
 
         Opcode::Shf => {
-            Ok(Instruction::Shf(try!(parse_register(args[0])),
-                                try!(parse_register(args[1])),
-                                try!(parse_register(args[2]))))
+            Ok(Instruction::Shf(try!(parse_register(&args[0])),
+                                try!(parse_register(&args[1])),
+                                try!(parse_register(&args[2]))))
         }
 
         Opcode::Shfi => {
-            Ok(Instruction::Shfi(try!(parse_register(args[0])), try!(parse_half(args[1]))))
+            Ok(Instruction::Shfi(try!(parse_register(&args[0])),
+                                 try!(parse_half(&args[1], symbols))))
         }
 
         Opcode::Cmp => {
-            Ok(Instruction::Cmp(try!(parse_register(args[0])),
-                                try!(parse_register(args[1])),
-                                try!(parse_register(args[2]))))
+            Ok(Instruction::Cmp(try!(parse_register(&args[0])),
+                                try!(parse_register(&args[1])),
+                                try!(parse_register(&args[2]))))
         }
 
-        Opcode::Jmp => Ok(Instruction::Jmp(try!(parse_label(args[0])))),
+        Opcode::Jmp => Ok(Instruction::Jmp(try!(parse_label(&args[0])))),
 
         Opcode::JT => {
-            Ok(Instruction::JT(try!(parse_register(args[0])), try!(parse_label(args[1]))))
+            Ok(Instruction::JT(try!(parse_register(&args[0])), try!(parse_label(&args[1]))))
         }
 
         Opcode::J0 => {
-            Ok(Instruction::J0(try!(parse_register(args[0])), try!(parse_label(args[1]))))
+            Ok(Instruction::J0(try!(parse_register(&args[0])), try!(parse_label(&args[1]))))
         }
 
         Opcode::J1 => {
-            Ok(Instruction::J1(try!(parse_register(args[0])), try!(parse_label(args[1]))))
+            Ok(Instruction::J1(try!(parse_register(&args[0])), try!(parse_label(&args[1]))))
         }
 
         Opcode::JT0 => {
-            Ok(Instruction::JT0(try!(parse_register(args[0])), try!(parse_label(args[1]))))
+            Ok(Instruction::JT0(try!(parse_register(&args[0])), try!(parse_label(&args[1]))))
         }
 
         Opcode::JT1 => {
-            Ok(Instruction::JT1(try!(parse_register(args[0])), try!(parse_label(args[1]))))
+            Ok(Instruction::JT1(try!(parse_register(&args[0])), try!(parse_label(&args[1]))))
         }
 
         Opcode::J01 => {
-            Ok(Instruction::J01(try!(parse_register(args[0])), try!(parse_label(args[1]))))
+            Ok(Instruction::J01(try!(parse_register(&args[0])), try!(parse_label(&args[1]))))
         }
 
-        Opcode::Call => Ok(Instruction::Call(try!(parse_label(args[0])))),
+        Opcode::Call => Ok(Instruction::Call(try!(parse_label(&args[0])))),
 
         Opcode::Ret => Ok(Instruction::Ret),
 
@@ -0,0 +1,110 @@
+use std::fmt;
+
+use program::parser::ParseError;
+use program::encode::{EncodeError, EncodeWarning};
+
+#[derive(Debug)]
+pub enum AssemblyError {
+    Parse(ParseError),
+    Encode(EncodeError),
+}
+
+impl AssemblyError {
+    pub fn position(&self) -> Option<(Option<String>, usize, usize)> {
+        match *self {
+            AssemblyError::Parse(ref e) => e.position(),
+            AssemblyError::Encode(ref e) => {
+                e.location().map(|location| {
+                    (location.path.clone(), location.span.line, location.span.column)
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AssemblyError::Parse(ref e) => write!(f, "error: {}", e),
+            AssemblyError::Encode(ref e) => write!(f, "error: {}", e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ErrorReport {
+    pub errors: Vec<AssemblyError>,
+    pub warnings: Vec<EncodeWarning>,
+}
+
+impl ErrorReport {
+    pub fn new() -> ErrorReport {
+        ErrorReport {
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty() && self.warnings.is_empty()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn add_parse_errors(&mut self, errors: Vec<ParseError>) {
+        self.errors.extend(errors.into_iter().map(AssemblyError::Parse));
+        self.sort_errors();
+    }
+
+    pub fn add_encode_errors(&mut self, errors: Vec<EncodeError>) {
+        self.errors.extend(errors.into_iter().map(AssemblyError::Encode));
+        self.sort_errors();
+    }
+
+    // errors come in the order they're found, which is by phase rather than by where they are.
+    // the ones without a location go last
+    fn sort_errors(&mut self) {
+        self.errors.sort_by_key(|error| {
+            let position = error.position();
+            (position.is_none(), position)
+        });
+    }
+
+    pub fn add_encode_warnings(&mut self, warnings: Vec<EncodeWarning>) {
+        self.warnings.extend(warnings);
+    }
+}
+
+impl fmt::Display for ErrorReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for error in &self.errors {
+            try!(write!(f, "{}\n\n", error));
+        }
+
+        for warning in &self.warnings {
+            try!(write!(f, "warning: {}\n\n", warning));
+        }
+
+        let mut counts = Vec::new();
+        if !self.errors.is_empty() || self.warnings.is_empty() {
+            counts.push(count(self.errors.len(), "error"));
+        }
+
+        if !self.warnings.is_empty() {
+            counts.push(count(self.warnings.len(), "warning"));
+        }
+
+        f.write_str(&counts.join(", "))
+    }
+}
+
+fn count(n: usize, noun: &str) -> String {
+    let plural = if n == 1 { "" } else { "s" };
+    format!("{} {}{}", n, noun, plural)
+}
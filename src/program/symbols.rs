@@ -0,0 +1,260 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::mem::replace;
+
+use types::*;
+use program::ast::*;
+use program::decode::Section;
+use program::lexer::Span;
+use program::location::Location;
+use program::parser::{ParseError, ParseErrorKind, ParseResult};
+
+pub struct SymbolTable {
+    constants: HashMap<String, (Spanned<Operand>, Location)>,
+    labels: HashMap<String, (Addr, Section)>,
+    sizes: HashMap<String, usize>,
+    evaluating: RefCell<Vec<String>>,
+    referenced: RefCell<HashSet<String>>,
+    out_of_range: RefCell<Vec<(String, isize, isize, Span)>>,
+    pub layout: bool,
+    pub relocatable: bool,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable {
+            constants: HashMap::new(),
+            labels: HashMap::new(),
+            sizes: HashMap::new(),
+            evaluating: RefCell::new(Vec::new()),
+            referenced: RefCell::new(HashSet::new()),
+            out_of_range: RefCell::new(Vec::new()),
+            layout: false,
+            relocatable: false,
+        }
+    }
+
+    pub fn insert_constant(&mut self,
+                           name: &Spanned<String>,
+                           value: &Spanned<Operand>,
+                           location: &Location)
+                           -> ParseResult<()> {
+        if self.constants.contains_key(&name.node) {
+            return Err(ParseError::new(ParseErrorKind::DuplicateConstant(name.node.clone()),
+                                       name.span));
+        }
+
+        self.constants.insert(name.node.clone(), (value.clone(), location.clone()));
+        Ok(())
+    }
+
+    pub fn insert_label(&mut self, label: &String, addr: Addr, section: Section) {
+        self.labels.entry(label.clone()).or_insert((addr, section));
+    }
+
+    pub fn add_size(&mut self, label: &String, size: usize) {
+        *self.sizes.entry(label.clone()).or_insert(0) += size;
+    }
+
+    pub fn referenced_labels(&self) -> HashSet<String> {
+        self.referenced.borrow().clone()
+    }
+
+    // values that don't fit are an encoding error rather than a parse error, so they're kept
+    // here for the caller to report instead of failing the statement
+    pub fn add_out_of_range(&self, value: String, min: isize, max: isize, span: Span) {
+        if !self.layout {
+            self.out_of_range.borrow_mut().push((value, min, max, span));
+        }
+    }
+
+    pub fn take_out_of_range(&self) -> Vec<(String, isize, isize, Span)> {
+        replace(&mut *self.out_of_range.borrow_mut(), Vec::new())
+    }
+
+    // while laying out the program only the shape of data matters, so values are left at zero
+    pub fn eval(&self, arg: &Spanned<Operand>) -> ParseResult<isize> {
+        if self.layout {
+            Ok(0)
+        } else {
+            self.eval_operand(arg, true).and_then(Value::absolute)
+        }
+    }
+
+    // anything that changes the layout must not depend on it
+    pub fn eval_constant(&self, arg: &Spanned<Operand>) -> ParseResult<isize> {
+        self.eval_operand(arg, false).and_then(Value::absolute)
+    }
+
+    fn eval_operand(&self, arg: &Spanned<Operand>, allow_addrs: bool) -> ParseResult<Value> {
+        match arg.node {
+            Operand::Decimal(n) => Ok(Value::new(n)),
+
+            Operand::Oversized(_) => Err(ParseError::new(ParseErrorKind::IntOverflow, arg.span)),
+
+            Operand::Ternary(ref trit_str) => {
+                ternary_value(trit_str)
+                    .map(Value::new)
+                    .ok_or_else(|| ParseError::new(ParseErrorKind::IntOverflow, arg.span))
+            }
+
+            Operand::Label(ref name) => self.eval_symbol(name, arg.span, allow_addrs),
+
+            Operand::Expr(ref expr) => {
+                match **expr {
+                    Expr::Neg(ref operand) => {
+                        let value = try!(self.eval_operand(operand, allow_addrs));
+                        Ok(Value::new(-try!(value.absolute())))
+                    }
+
+                    Expr::Binary(op, ref lhs, ref rhs) => {
+                        let lhs = try!(self.eval_operand(lhs, allow_addrs));
+                        let rhs = try!(self.eval_operand(rhs, allow_addrs));
+                        let (n, base) = match op {
+                            BinOp::Add => (lhs.n.checked_add(rhs.n), try!(lhs.add_base(rhs))),
+                            BinOp::Sub => (lhs.n.checked_sub(rhs.n), try!(lhs.sub_base(rhs))),
+                            BinOp::Mul => {
+                                let (lhs, rhs) = (try!(lhs.absolute()), try!(rhs.absolute()));
+                                (lhs.checked_mul(rhs), None)
+                            }
+                        };
+
+                        match n {
+                            Some(n) => Ok(Value { n: n, base: base }),
+                            None => Err(ParseError::new(ParseErrorKind::IntOverflow, arg.span)),
+                        }
+                    }
+
+                    Expr::Sizeof(ref label) => {
+                        if !allow_addrs {
+                            let name = format!("sizeof {}", label.node);
+                            return Err(ParseError::new(ParseErrorKind::NotConstant(name),
+                                                       arg.span));
+                        }
+
+                        self.referenced.borrow_mut().insert(label.node.clone());
+                        match self.sizes.get(&label.node) {
+                            Some(&size) => Ok(Value::new(size as isize)),
+                            None => {
+                                let kind = ParseErrorKind::UndefinedSymbol(label.node.clone());
+                                Err(ParseError::new(kind, label.span))
+                            }
+                        }
+                    }
+                }
+            }
+
+            _ => {
+                let kind = ParseErrorKind::InvalidOperand("integer", arg.node.description());
+                Err(ParseError::new(kind, arg.span))
+            }
+        }
+    }
+
+    fn eval_symbol(&self, name: &String, span: Span, allow_addrs: bool) -> ParseResult<Value> {
+        if let Some(&(ref value, ref location)) = self.constants.get(name) {
+            if self.evaluating.borrow().contains(name) {
+                return Err(ParseError::new(ParseErrorKind::RecursiveConstant(name.clone()),
+                                           span));
+            }
+
+            self.evaluating.borrow_mut().push(name.clone());
+            let result = self.eval_operand(value, allow_addrs);
+            self.evaluating.borrow_mut().pop();
+
+            // errors inside the definition point at the definition
+            return result.map_err(|e| e.with_location(location));
+        }
+
+        self.referenced.borrow_mut().insert(name.clone());
+        match self.labels.get(name) {
+            Some(&(addr, section)) if allow_addrs && self.relocatable => {
+                Ok(Value {
+                    n: addr as isize,
+                    base: Some(Base {
+                        section: section,
+                        label: name.clone(),
+                        span: span,
+                    }),
+                })
+            }
+            Some(&(addr, _)) if allow_addrs => Ok(Value::new(addr as isize)),
+            Some(_) => Err(ParseError::new(ParseErrorKind::NotConstant(name.clone()), span)),
+            None => Err(ParseError::new(ParseErrorKind::UndefinedSymbol(name.clone()), span)),
+        }
+    }
+}
+
+// in relocatable mode a label's address only holds within its own section, so a value that
+// depends on one keeps track of which; the difference of two labels in the same section doesn't
+// change at link time, and is an ordinary number again
+struct Value {
+    n: isize,
+    base: Option<Base>,
+}
+
+struct Base {
+    section: Section,
+    label: String,
+    span: Span,
+}
+
+impl Value {
+    fn new(n: isize) -> Value {
+        Value { n: n, base: None }
+    }
+
+    fn absolute(self) -> ParseResult<isize> {
+        match self.base {
+            Some(base) => Err(base.not_relocatable()),
+            None => Ok(self.n),
+        }
+    }
+
+    fn add_base(self, rhs: Value) -> ParseResult<Option<Base>> {
+        match (self.base, rhs.base) {
+            (Some(base), Some(_)) => Err(base.not_relocatable()),
+            (base, None) | (None, base) => Ok(base),
+        }
+    }
+
+    fn sub_base(self, rhs: Value) -> ParseResult<Option<Base>> {
+        match (self.base, rhs.base) {
+            (Some(ref lhs), Some(ref rhs)) if lhs.section == rhs.section => Ok(None),
+            (Some(base), Some(_)) | (None, Some(base)) => Err(base.not_relocatable()),
+            (base, None) => Ok(base),
+        }
+    }
+}
+
+impl Base {
+    fn not_relocatable(self) -> ParseError {
+        ParseError::new(ParseErrorKind::NotRelocatable(self.label), self.span)
+    }
+}
+
+// numeric labels are numbered apart from each other, as `1~0`, `1~1` and so on
+pub fn numeric_label(digits: &str, n: usize) -> String {
+    format!("{}~{}", digits, n)
+}
+
+pub fn is_numeric_label(label: &str) -> bool {
+    label.contains('~')
+}
+
+// the label as it was written, without the numbering the source doesn't show
+pub fn display_label(label: &str) -> &str {
+    label.split('~').next().unwrap_or(label)
+}
+
+fn ternary_value(trit_str: &str) -> Option<isize> {
+    trit_str.chars().fold(Some(0), |n, c| {
+        let trit = match c {
+            'T' => -1,
+            '1' => 1,
+            _ => 0,
+        };
+
+        n.and_then(|n| n.checked_mul(3)).and_then(|n| n.checked_add(trit))
+    })
+}
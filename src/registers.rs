@@ -96,6 +96,29 @@ impl Register {
         }
     }
 
+    pub fn role(self) -> &'static str {
+        match self {
+            Register::ZERO => "always zero, anything written to it is discarded",
+            Register::RA => "return address, set by `call` and used by `ret`",
+            Register::LO => "low word of `mul` and `shf` results",
+            Register::HI => {
+                "high word of `mul` and `shf` results, carry of `add`, scratch for branches"
+            }
+            Register::SP => "stack pointer, kept across calls",
+            Register::FP => "frame pointer, kept across calls",
+            Register::A0 => "first argument and result, and the syscall argument",
+            Register::A1 | Register::A2 | Register::A3 | Register::A4 | Register::A5 => {
+                "argument, may be changed by calls"
+            }
+            Register::T0 => "temporary, may be changed by calls, and the syscall index",
+            Register::T1 | Register::T2 | Register::T3 | Register::T4 | Register::T5 => {
+                "temporary, may be changed by calls"
+            }
+            Register::S0 | Register::S1 | Register::S2 | Register::S3 | Register::S4 |
+            Register::S5 => "saved, kept across calls",
+        }
+    }
+
     pub fn index_name(self) -> &'static str {
         match self {
             Register::ZERO => "$0",
@@ -1,41 +1,78 @@
 use rand;
-use std::mem::transmute;
-use std::process;
+use std::char;
+use std::io;
+use std::io::prelude::*;
+use std::str;
 
 use types::*;
 use ternary;
 use registers::Register;
-use vm::VM;
+use vm::{VM, VMError};
 use text;
 use util;
 
-#[derive(Debug)]
+// runs a syscall against the VM, reading its arguments from and writing its results to registers
+pub trait SyscallHandler {
+    fn call(&mut self, vm: &mut VM);
+}
+
+impl<F: FnMut(&mut VM)> SyscallHandler for F {
+    fn call(&mut self, vm: &mut VM) {
+        self(vm)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Syscall {
     PrintString = 0,
     PrintDecimal = 1,
     PrintTernary = 2,
     GetRand = 3,
     Exit = 4,
+    PutChar = 5,
+    ReadLine = 6,
+    ReadChar = 7,
+    ReadInt = 8,
 }
 
+pub const DEFAULT_SYSCALLS: [Syscall; 9] = [Syscall::PrintString,
+                                            Syscall::PrintDecimal,
+                                            Syscall::PrintTernary,
+                                            Syscall::GetRand,
+                                            Syscall::Exit,
+                                            Syscall::PutChar,
+                                            Syscall::ReadLine,
+                                            Syscall::ReadChar,
+                                            Syscall::ReadInt];
+
 impl Syscall {
-    pub unsafe fn perform(self, vm: &mut VM) {
-        match self {
+    pub fn number(self) -> isize {
+        self as isize
+    }
+
+    pub fn from_number(n: isize) -> Option<Syscall> {
+        DEFAULT_SYSCALLS.iter().cloned().find(|syscall| syscall.number() == n)
+    }
+}
+
+impl SyscallHandler for Syscall {
+    fn call(&mut self, vm: &mut VM) {
+        match *self {
             Syscall::PrintString => {
                 let addr = vm.read(Register::A0);
-                let local_memory = vm.memory.offset(addr);
-                let (s, _) = text::decode_str(local_memory);
-                print!("{}", s);
+                let (s, _) = unsafe { text::decode_str(vm.memory.offset(addr)) };
+                write_output(vm, &s);
             }
 
             Syscall::PrintDecimal => {
                 let n = vm.read(Register::A0);
-                print!("{}", n);
+                write_output(vm, &n.to_string());
             }
 
             Syscall::PrintTernary => {
                 let src = vm.src(Register::A0);
-                print!("{}", ternary::to_str(src, WORD_ISIZE));
+                let s = unsafe { ternary::to_str(src, WORD_ISIZE) };
+                write_output(vm, &s);
             }
 
             Syscall::GetRand => {
@@ -45,15 +82,185 @@ impl Syscall {
             }
 
             Syscall::Exit => {
-                let code = vm.read(Register::A0) as i32;
-                process::exit(code);
+                let status = vm.read(Register::A0) as i32;
+                vm.halt(status);
+            }
+
+            Syscall::PutChar => {
+                let codepoint = vm.read(Register::A0) as u32;
+                let c = char::from_u32(codepoint).unwrap_or(char::REPLACEMENT_CHARACTER);
+                write_output(vm, c.encode_utf8(&mut [0; 4]));
+            }
+
+            // lines that don't fit in the buffer are cut short at a character boundary, and the
+            // rest of the line is dropped
+            Syscall::ReadLine => {
+                let addr = vm.read(Register::A0);
+                let size = vm.read(Register::A1);
+                if addr < 0 || size < WORD_ISIZE || addr + size > vm.memory_size as isize {
+                    vm.fault(VMError::InvalidBuffer(addr, size));
+                    return;
+                }
+
+                let line = match read_line(vm.input()) {
+                    Ok(line) => line,
+                    Err(e) => return fault_io(vm, e),
+                };
+
+                match line {
+                    Some(line) => {
+                        let (trits, len, truncated) = encode_line(&line, size as usize);
+                        vm.store_trits(addr as Addr, &trits);
+                        vm.write(Register::A0, len as isize);
+                        vm.write(Register::A1, truncated as isize);
+                    }
+
+                    None => {
+                        vm.write(Register::A0, -1);
+                        vm.write(Register::A1, 0);
+                    }
+                }
+            }
+
+            Syscall::ReadChar => {
+                match read_char(vm.input()) {
+                    Ok(c) => vm.write(Register::A0, c.map_or(-1, |c| c as isize)),
+                    Err(e) => fault_io(vm, e),
+                }
             }
+
+            Syscall::ReadInt => {
+                let line = match read_line(vm.input()) {
+                    Ok(line) => line,
+                    Err(e) => return fault_io(vm, e),
+                };
+
+                let (n, status) = match line {
+                    Some(line) => parse_int(line.trim()).map_or((0, 0), |n| (n, 1)),
+                    None => (0, -1),
+                };
+
+                vm.write(Register::A0, n);
+                vm.write(Register::A1, status);
+            }
+        }
+    }
+}
+
+fn write_output(vm: &mut VM, s: &str) {
+    if let Err(e) = vm.output().write_all(s.as_bytes()) {
+        fault_io(vm, e);
+    }
+}
+
+fn fault_io(vm: &mut VM, error: io::Error) {
+    vm.fault(VMError::Io(error.to_string()));
+}
+
+// a line without its line ending, or nothing at the end of input
+fn read_line(input: &mut dyn BufRead) -> io::Result<Option<String>> {
+    let mut bytes = Vec::new();
+    if try!(input.read_until(b'\n', &mut bytes)) == 0 {
+        return Ok(None);
+    }
+
+    if bytes.last() == Some(&b'\n') {
+        bytes.pop();
+        if bytes.last() == Some(&b'\r') {
+            bytes.pop();
+        }
+    }
+
+    Ok(Some(String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+// invalid UTF-8 reads as U+FFFD, like it does in lines. only bytes that continue the character
+// are taken, so whatever breaks off a malformed sequence is read as the next character
+fn read_char(input: &mut dyn BufRead) -> io::Result<Option<char>> {
+    let mut bytes = [0; 4];
+    bytes[0] = match try!(next_byte(input, |_| true)) {
+        Some(byte) => byte,
+        None => return Ok(None),
+    };
+
+    let len = match bytes[0] {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    };
+
+    for i in 1..len {
+        match try!(next_byte(input, |byte| byte & 0xc0 == 0x80)) {
+            Some(byte) => bytes[i] = byte,
+            None => return Ok(Some(char::REPLACEMENT_CHARACTER)),
         }
     }
+
+    let c = str::from_utf8(&bytes[..len]).ok().and_then(|s| s.chars().next());
+    Ok(Some(c.unwrap_or(char::REPLACEMENT_CHARACTER)))
 }
 
-impl From<isize> for Syscall {
-    fn from(i: isize) -> Syscall {
-        unsafe { transmute(i as u8) }
+// the next byte if there is one and `accept` takes it, leaving it unread otherwise
+fn next_byte<F>(input: &mut dyn BufRead, accept: F) -> io::Result<Option<u8>>
+    where F: Fn(u8) -> bool
+{
+    let byte = match try!(input.fill_buf()).first() {
+        Some(&byte) if accept(byte) => byte,
+        _ => return Ok(None),
+    };
+
+    input.consume(1);
+    Ok(Some(byte))
+}
+
+// the longest start of `line` that fits in `size` trits as a string, its length in trytes and
+// whether anything was left out
+fn encode_line(line: &str, size: usize) -> (Vec<Trit>, usize, bool) {
+    let mut word = EMPTY_WORD;
+    let mut len = 0;
+    let mut end = 0;
+
+    for (i, c) in line.char_indices() {
+        let char_len = unsafe { text::encode_char(word.as_mut_ptr(), c) };
+        if WORD_SIZE + (len + char_len) * TRYTE_SIZE > size {
+            break;
+        }
+
+        len += char_len;
+        end = i + c.len_utf8();
     }
+
+    let mut trits = vec![Trit::Zero; WORD_SIZE + len * TRYTE_SIZE];
+    text::encode_str(trits.as_mut_ptr(), &line[..end]);
+    (trits, len, end < line.len())
+}
+
+// decimal, or balanced ternary with the same `0t` prefix the assembler uses, either one negated
+// by a leading `-`
+fn parse_int(s: &str) -> Option<isize> {
+    let (negative, digits) = if s.starts_with('-') {
+        (true, &s[1..])
+    } else {
+        (false, s)
+    };
+
+    let n = if digits.starts_with("0t") && digits.len() > 2 {
+        digits[2..].chars().fold(Some(0), |n, c| {
+            let trit = match c {
+                'T' => -1,
+                '0' => 0,
+                '1' => 1,
+                _ => return None,
+            };
+
+            n.and_then(|n: isize| n.checked_mul(3)).and_then(|n| n.checked_add(trit))
+        })
+    } else if !digits.is_empty() && digits.chars().all(|c| c.is_digit(10)) {
+        digits.parse().ok()
+    } else {
+        None
+    };
+
+    n.map(|n| if negative { -n } else { n }).filter(|&n| n >= WORD_MIN && n <= WORD_MAX)
 }
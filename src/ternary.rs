@@ -180,7 +180,7 @@ pub unsafe fn multiply(dest: *mut Trit, lhs: *const Trit, rhs: *const Trit, len:
 }
 
 pub unsafe fn compare(lhs: *const Trit, rhs: *const Trit, len: isize) -> Trit {
-    for i in (0..len - 1).rev() {
+    for i in (0..len).rev() {
         let lt = *lhs.offset(i);
         let rt = *rhs.offset(i);
         if lt != rt {
@@ -192,7 +192,7 @@ pub unsafe fn compare(lhs: *const Trit, rhs: *const Trit, len: isize) -> Trit {
 }
 
 pub unsafe fn lowest_trit(trits: *const Trit, len: isize) -> Trit {
-    for i in 0..len - 1 {
+    for i in 0..len {
         let trit = *trits.offset(i);
         if trit != Trit::Zero {
             return trit;
@@ -0,0 +1,229 @@
+use std::env;
+use std::fs;
+use std::process;
+
+use opcodes::Opcode;
+use program::{DecodedProgram, EncodedProgram};
+use program::decode::Section;
+use program::encode::{EncodeError, EncodeWarning};
+use program::instructions::Instruction;
+use program::lines::SourcePosition;
+use program::listing::SymbolMap;
+use program::report::AssemblyError;
+use registers::Register;
+use types::*;
+use util::{assemble, vm_from_code};
+use vm::VM;
+
+fn encode_errors(code: &str) -> Vec<EncodeError> {
+    match vm_from_code(code) {
+        Ok(_) => panic!("expected errors"),
+        Err(report) => {
+            report.errors
+                .into_iter()
+                .map(|error| match error {
+                    AssemblyError::Encode(e) => e,
+                    AssemblyError::Parse(e) => panic!("unexpected parse error: {}", e),
+                })
+                .collect()
+        }
+    }
+}
+
+#[test]
+fn encode_undefined_label() {
+    let code = "__start:\n    jmp foo\n    beq $t0, $t1, foo\n    halt\n";
+    let errors = encode_errors(code);
+    assert_eq!(errors.len(), 2);
+
+    let lines: Vec<usize> = errors.iter()
+        .map(|e| match *e {
+            EncodeError::UndefinedLabel(ref label, ref location) => {
+                assert_eq!(label, "foo");
+                location.span.line
+            }
+            _ => panic!("unexpected error: {}", e),
+        })
+        .collect();
+    assert_eq!(lines, vec![2, 3]);
+
+    assert_eq!(errors[0].to_string(),
+               "<input>:2:9: undefined label `foo`\n    jmp foo\n        ^^^");
+    assert_eq!(errors[1].location().unwrap().span.column, 19);
+}
+
+#[test]
+fn encode_duplicate_label() {
+    let code = "__start:\n    jmp __start\nloop:\nloop:\n    jmp loop\n";
+    let errors = encode_errors(code);
+    assert_eq!(errors.len(), 1);
+
+    match errors[0] {
+        EncodeError::DuplicateLabel(ref label, ref location, ref previous) => {
+            assert_eq!(label, "loop");
+            assert_eq!(location.span.line, 4);
+            assert_eq!(previous.span.line, 3);
+        }
+        _ => panic!("unexpected error: {}", errors[0]),
+    }
+}
+
+#[test]
+fn encode_unused_label_warning() {
+    let code = ".data\nunused_data:\n    %word 0\n.code\n__start:\nunused:\n    halt\n";
+    let mut program = DecodedProgram::new();
+    let result = program.read_str(code);
+    let (_, report) = assemble(program, result);
+
+    assert!(!report.has_errors());
+
+    let labels: Vec<&str> = report.warnings
+        .iter()
+        .map(|w| match *w {
+            EncodeWarning::UnusedLabel(ref label, _) => &label[..],
+        })
+        .collect();
+    assert_eq!(labels, vec!["unused_data", "unused"]);
+    assert!(report.to_string().ends_with("2 warnings"));
+}
+
+#[test]
+fn encode_listing_and_symbol_map() {
+    let code = ".data\nvalue: %word 5\n.rodata\nmsg:\n    %tryte 1\n.code\n__start:  halt\n\
+                .bss\nbuf:\n    %space 6\n";
+
+    let mut program = DecodedProgram::new();
+    program.read_str(code).unwrap();
+
+    let vm = VM::new(program.size());
+    let mut encoder = EncodedProgram::new(vm.memory, vm.memory_size);
+    encoder.encode(program).unwrap();
+
+    let listing = encoder.listing().to_string();
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines[0], ".data");
+    assert_eq!(lines[1], "; <input>");
+    assert_eq!(lines[2], "      48  0001TT 000000 000000 000000  value: %word 5");
+    assert_eq!(lines[4], "      72                               msg:");
+    assert_eq!(lines[5], "      72  000001                           %tryte 1");
+    assert_eq!(lines[7], "      96  0011T0 000000 000000 000000  __start:  halt");
+
+    let symbol_map = encoder.symbol_map();
+    let text = symbol_map.to_string();
+    assert_eq!(text, "48 data value\n72 rodata msg\n96 code __start\n120 bss buf\n");
+
+    let parsed = SymbolMap::parse(&text).unwrap();
+    assert_eq!(parsed.symbols, symbol_map.symbols);
+    assert_eq!(parsed.get("msg").map(|symbol| symbol.section), Some(Section::ReadOnlyData));
+    assert_eq!(parsed.symbol_at(100).map(|symbol| &symbol.name[..]), Some("__start"));
+    assert!(parsed.symbol_at(47).is_none());
+    assert_eq!(SymbolMap::parse("48 data value\n72 heap x\n").unwrap_err().line, 2);
+}
+
+#[test]
+fn encode_fields_stay_in_instruction() {
+    // registers in the last trytes of the last instruction used to be written a word wide,
+    // spilling past the end of the program
+    let code = "__start:\n    add $t0, $t1, $t2\n";
+
+    let mut program = DecodedProgram::new();
+    program.read_str(code).unwrap();
+
+    let size = program.size();
+    let mut memory = vec![Trit::Pos; size + WORD_SIZE];
+    let mut encoder = EncodedProgram::new(memory.as_mut_ptr(), size);
+    encoder.encode(program).unwrap();
+
+    assert!(memory[size..].iter().all(|&trit| trit == Trit::Pos));
+}
+
+#[test]
+fn encode_pseudo_opcodes() {
+    let (a, b) = (Register::A0, Register::A1);
+    let pseudos = vec![Instruction::Sub(a, a, a),
+                       Instruction::Sub(a, a, b),
+                       Instruction::Sub(b, a, b),
+                       Instruction::Beq(a, b, "x".to_string()),
+                       Instruction::Blt(a, b, "x".to_string()),
+                       Instruction::Bgt(a, b, "x".to_string())];
+
+    for pseudo in pseudos {
+        let first: Opcode = pseudo.expand().unwrap().remove(0).into();
+        let opcode: Opcode = pseudo.clone().into();
+        assert!(opcode == first, "{:?}", pseudo);
+    }
+}
+
+#[test]
+fn encode_branch_out_of_range() {
+    let far = |nops| {
+        let mut code = String::from("__start:\n    j0 $a0, 1f\n    j1 $a0, .far\n");
+        for _ in 0..nops {
+            code.push_str("    nop\n");
+        }
+        code.push_str("1:\n.far:\n    halt\n");
+        code
+    };
+
+    assert!(vm_from_code(&far(11_000)).is_ok());
+
+    let errors = encode_errors(&far(11_100));
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].to_string(),
+               "<input>:2:13: branch to `1` is 266424 trits away, out of range (-265720 to \
+                265720)\n    j0 $a0, 1f\n            ^^");
+    match errors[1] {
+        EncodeError::BranchOutOfRange(ref label, offset, ref location) => {
+            assert_eq!(label, "__start.far");
+            assert_eq!(offset, 266400);
+            assert_eq!(location.span.line, 3);
+        }
+        ref e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn encode_line_table() {
+    let dir = env::temp_dir().join(format!("tern-lines-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("twice.tasm"),
+              ".macro twice\n    addi $a0, 1\n    addi $a0, 1\n.endm\n")
+        .unwrap();
+    fs::write(dir.join("main.tasm"),
+              ".include twice.tasm\n__start:\n    twice\n    movw $a1, 5\n    halt\n")
+        .unwrap();
+
+    let main = dir.join("main.tasm").to_str().unwrap().to_string();
+    let mut program = DecodedProgram::new();
+    program.read_file(&main).unwrap();
+
+    let vm = VM::new(program.size());
+    let mut encoder = EncodedProgram::new(vm.memory, vm.memory_size);
+    encoder.encode(program).unwrap();
+    let table = encoder.line_table();
+    fs::remove_dir_all(dir).unwrap();
+
+    let position = |path: &str, line, column| {
+        SourcePosition {
+            file: table.files.iter().position(|file| file.ends_with(path)).unwrap(),
+            line: line,
+            column: column,
+        }
+    };
+
+    let entries: Vec<(usize, usize, SourcePosition, Vec<SourcePosition>)> = table.entries
+        .iter()
+        .map(|entry| (entry.addr, entry.size, entry.position, entry.expansions.clone()))
+        .collect();
+    assert_eq!(entries,
+               vec![(48, 24, position("twice.tasm", 2, 5), vec![position("main.tasm", 3, 5)]),
+                    (72, 24, position("twice.tasm", 3, 5), vec![position("main.tasm", 3, 5)]),
+                    (96, 48, position("main.tasm", 4, 5), vec![]),
+                    (144, 24, position("main.tasm", 5, 5), vec![])]);
+
+    assert_eq!(table.lookup(120).map(|entry| entry.addr), Some(96));
+    assert_eq!(table.lookup(143).map(|entry| entry.position.line), Some(4));
+    assert!(table.lookup(47).is_none());
+    assert!(table.lookup(168).is_none());
+    assert_eq!(table.describe(&entries[3].2), format!("{}:5:5", main));
+}
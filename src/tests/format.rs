@@ -0,0 +1,114 @@
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::Path;
+
+use program::format::format_source;
+use util::encode_source;
+
+#[test]
+fn format_layout() {
+    let source = r#"
+; counts down
+
+.data
+count:   %word 3 ; initial value
+.code
+
+
+__start:    la $s0,count
+  lw $a0,$s0,0 ;load
+    call  countdown
+ halt
+; loops until zero
+countdown:
+1: beq $a0,$zero,2f
+  addi $6 , -1      ; one less
+ b 1b
+2:ret
+"#;
+
+    let expected = r#"; counts down
+
+.data
+count:
+    %word 3 ; initial value
+.code
+
+__start:
+    la   $s0, count
+    lw   $a0, $s0, 0 ;load
+    call countdown
+    halt
+
+; loops until zero
+countdown:
+1:  beq  $a0, $zero, 2f
+    addi $a0, -1 ; one less
+    b    1b
+2:  ret
+"#;
+
+    assert_eq!(format_source(source), expected);
+    assert_eq!(format_source(expected), expected);
+}
+
+#[test]
+fn format_operands() {
+    let source = ".equ  SIZE   2*( 1+1 )\n.data\nx: %array %word -1 x SIZE - -0t1\ny: %string \
+                  \"a ; b\"  ;  comment\n.code\n__start: li $a0,';'\n movw $a1, 3*-SIZE\nbad $\n";
+
+    let expected = ".equ SIZE   2*( 1+1 )\n.data\nx:  %array %word -1 x SIZE - -0t1\n\ny:  \
+                    %string \"a ; b\" ;  comment\n\n.code\n__start:\n    li   $a0, ';'\n    \
+                    movw $a1, 3 * -SIZE\n    bad $\n";
+
+    assert_eq!(format_source(source), expected);
+    assert_eq!(format_source(expected), expected);
+}
+
+#[test]
+fn format_register_names() {
+    let source = "__start:\n    mov $12, $7\n    add $13, $12, $0\n    halt\n";
+    let expected = "__start:\n    mov $t0, $a1\n    add $t1, $t0, $zero\n    halt\n";
+
+    assert_eq!(format_source(source), expected);
+    assert!(encode_source("<input>", source, &[]).unwrap() ==
+            encode_source("<input>", expected, &[]).unwrap());
+}
+
+#[test]
+fn format_label_after_directive() {
+    let source = ".data\nbuffer: %word 0\n.code\n.global __start\n__start:\n    halt\n.data\nend: \
+                  %word 1\n";
+    let expected = ".data\nbuffer:\n    %word 0\n\n.code\n.global __start\n__start:\n    \
+                    halt\n\n.data\nend:\n    %word 1\n";
+
+    assert_eq!(format_source(source), expected);
+    assert_eq!(format_source(expected), expected);
+}
+
+#[test]
+fn format_preserves_encoding() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let include_paths = vec![root.join("lib").to_string_lossy().into_owned()];
+
+    for dir in &["programs", "lib"] {
+        for entry in fs::read_dir(root.join(dir)).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map_or(true, |extension| extension != "tasm") {
+                continue;
+            }
+
+            let mut source = String::new();
+            File::open(&path).unwrap().read_to_string(&mut source).unwrap();
+
+            let path = path.to_string_lossy();
+            let formatted = format_source(&source);
+            assert_eq!(format_source(&formatted), formatted, "{}", path);
+            assert!(formatted == source, "{} isn't formatted", path);
+
+            let before = encode_source(&path, &source, &include_paths).unwrap();
+            let after = encode_source(&path, &formatted, &include_paths).unwrap();
+            assert!(before == after, "{}", path);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use jit::BlockCompiler;
+use registers::{Register, REGISTER_COUNT};
+use util::*;
+use vm::VM;
+
+fn compare_with_interpreter(code: &str) -> VM {
+    let mut interpreted = vm_from_code(code).unwrap();
+    interpreted.run();
+
+    let mut compiled = vm_from_code(code).unwrap();
+    BlockCompiler::new().run(&mut compiled);
+
+    for i in 0..REGISTER_COUNT {
+        let r = Register::from(i as isize);
+        assert_eq!(compiled.read(r), interpreted.read(r), "{:?}", r);
+    }
+
+    compiled
+}
+
+#[test]
+fn jit_loop() {
+    let code = r#"
+        __start:
+            movi $a0, 0
+            movi $a1, 50
+
+        loop:
+            cmp $t0, $a0, $a1
+            j01 $t0, end
+            addi $a0, 1
+            muli $a0, 2
+            add $a2, $a2, $lo
+            jmp loop
+
+        end:
+            halt
+    "#;
+
+    let mut vm = compare_with_interpreter(code);
+    assert_eq!(vm.read(Register::A0), 50);
+    assert_eq!(vm.read(Register::A2), 2550);
+}
+
+#[test]
+fn jit_call() {
+    let code = r#"
+        double:
+            add $a0, $a0, $a0
+            ret
+
+        __start:
+            movi $a0, 3
+            call double
+            call double
+            mov $zero, $a0
+            add $a1, $zero, $a0
+            halt
+    "#;
+
+    let mut vm = compare_with_interpreter(code);
+    assert_eq!(vm.read(Register::A0), 12);
+    assert_eq!(vm.read(Register::A1), 12);
+}
+
+#[test]
+fn jit_self_modifying_store() {
+    let code = r#"
+        __start:
+            mova $t0, patch
+            mova $t1, replacement
+            lw $t2, $t1, 0
+            movi $s1, 0
+
+        patch:
+            addi $s0, 1
+            j1 $s1, done
+            sw $t0, $t2, 0
+            movi $s1, 1
+            jmp patch
+
+        done:
+            halt
+
+        replacement:
+            addi $s0, 100
+    "#;
+
+    let mut vm = compare_with_interpreter(code);
+    assert_eq!(vm.read(Register::S0), 101);
+}
+
+#[test]
+fn jit_modify_own_block() {
+    let code = r#"
+        __start:
+            mova $t0, patch
+            mova $t1, replacement
+            lw $t2, $t1, 0
+            sw $t0, $t2, 0
+
+        patch:
+            addi $s0, 1
+            halt
+
+        replacement:
+            addi $s0, 100
+    "#;
+
+    let mut vm = compare_with_interpreter(code);
+    assert_eq!(vm.read(Register::S0), 100);
+}
+
+#[test]
+fn jit_multiply_immediate() {
+    let code = r#"
+        __start:
+            movi $a0, 7
+            muli $a0, 0
+            mov  $s0, $lo
+
+            muli $a0, 13
+            mov  $s1, $lo
+
+            muli $a0, -265720
+            mov  $s2, $lo
+            mov  $s3, $hi
+
+            movw $a1, 141214768240
+            muli $a1, 265720
+            mov  $s4, $lo
+            mov  $s5, $hi
+
+            muli $lo, 5
+            halt
+    "#;
+
+    let mut vm = compare_with_interpreter(code);
+    assert_eq!(vm.read(Register::S1), 91);
+    assert_eq!(vm.read(Register::S2), -7 * 265720);
+}
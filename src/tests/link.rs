@@ -0,0 +1,242 @@
+use registers::Register;
+use program::decode::Section;
+use program::archive::Archive;
+use program::link::{LinkError, Linker};
+use program::object::{Image, ObjectFile, Relocation, RelocationKind, Visibility};
+use util::{object_from_code, vm_from_image};
+use vm::VM;
+
+static MAIN: &'static str = r#"
+    .data
+    count:
+        %word 3
+
+    .code
+    __start:
+        mova $s0, count
+        lw $a0, $s0, 0
+        call double
+        halt
+"#;
+
+static LIB: &'static str = r#"
+    .global double
+
+    .rodata
+    factor:
+        %word 2
+
+    .code
+    double:
+        mova $t0, factor
+        lw $t0, $t0, 0
+        mul $a0, $t0
+        mov $a0, $lo
+    1:  j0 $zero, 1f
+    1:  ret
+"#;
+
+fn link(objects: Vec<ObjectFile>) -> Result<VM, Vec<LinkError>> {
+    let mut linker = Linker::new();
+    for (i, object) in objects.into_iter().enumerate() {
+        linker.add_object(format!("{}.o", i), object);
+    }
+
+    linker.link().map(|image| vm_from_image(&image))
+}
+
+#[test]
+fn link_objects() {
+    let main = object_from_code(MAIN).unwrap();
+    let lib = object_from_code(LIB).unwrap();
+
+    assert_eq!(main.symbol("__start").map(|symbol| symbol.visibility),
+               Some(Visibility::Global));
+    assert_eq!(main.symbol("count").map(|symbol| symbol.visibility),
+               Some(Visibility::Local));
+    assert_eq!(main.relocations[1],
+               Relocation {
+                   section: Section::Code,
+                   offset: 96,
+                   kind: RelocationKind::Absolute,
+                   symbol: "double".to_string(),
+               });
+    assert!(lib.relocations.iter().any(|r| r.kind == RelocationKind::Relative(144)));
+
+    let lib = ObjectFile::parse(&lib.to_string()).unwrap();
+    assert_eq!(lib.to_string(), object_from_code(LIB).unwrap().to_string());
+
+    let mut vm = link(vec![main, lib]).unwrap();
+    vm.run();
+    assert_eq!(vm.read(Register::A0), 6);
+}
+
+#[test]
+fn link_errors() {
+    let main = object_from_code(MAIN).unwrap();
+    let errors = link(vec![main]).err().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].to_string(), "0.o: undefined symbol `double`");
+
+    let objects = vec![object_from_code(MAIN).unwrap(),
+                       object_from_code(LIB).unwrap(),
+                       object_from_code(LIB).unwrap()];
+    let errors = link(objects).err().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].to_string(),
+               "2.o: duplicate symbol `double` (first defined in 1.o)");
+
+    let errors = link(vec![object_from_code(LIB).unwrap()]).err().unwrap();
+    assert_eq!(errors[0].to_string(), "no object defines `__start`");
+}
+
+#[test]
+fn link_archives() {
+    let unused = ".global unused\n.code\nunused:\n    call missing\n    ret\n";
+
+    let mut archive = Archive::new();
+    archive.add_member("unused.o".to_string(), object_from_code(unused).unwrap());
+    archive.add_member("lib.o".to_string(), object_from_code(LIB).unwrap());
+    assert_eq!(archive.find_member("double"), Some(1));
+    assert_eq!(archive.find_member("factor"), None);
+
+    let archive = Archive::parse(&archive.to_string()).unwrap();
+    assert_eq!(archive.members.len(), 2);
+    assert_eq!(archive.find_member("unused"), Some(0));
+
+    // only the member defining `double` is linked, so `missing` is never looked for
+    let mut linker = Linker::new();
+    linker.add_archive("lib.a".to_string(), archive);
+    linker.add_object("main.o".to_string(), object_from_code(MAIN).unwrap());
+
+    let mut vm = vm_from_image(&linker.link().unwrap());
+    vm.run();
+    assert_eq!(vm.read(Register::A0), 6);
+
+    let mut archive = Archive::new();
+    archive.add_member("unused.o".to_string(), object_from_code(unused).unwrap());
+
+    let mut linker = Linker::new();
+    linker.add_archive("lib.a".to_string(), archive);
+    linker.add_object("main.o".to_string(), object_from_code(MAIN).unwrap());
+    let errors = linker.link().err().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].to_string(), "main.o: undefined symbol `double`");
+}
+
+#[test]
+fn link_archive_duplicate_member_names() {
+    let first = ".global first\n.code\nfirst:\n    ret\n";
+    let second = ".global second\n.code\nsecond:\n    ret\n";
+
+    let mut archive = Archive::new();
+    archive.add_member("lib.o".to_string(), object_from_code(first).unwrap());
+    archive.add_member("lib.o".to_string(), object_from_code(second).unwrap());
+
+    let archive = Archive::parse(&archive.to_string()).unwrap();
+    assert_eq!(archive.find_member("first"), Some(0));
+    assert_eq!(archive.find_member("second"), Some(1));
+
+    let contents = archive.to_string().replace("index second 1", "index second 2");
+    let error = Archive::parse(&contents).err().unwrap();
+    assert_eq!(error.line, 3);
+}
+
+#[test]
+fn link_relocatable_errors() {
+    let code = ".global __start, missing\n.data\nx:\n    %word x\n.code\n__start:\n    halt\n";
+    let report = object_from_code(code).err().unwrap().to_string();
+
+    assert!(report.contains("<input>:4:11: the address of `x` isn't known until link time"),
+            "{}",
+            report);
+    assert!(report.contains("<input>:1:18: undefined label `missing`\n.global __start, \
+                             missing\n                 ^^^^^^^"),
+            "{}",
+            report);
+}
+
+#[test]
+fn link_label_differences() {
+    let code = r#"
+        .data
+        buffer:
+            %array %word 0 x 4
+        buffer_end:
+        size:
+            %word buffer_end - buffer
+
+        .code
+        __start:
+            movi $a0, buffer_end - buffer
+            mova $a1, size
+            lw $a1, $a1, 0
+            halt
+    "#;
+
+    let main = object_from_code(code).unwrap();
+    let lib = object_from_code(LIB).unwrap();
+    let mut vm = link(vec![lib, main]).unwrap();
+    vm.run();
+    assert_eq!(vm.read(Register::A0), 96);
+    assert_eq!(vm.read(Register::A1), 96);
+
+    let code = ".data\nx:\n    %word 0\n.code\n__start:\n    movi $a0, __start - x\n    halt\n";
+    let report = object_from_code(code).err().unwrap().to_string();
+    assert!(report.contains("<input>:6:15: the address of `__start` isn't known until link time"),
+            "{}",
+            report);
+}
+
+#[test]
+fn link_branch_out_of_range() {
+    let main = ".code\n__start:\n    j0 $a0, far\n    halt\n";
+    let mut far = String::from(".global far\n.code\n");
+    for _ in 0..11_100 {
+        far.push_str("    nop\n");
+    }
+    far.push_str("far:\n    halt\n");
+
+    let main = object_from_code(main).unwrap();
+    assert_eq!(main.relocations[0].kind, RelocationKind::Relative(24));
+
+    let errors = link(vec![main, object_from_code(&far).unwrap()]).err().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].to_string(),
+               "0.o: branch to `far` is 266424 trits away, out of range (-265720 to 265720)");
+}
+
+#[test]
+fn link_line_table() {
+    let main = object_from_code(MAIN).unwrap();
+    let lib = object_from_code(LIB).unwrap();
+
+    // offsets in an object's table are relative to its code section
+    let lib_lines = lib.lines.clone().unwrap();
+    assert_eq!(lib_lines.files, vec!["<input>"]);
+    assert_eq!(lib_lines.entries[0].addr, 0);
+    assert_eq!(lib_lines.lookup(96).map(|entry| entry.position.line), Some(13));
+
+    let text = lib.to_string();
+    assert!(text.contains("\nfile 0 <input>\nline 0 48 0 10 9\n"));
+    assert_eq!(ObjectFile::parse(&text).unwrap().lines, lib.lines);
+    assert_eq!(ObjectFile::parse("tern-object\nline 0 24 0 1 1\n").unwrap_err().line, 2);
+
+    let stripped = ObjectFile { lines: None, ..lib.clone() };
+    assert!(ObjectFile::parse(&stripped.to_string()).unwrap().lines.is_none());
+
+    let mut linker = Linker::new();
+    linker.add_object("main.o".to_string(), main);
+    linker.add_object("lib.o".to_string(), lib);
+    let image = linker.link().unwrap();
+
+    let lines = image.lines.clone().unwrap();
+    let lib_code =
+        lines.entries.iter().rev().find(|entry| entry.position.line == 10).unwrap().addr;
+    assert_eq!(lines.lookup(lib_code + 96).map(|entry| entry.position.line), Some(13));
+    assert_eq!(lines.lookup(lines.entries[0].addr).map(|entry| entry.position.line), Some(8));
+
+    let parsed = Image::parse(&image.to_string()).unwrap();
+    assert_eq!(parsed.lines, image.lines);
+    assert_eq!(parsed.trits, image.trits);
+}
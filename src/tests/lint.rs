@@ -0,0 +1,193 @@
+use std::fs;
+use std::path::Path;
+
+use program::DecodedProgram;
+use program::lint::{lint, LintWarning};
+use registers::Register;
+
+fn lint_code(code: &str) -> Vec<LintWarning> {
+    let mut program = DecodedProgram::new();
+    program.read_str(code).unwrap();
+    lint(&program)
+}
+
+fn lines(warnings: &[LintWarning]) -> Vec<usize> {
+    warnings.iter().map(|warning| warning.location().span.line).collect()
+}
+
+#[test]
+fn lint_zero_writes() {
+    let code = "__start:\n    li $zero, 3\n    nop\n    sub $zero, $a0, $a1\n    halt\n";
+    let warnings = lint_code(code);
+
+    assert_eq!(lines(&warnings), vec![2, 4]);
+    assert!(warnings.iter().all(|warning| match *warning {
+        LintWarning::ZeroWrite(_) => true,
+        _ => false,
+    }));
+}
+
+#[test]
+fn lint_lo_hi_reads() {
+    let code = r#"
+__start:
+    li $t0, 1
+    mov $a0, $hi
+    mul $a0, $a1
+    mov $t0, $lo
+    mov $t1, $hi
+    addi $t1, 1
+    sw $sp, $t1, 0
+    mov $t2, $hi
+    blt $a0, $a1, 1f
+    mov $t3, $lo
+1:
+    mov $t4, $lo
+    movi $t4, 2
+    mov $t5, $lo
+    halt
+"#;
+
+    let warnings = lint_code(code);
+    assert_eq!(warnings,
+               vec![LintWarning::UnsetRegister(Register::HI, warnings[0].location().clone()),
+                    LintWarning::UnsetRegister(Register::LO, warnings[1].location().clone())]);
+    assert_eq!(lines(&warnings), vec![4, 16]);
+}
+
+#[test]
+fn lint_branch_clobbers_hi() {
+    let code = r#"
+__start:
+    add $t0, $a0, $a1
+    beq $a0, $a1, 1f
+    mov $t1, $hi
+    bgt $a0, $a1, 1f
+    call f
+    mov $t2, $hi
+1:
+    mov $t3, $hi
+    blt $a0, $a1, 2f
+    cmp $hi, $a0, $a1
+    mov $t4, $hi
+2:
+    halt
+f:
+    ret
+"#;
+
+    let warnings = lint_code(code);
+    assert_eq!(warnings,
+               vec![LintWarning::BranchClobbersHi(warnings[0].location().clone())]);
+    assert_eq!(lines(&warnings), vec![5]);
+}
+
+#[test]
+fn lint_unsaved_return_address() {
+    let code = r#"
+__start:
+    call a
+    halt
+
+a:
+    call leaf
+    ret
+
+b:
+    sw $sp, $ra, 0
+    call leaf
+    lw $ra, $sp, 0
+    ret
+
+c:
+    mov $t5, $ra
+    call leaf
+    mov $ra, $t5
+    ret
+
+leaf:
+    call leaf
+    jmp b
+"#;
+
+    let warnings = lint_code(code);
+    assert_eq!(lines(&warnings), vec![7]);
+    match warnings[0] {
+        LintWarning::UnsavedReturnAddress(_) => {}
+        ref warning => panic!("unexpected warning: {}", warning),
+    }
+}
+
+#[test]
+fn lint_unreachable_code() {
+    let code = r#"
+__start:
+    call f
+    halt
+    mov $a0, $a1
+    mov $a0, $a2
+.after:
+    b .after
+    nop
+f:
+    jmp .done
+    ret
+.done:
+    ret
+"#;
+
+    let warnings = lint_code(code);
+    assert_eq!(lines(&warnings), vec![5, 9, 12]);
+    assert!(warnings.iter().all(|warning| match *warning {
+        LintWarning::UnreachableCode(_) => true,
+        _ => false,
+    }));
+}
+
+#[test]
+fn lint_branch_range() {
+    let mut code = String::from("__start:\n    j0 $a0, 1f\n    beq $a0, $a1, .end\n");
+    for _ in 0..11_100 {
+        code.push_str("    nop\n");
+    }
+    code.push_str("1:  j0 $a0, __start\n.end:\n    halt\n");
+
+    let warnings = lint_code(&code);
+    assert_eq!(lines(&warnings), vec![2, 3, 11_104]);
+
+    let offsets: Vec<isize> = warnings.iter()
+        .map(|warning| match *warning {
+            LintWarning::BranchOutOfRange(_, offset, _) => offset,
+            _ => panic!("unexpected warning: {}", warning),
+        })
+        .collect();
+    assert_eq!(offsets, vec![24 * 11_102, 24 * 11_101, -24 * 11_104]);
+    assert!(warnings[0].to_string().contains("branch to `1` is 266448 trits away"));
+
+    let mut code = String::from("__start:\n    j0 $a0, 1f\n");
+    for _ in 0..11_000 {
+        code.push_str("    nop\n");
+    }
+    code.push_str("1:  halt\n");
+    assert!(lint_code(&code).is_empty());
+}
+
+#[test]
+fn lint_clean_sources() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+    for dir in &["programs", "lib"] {
+        for entry in fs::read_dir(root.join(dir)).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map_or(true, |extension| extension != "tasm") {
+                continue;
+            }
+
+            let mut program = DecodedProgram::new();
+            program.read_file(&path.to_string_lossy()).unwrap();
+
+            let warnings = lint(&program);
+            assert!(warnings.is_empty(), "{}: {:?}", path.display(), warnings);
+        }
+    }
+}
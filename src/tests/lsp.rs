@@ -0,0 +1,217 @@
+use std::io::Cursor;
+
+use lsp::document::{CompletionKind, Document, Severity};
+use lsp::json::Json;
+use lsp::protocol::{path_to_uri, read_message, uri_to_path, write_message};
+use lsp::server::Server;
+use program::lexer::Span;
+
+static SOURCE: &'static str = r#".equ COUNT 3
+
+.data
+message:
+    %string "héllo"
+
+.code
+__start:
+    li $a0, COUNT
+    call countdown
+    halt
+
+countdown:
+.loop:
+    beq $a0, $zero, 1f
+    dec $a0
+    b .loop
+1:  mova $a0, message
+    ret
+"#;
+
+fn document(source: &str) -> Document {
+    Document::new("/tmp/test.tasm", source, &[])
+}
+
+#[test]
+fn lsp_json() {
+    let text = r#"{"a": [1, -2.5, true, null], "b": "x\"é😀\n", "c": {}}"#;
+    let value = Json::parse(text).unwrap();
+
+    assert_eq!(value.path(&["a"]).and_then(Json::as_array).map(|a| a.len()), Some(4));
+    assert_eq!(value.get("b").and_then(Json::as_str), Some("x\"é😀\n"));
+    assert_eq!(value.to_string(),
+               r#"{"a":[1,-2.5,true,null],"b":"x\"é😀\n","c":{}}"#);
+    assert_eq!(Json::parse(&value.to_string()), Ok(value));
+
+    assert!(Json::parse("[1, 2").is_err());
+    assert!(Json::parse("{} x").is_err());
+    assert!(Json::parse(r#""\ud83d""#).is_err());
+}
+
+#[test]
+fn lsp_uris() {
+    assert_eq!(uri_to_path("file:///home/a%20b/x.tasm"), Some("/home/a b/x.tasm".to_string()));
+    assert_eq!(path_to_uri("/home/a b/x.tasm"), "file:///home/a%20b/x.tasm");
+    assert_eq!(uri_to_path("untitled:1"), None);
+}
+
+#[test]
+fn lsp_diagnostics() {
+    let source = "__start:\n    li $zero, 1\n    foo $a0\n    halt\n";
+    let diagnostics = document(source).diagnostics;
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+    assert_eq!(diagnostics[0].span.line, 3);
+
+    let source = "__start:\n    li $zero, 1\n    jmp missing\n    halt\n";
+    let diagnostics = document(source).diagnostics;
+    let found: Vec<(usize, Severity)> = diagnostics.iter()
+        .map(|diagnostic| (diagnostic.span.line, diagnostic.severity))
+        .collect();
+    assert_eq!(found,
+               vec![(2, Severity::Warning), (4, Severity::Warning), (3, Severity::Error)]);
+    assert_eq!(diagnostics[2].message, "undefined label `missing`");
+
+    // errors in a macro are shown where it's used
+    let source = ".macro bad\n    mov $a0, $nope\n.endm\n__start:\n    bad\n    halt\n";
+    let diagnostics = document(source).diagnostics;
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].span.line, 5);
+
+    // a file without `__start` is checked as an object
+    assert!(document(".global helper\nhelper:\n    ret\n").diagnostics.is_empty());
+}
+
+#[test]
+fn lsp_definitions_and_references() {
+    let document = document(SOURCE);
+    assert!(document.diagnostics.is_empty());
+
+    let definition = |line, column| document.definition(line, column).map(|d| d.span);
+    assert_eq!(definition(10, 12), Some(Span::new(13, 1, 9)));
+    assert_eq!(definition(17, 8), Some(Span::new(14, 1, 5)));
+    assert_eq!(definition(15, 22), Some(Span::new(18, 1, 1)));
+    assert_eq!(definition(9, 15), Some(Span::new(1, 6, 5)));
+    assert_eq!(definition(18, 17), Some(Span::new(4, 1, 7)));
+    assert_eq!(definition(9, 6), None);
+
+    let references = document.references(14, 3, true);
+    assert_eq!(references, vec![Span::new(14, 1, 5), Span::new(17, 7, 5)]);
+    assert_eq!(document.references(17, 8, false), vec![Span::new(17, 7, 5)]);
+    assert_eq!(document.references(1, 6, true).len(), 2);
+}
+
+#[test]
+fn lsp_hover() {
+    let document = document(SOURCE);
+
+    assert_eq!(document.hover(9, 6), Some("`li`: pseudo-instruction, 2 operands".to_string()));
+    assert_eq!(document.hover(18, 6), Some("`mova`: 2 operands".to_string()));
+    assert_eq!(document.hover(19, 5), Some("`ret`: 0 operands".to_string()));
+    assert_eq!(document.hover(9, 9),
+               Some("`$a0` (`$6`): first argument and result, and the syscall argument"
+                   .to_string()));
+    assert_eq!(document.hover(10, 12),
+               Some("label `countdown`, defined on line 13".to_string()));
+    assert_eq!(document.hover(9, 15), Some("constant `COUNT`, defined on line 1".to_string()));
+    assert_eq!(document.hover(15, 22), Some("label `1`, defined on line 18".to_string()));
+    assert_eq!(document.hover(2, 1), None);
+}
+
+#[test]
+fn lsp_completion() {
+    let source = format!("{}\nafter:\n    \n    mov $\n    b .\n    jmp c\n", SOURCE);
+    let document = document(&source);
+    let texts = |line, column| -> Vec<String> {
+        document.completions(line, column).1.into_iter().map(|c| c.text).collect()
+    };
+
+    let opcodes = document.completions(22, 5).1;
+    assert!(opcodes.iter().all(|c| c.kind == CompletionKind::Opcode));
+    assert!(opcodes.iter().any(|c| c.text == "syscall"));
+    assert!(opcodes.iter().any(|c| c.text == "beq"));
+
+    let (span, registers) = document.completions(23, 10);
+    assert_eq!(span, Span::new(23, 9, 1));
+    assert_eq!(registers.len(), 24);
+    assert_eq!(registers[12].text, "$t0");
+
+    let (span, _) = document.completions(24, 8);
+    assert_eq!(span, Span::new(24, 7, 1));
+    assert_eq!(texts(24, 8), vec!["COUNT", "__start", "after", "countdown", "countdown.loop",
+                                  "message"]);
+
+    let (span, _) = document.completions(17, 12);
+    assert_eq!(span, Span::new(17, 7, 5));
+    assert!(texts(17, 12).contains(&".loop".to_string()));
+}
+
+#[test]
+fn lsp_session() {
+    let uri = "file:///tmp/session.tasm";
+    let open = format!(r#"{{"jsonrpc": "2.0", "method": "textDocument/didOpen",
+                           "params": {{"textDocument": {{"uri": "{}", "text": {}}}}}}}"#,
+                       uri,
+                       Json::from(SOURCE));
+    let hover = format!(r#"{{"jsonrpc": "2.0", "id": 2, "method": "textDocument/hover",
+                            "params": {{"textDocument": {{"uri": "{}"}},
+                                        "position": {{"line": 8, "character": 5}}}}}}"#,
+                        uri);
+    let definition = format!(r#"{{"jsonrpc": "2.0", "id": 3, "method": "textDocument/definition",
+                                 "params": {{"textDocument": {{"uri": "{}"}},
+                                             "position": {{"line": 17, "character": 14}}}}}}"#,
+                             uri);
+
+    let messages = vec![r#"{"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}"#
+                            .to_string(),
+                        open,
+                        hover,
+                        definition,
+                        r#"{"jsonrpc": "2.0", "id": 4, "method": "nope"}"#.to_string(),
+                        r#"{"jsonrpc": "2.0", "id": 5, "method": "shutdown"}"#.to_string(),
+                        r#"{"jsonrpc": "2.0", "method": "exit"}"#.to_string()];
+
+    let mut input = Vec::new();
+    for message in &messages {
+        write_message(&mut input, &Json::parse(message).unwrap()).unwrap();
+    }
+
+    let mut server = Server::new(Vec::new(), Vec::new());
+    assert_eq!(server.run(&mut Cursor::new(input)).unwrap(), 0);
+
+    let mut output = Cursor::new(server.into_writer());
+    let mut responses = Vec::new();
+    while let Some(content) = read_message(&mut output).unwrap() {
+        responses.push(Json::parse(&content).unwrap());
+    }
+
+    assert_eq!(responses.len(), 6);
+    assert!(responses[0].path(&["result", "capabilities", "hoverProvider"]).is_some());
+    assert_eq!(responses[1].get("method").and_then(Json::as_str),
+               Some("textDocument/publishDiagnostics"));
+    assert_eq!(responses[1].path(&["params", "diagnostics"]).and_then(Json::as_array),
+               Some(&[][..]));
+    assert_eq!(responses[2].path(&["result", "contents", "value"]).and_then(Json::as_str),
+               Some("`li`: pseudo-instruction, 2 operands"));
+    assert_eq!(responses[3].path(&["result", "range", "start", "line"]).and_then(Json::as_usize),
+               Some(3));
+    assert_eq!(responses[3].path(&["result", "uri"]).and_then(Json::as_str), Some(uri));
+    assert_eq!(responses[4].path(&["error", "code"]), Some(&Json::Number(-32601.0)));
+    assert_eq!(responses[5].get("result"), Some(&Json::Null));
+}
+
+#[test]
+fn lsp_included_definitions() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/programs/lsp.tasm");
+    let source = ".include \"../lib/io.tasm\"\n__start:\n    li $t0, SYSCALL_EXIT\n    call \
+                  putChar\n    halt\n";
+    let document = Document::new(path, source, &[]);
+    assert!(document.diagnostics.is_empty());
+
+    let definition = document.definition(4, 12).unwrap();
+    assert!(definition.path.unwrap().ends_with("lib/io.tasm"));
+    assert!(document.hover(4, 12).unwrap().starts_with("label `putChar`, defined at "));
+
+    let definition = document.definition(3, 20).unwrap();
+    assert!(definition.path.unwrap().ends_with("lib/io.tasm"));
+    assert!(document.hover(3, 20).unwrap().starts_with("constant `SYSCALL_EXIT`, defined at "));
+}
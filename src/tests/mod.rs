@@ -1,4 +1,13 @@
 mod trit;
 mod ternary;
 mod vm;
+mod jit;
 mod text;
+mod parser;
+mod encode;
+mod link;
+mod stdlib;
+mod format;
+mod lint;
+mod lsp;
+mod programs;
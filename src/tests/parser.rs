@@ -0,0 +1,426 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use registers::Register;
+use program::DecodedProgram;
+use program::ast::*;
+use program::lexer::{tokenize, Span, TokenKind};
+use program::encode::EncodeError;
+use program::parser::*;
+use program::report::AssemblyError;
+use text;
+use util::{assemble, vm_from_code};
+
+#[test]
+fn parser_tokenize() {
+    let tokens = tokenize(r#"loop: movi $t0, -0t1T ; "comment""#, 1).unwrap();
+    let kinds: Vec<TokenKind> = tokens.into_iter().map(|token| token.kind).collect();
+
+    assert_eq!(kinds,
+               vec![TokenKind::Ident("loop".to_string()),
+                    TokenKind::Colon,
+                    TokenKind::Ident("movi".to_string()),
+                    TokenKind::Register("t0".to_string()),
+                    TokenKind::Comma,
+                    TokenKind::Minus,
+                    TokenKind::Ternary("1T".to_string())]);
+}
+
+#[test]
+fn parser_string_escapes() {
+    let tokens = tokenize(r#"%string "a;\"b\u0041\n""#, 1).unwrap();
+    assert_eq!(tokens[2].kind, TokenKind::String("a;\"bA\n".to_string()));
+    assert_eq!(tokens[2].span, Span::new(1, 9, 15));
+}
+
+#[test]
+fn parser_statements() {
+    let statements = parse_line("  end: lw $a0, $sp, -24", 3).unwrap();
+    assert_eq!(statements.len(), 2);
+    assert_eq!(statements[0],
+               Statement::Label(Spanned::new("end".to_string(), Span::new(3, 3, 3))));
+
+    match statements[1] {
+        Statement::Instruction(ref opcode, ref args) => {
+            assert_eq!(opcode.node, "lw");
+            assert_eq!(args[0].node, Operand::Register(Register::A0));
+            assert_eq!(args[2], Spanned::new(Operand::Decimal(-24), Span::new(3, 21, 3)));
+        }
+
+        _ => panic!("expected an instruction"),
+    }
+}
+
+#[test]
+fn parser_array() {
+    let statements = parse_line("%array %word 0 x 24", 1).unwrap();
+
+    match statements[0] {
+        Statement::Data(ref spec) => {
+            assert_eq!(spec.type_name.node, "array");
+            assert_eq!(spec.args.len(), 2);
+            assert_eq!(spec.args[1].node, Operand::Decimal(24));
+        }
+
+        _ => panic!("expected data"),
+    }
+}
+
+#[test]
+fn parser_error_location() {
+    let mut program = DecodedProgram::new();
+    let code = ".code\n__start:\n    add $t0, $t1, 5\n";
+    let errors = program.read_str(code).unwrap_err();
+    let error = &errors[0];
+
+    assert_eq!(error.span, Some(Span::new(3, 19, 1)));
+    assert_eq!(error.to_string(),
+               "<input>:3:19: expected register operand, found decimal\n    add $t0, $t1, \
+                5\n                  ^");
+}
+
+#[test]
+fn parser_out_of_range() {
+    let code = "__start:\n    lt $t0, $t1, 365\n    movi $t0, -99999999999999999999\n    halt\n";
+    let report = vm_from_code(code).err().unwrap();
+    assert_eq!(report.len(), 2);
+
+    match report.errors[0] {
+        AssemblyError::Encode(EncodeError::IntOutOfRange(ref n, -364, 364, ref location)) => {
+            assert_eq!(n, "365");
+            assert_eq!(location.span, Span::new(2, 18, 3));
+        }
+        ref error => panic!("unexpected error: {}", error),
+    }
+
+    match report.errors[1] {
+        AssemblyError::Encode(EncodeError::IntOutOfRange(ref n, -265720, 265720, _)) => {
+            assert_eq!(n, "-99999999999999999999");
+        }
+        ref error => panic!("unexpected error: {}", error),
+    }
+}
+
+#[test]
+fn parser_collects_all_errors() {
+    let mut program = DecodedProgram::new();
+    let code = "ad $t0, $t1\n__start:\n    add $t0, $t1\n    halt\n    movi $t0, 999999\n";
+    let errors = program.read_str(code).unwrap_err();
+
+    let lines: Vec<usize> = errors.iter().map(|e| e.span.unwrap().line).collect();
+    assert_eq!(lines, vec![1, 3]);
+    assert_eq!(program.code.len(), 3);
+
+    // values that don't fit are left for the encoder to report
+    assert_eq!(program.range_errors.len(), 1);
+    let message = program.range_errors[0].to_string();
+    assert!(message.starts_with("<input>:5:15: 999999 is out of range"));
+}
+
+#[test]
+fn parser_report_includes_encode_errors() {
+    let code = "__start:\n    ad $t0, $t1\n    call foo\n    jmp bar\n";
+
+    match vm_from_code(code) {
+        Ok(_) => panic!("expected errors"),
+        Err(report) => {
+            assert_eq!(report.len(), 3);
+            assert!(report.to_string().ends_with("3 errors"));
+        }
+    }
+}
+
+#[test]
+fn parser_report_in_source_order() {
+    let code = "__start:\n    movi $t0, 999999\n    jmp nowhere\n    add $t0\n    \
+                movi $t1, missing\n    halt\n";
+    let report = vm_from_code(code).err().unwrap();
+
+    let lines: Vec<usize> = report.errors
+        .iter()
+        .map(|error| error.position().unwrap().1)
+        .collect();
+    assert_eq!(lines, vec![2, 3, 4, 5]);
+
+    let mut program = DecodedProgram::new();
+    let code = ".equ A missing\n.code\n__start:\n    add $t0\n    movi $t0, A\n";
+    let errors = program.read_str(code).unwrap_err();
+    let lines: Vec<usize> = errors.iter().map(|e| e.span.unwrap().line).collect();
+    assert_eq!(lines, vec![1, 4]);
+}
+
+#[test]
+fn parser_constant_expressions() {
+    let code = r#"
+        .equ WORDS 2 * (1 + 1)
+        .equ BYTES WORDS * 24 - -0t1
+
+        .data
+        buffer:
+            %array %word 0 x WORDS
+        buffer_end:
+        message:
+            %string "hi"
+
+        .code
+        __start:
+            movi $a0, buffer_end - buffer
+            movi $a1, sizeof buffer + sizeof message
+            movi $a2, BYTES
+            movw $a3, LATER
+            halt
+
+        .equ LATER -(3 * WORDS)
+    "#;
+
+    let mut vm = vm_from_code(code).unwrap();
+    vm.run();
+
+    assert_eq!(vm.read(Register::A0), 96);
+    assert_eq!(vm.read(Register::A1), 96 + 24 + 12);
+    assert_eq!(vm.read(Register::A2), 97);
+    assert_eq!(vm.read(Register::A3), -12);
+}
+
+#[test]
+fn parser_expression_errors() {
+    let code = ".equ A B\n.equ B A\n.data\nx:\n%array %word 0 x y - x\ny:\n.code\n__start:\n\
+                movi $t0, A\nmovi $t1, nope\nmovi $t2, 100000 * 100000\nhalt\n";
+    let mut program = DecodedProgram::new();
+    let errors = program.read_str(code).unwrap_err();
+
+    let kinds: Vec<String> = errors.iter().map(|e| e.kind.to_string()).collect();
+    assert_eq!(kinds,
+               vec!["constant `A` is defined in terms of itself",
+                    "`y` depends on the program layout and can't be used here",
+                    "undefined symbol `nope`"]);
+    assert!(program.range_errors[0]
+        .to_string()
+        .starts_with("<input>:11:11: 10000000000 is out of range (-265720 to 265720)"));
+    assert_eq!(errors[0].span.unwrap().line, 2);
+}
+
+#[test]
+fn parser_macros() {
+    let code = r#"
+        .macro count_down reg, from
+            movi \reg, \from
+        loop:
+            addi \reg, -1
+            cmp $t0, \reg, $zero
+            j1 $t0, loop
+        .endm
+
+        .macro twice reg
+            count_down \reg, 3
+            addi $a2, 1
+            count_down \reg, 4 ; labels stay unique per expansion
+        .endm
+
+        __start:
+            twice $a0
+            mov $a1, $a0
+            twice $a1
+            halt
+    "#;
+
+    let mut vm = vm_from_code(code).unwrap();
+    vm.run();
+
+    assert_eq!(vm.read(Register::A0), 0);
+    assert_eq!(vm.read(Register::A1), 0);
+    assert_eq!(vm.read(Register::A2), 2);
+}
+
+#[test]
+fn parser_macro_error_location() {
+    let code = ".macro load reg\n    movi \\reg, 999999\n.endm\n__start:\n    load $t0\n    halt\n";
+    let mut program = DecodedProgram::new();
+    program.read_str(code).unwrap();
+    assert_eq!(program.range_errors.len(), 1);
+    assert_eq!(program.range_errors[0].to_string(),
+               "<input>:2:15: 999999 is out of range (-265720 to 265720)\n    movi $t0, \
+                999999\n              ^^^^^^\nnote: in expansion of macro `load` at \
+                <input>:5:5\n    load $t0\n    ^^^^^^^^");
+}
+
+#[test]
+fn parser_local_and_numeric_labels() {
+    let code = r#"
+        .data
+        table:
+            %word 1
+        .end:
+
+        .code
+        __start:
+            li $a0, table.end - table
+            li $a1, 0
+            call first
+            call second
+            halt
+
+        first:
+            li $t0, 3
+        .loop:
+            inc $a1
+            dec $t0
+            beq $t0, $zero, .end
+            b .loop
+        .end:
+            ret
+
+        second:
+            li $t0, 2
+        1:  inc $a1
+            dec $t0
+            beq $t0, $zero, 1f
+            b 1b
+        1:  ret
+    "#;
+
+    let mut vm = vm_from_code(code).unwrap();
+    vm.run();
+
+    assert_eq!(vm.read(Register::A0), 24);
+    assert_eq!(vm.read(Register::A1), 5);
+}
+
+#[test]
+fn parser_undefined_numeric_label() {
+    let code = "__start:\n1:  b 1b\n    b 2b\n    halt\n";
+
+    match vm_from_code(code) {
+        Ok(_) => panic!("expected an error"),
+        Err(report) => assert!(report.to_string().contains("undefined label `2b`")),
+    }
+}
+
+fn include_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = env::temp_dir().join(format!("tern-include-{}-{}", name, process::id()));
+    for &(path, contents) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    dir
+}
+
+#[test]
+fn parser_include_paths() {
+    let dir = include_dir("paths",
+                          &[("src/main.tasm",
+                             "__start:\n.include \"lib/one.tasm\"\n.include two.tasm\nhalt\n"),
+                            ("src/lib/one.tasm", "addi $a0, 1\n"),
+                            ("inc/two.tasm", "addi $a0, 2\n")]);
+
+    let mut program = DecodedProgram::new();
+    program.add_include_path(dir.join("inc"));
+    let result = program.read_file(dir.join("src/main.tasm").to_str().unwrap());
+    let (mut vm, report) = assemble(program, result);
+    assert!(report.is_empty(), "{}", report);
+
+    vm.run();
+    assert_eq!(vm.read(Register::A0), 3);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn parser_include_once() {
+    let dir = include_dir("once",
+                          &[("main.tasm",
+                             "__start:\n.include_once inc.tasm\n.include_once inc.tasm\nhalt\n"),
+                            ("inc.tasm", "addi $a0, 1\n")]);
+
+    let mut program = DecodedProgram::new();
+    let result = program.read_file(dir.join("main.tasm").to_str().unwrap());
+    let (mut vm, report) = assemble(program, result);
+    assert!(report.is_empty(), "{}", report);
+
+    vm.run();
+    assert_eq!(vm.read(Register::A0), 1);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn parser_include_errors() {
+    let dir = include_dir("errors",
+                          &[("main.tasm", "__start:\n.include a.tasm\n.include missing.tasm\n"),
+                            ("a.tasm", "halt\n.include b.tasm\n"),
+                            ("b.tasm", ".include a.tasm\n")]);
+
+    let mut program = DecodedProgram::new();
+    let errors = program.read_file(dir.join("main.tasm").to_str().unwrap()).unwrap_err();
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+    assert_eq!(messages.len(), 2);
+    assert!(messages[0].contains("b.tasm:1:10"), "{}", messages[0]);
+    assert!(messages[0].contains("a.tasm` includes itself"), "{}", messages[0]);
+    assert!(messages[1].contains("main.tasm:3:10"), "{}", messages[1]);
+    assert!(messages[1].contains("can't find included file `missing.tasm`"), "{}", messages[1]);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn parser_literals() {
+    let tokens = tokenize(r#"'A' '\n' '\'' ';' 0n18 0h1q 0t1T0"#, 1).unwrap();
+    let kinds: Vec<TokenKind> = tokens.into_iter().map(|token| token.kind).collect();
+
+    assert_eq!(kinds,
+               vec![TokenKind::Char('A'),
+                    TokenKind::Char('\n'),
+                    TokenKind::Char('\''),
+                    TokenKind::Char(';'),
+                    TokenKind::Nonary("18".to_string()),
+                    TokenKind::Heptavigesimal("1q".to_string()),
+                    TokenKind::Ternary("1T0".to_string())]);
+
+    let code = r#"
+        .data
+        hi:
+            %word 4
+            %tryte 'h', 'i'
+            %half '‽'
+
+        .code
+        __start:
+            li $a0, 0n18 + 0h1Q - 0t1T0
+            li $a1, -'A'
+            mova $s0, hi
+            halt
+    "#;
+
+    let mut vm = vm_from_code(code).unwrap();
+    vm.run();
+
+    assert_eq!(vm.read(Register::A0), 17 + 53 - 6);
+    assert_eq!(vm.read(Register::A1), -text::char_value('A').unwrap());
+
+    let addr = vm.read(Register::S0);
+    let (s, _) = text::decode_str(unsafe { vm.memory.offset(addr) });
+    assert_eq!(s, "hi\u{203D}");
+}
+
+#[test]
+fn parser_literal_errors() {
+    let code = "__start:\n    movi $a0, 0h10000\n    movi $a0, 0n19\n    movi $a0, 'ab'\n\
+                .data\n    %tryte '\u{203D}'\n";
+    let mut program = DecodedProgram::new();
+    let errors = program.read_str(code).unwrap_err();
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+    assert_eq!(messages.len(), 2);
+    assert!(messages[0].contains("3:15: invalid number `0n19`"));
+    assert!(messages[1].contains("4:15: invalid character literal `'ab'`"));
+
+    let messages: Vec<String> = program.range_errors.iter().map(|e| e.to_string()).collect();
+    assert_eq!(messages.len(), 2);
+    assert!(messages[0].contains("6:12: -191452 is out of range (-364 to 364)"));
+    assert!(messages[1].contains("2:15: 531441 is out of range (-265720 to 265720)"));
+}
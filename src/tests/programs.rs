@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use ternary;
+use types::*;
+use util::{vm_from_file, SharedBuffer};
+use vm::VM;
+
+fn program_output(name: &str) -> (VM, String) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("programs").join(name);
+    let mut vm = vm_from_file(path.to_str().unwrap()).unwrap();
+
+    let output = SharedBuffer::new();
+    vm.set_output(output.clone());
+    vm.run();
+
+    (vm, output.contents())
+}
+
+#[test]
+fn programs_hello() {
+    let (vm, output) = program_output("hello.tasm");
+    assert_eq!(vm.error(), None);
+    assert_eq!(output, "Hello, ternary world!");
+}
+
+#[test]
+fn programs_print() {
+    let (vm, output) = program_output("print.tasm");
+    assert_eq!(vm.error(), None);
+    assert_eq!(output, "Here's some stuff:1230000000000000000001TTTT0");
+}
+
+#[test]
+fn programs_loop() {
+    let (vm, output) = program_output("loop.tasm");
+    assert_eq!(vm.error(), None);
+
+    let expected: String = ["0", "1", "1T", "10", "11", "1TT", "1T0", "1T1", "10T", "100"]
+        .iter()
+        .map(|n| format!("{:0>24}", n))
+        .collect();
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn programs_sum() {
+    let (vm, output) = program_output("sum.tasm");
+    assert_eq!(vm.error(), None);
+
+    let mut expected = EMPTY_WORD;
+    unsafe { ternary::from_int(mut_ptr!(expected), 149_985_000, WORD_ISIZE) };
+    assert_eq!(output, unsafe { ternary::to_str(ptr!(expected), WORD_ISIZE) });
+}
@@ -0,0 +1,286 @@
+use rand::{Rng, SeedableRng, StdRng};
+use std::path::Path;
+
+use ternary;
+use text;
+use types::*;
+use registers::Register;
+use program::DecodedProgram;
+use util::assemble;
+use vm::VM;
+
+fn run_with_library(code: &str) -> VM {
+    let mut program = DecodedProgram::new();
+    program.add_include_path(Path::new(env!("CARGO_MANIFEST_DIR")).join("lib"));
+
+    let result = program.read_str(code);
+    let (mut vm, report) = assemble(program, result);
+    assert!(!report.has_errors(), "{}", report);
+
+    vm.run();
+    vm
+}
+
+fn read_string(vm: &mut VM, r: Register) -> String {
+    let addr = vm.read(r);
+    text::decode_str(unsafe { vm.memory.offset(addr) }).0
+}
+
+fn read_trits(vm: &mut VM, r: Register, len: usize) -> String {
+    let addr = vm.read(r);
+    unsafe { ternary::to_str(vm.memory.offset(addr), len as isize) }
+}
+
+#[test]
+fn stdlib_strings() {
+    let code = r#"
+        .include "std.tasm"
+
+        .data
+        hello:
+            %string "hello"
+        world:
+            %string ", wörld 👍"
+
+        .bss
+        buffer:
+            %space 24 * 20
+
+        .code
+        __start:
+            la $a0, hello
+            call strLen
+            mov $s0, $a0
+
+            la $a0, buffer
+            la $a1, hello
+            call strCopy
+            la $a1, world
+            call strConcat
+            mov $s1, $a0
+
+            call strLen
+            mov $s2, $a0
+            halt
+    "#;
+
+    let mut vm = run_with_library(code);
+    assert_eq!(vm.read(Register::S0), 5);
+    assert_eq!(read_string(&mut vm, Register::S1), "hello, wörld 👍");
+    assert_eq!(vm.read(Register::S2), 5 + 7 + 2 + 3);
+}
+
+#[test]
+fn stdlib_str_compare() {
+    let pairs = [("abc", "abc"), ("abc", "abd"), ("abd", "abc"), ("ab", "abc"), ("abc", "ab"),
+                 ("", ""), ("", "a"), ("b", "abc")];
+
+    for &(lhs, rhs) in &pairs {
+        let code = format!(".include \"string.tasm\"\n.data\nlhs:\n%string \"{}\"\nrhs:\n\
+                            %string \"{}\"\n.code\n__start:\nla $a0, lhs\nla $a1, rhs\n\
+                            call strCompare\nhalt\n",
+                           lhs,
+                           rhs);
+
+        let mut vm = run_with_library(&code);
+        assert_eq!(vm.read(Register::A0), lhs.cmp(rhs) as isize, "{:?}", (lhs, rhs));
+    }
+}
+
+#[test]
+fn stdlib_decode_chars() {
+    let mut trits = vec![Trit::Zero; 200 * TRYTE_SIZE];
+    let len = text::encode_str(trits.as_mut_ptr(), "aé€\u{ffff}\u{10000}👍\u{10ffff}z");
+    let mut trytes: Vec<isize> = trits[WORD_SIZE..]
+        .chunks(TRYTE_SIZE)
+        .take(len)
+        .map(|tryte| unsafe { ternary::to_int(tryte.as_ptr(), TRYTE_ISIZE) })
+        .collect();
+
+    // malformed sequences and invalid code points have to decode the same way too
+    let mut rng: StdRng = SeedableRng::from_seed(&[6, 12, 24][..]);
+    trytes.extend((0..300).map(|_| rng.gen_range(-364, 365)));
+
+    let list: Vec<String> = trytes.iter().map(|n| n.to_string()).collect();
+    let code = format!(r#"
+        .include "text.tasm"
+
+        .data
+        string:
+            %word {}
+            %tryte {}
+            %word 0
+
+        .bss
+        points:
+            %space 24 * {}
+
+        .code
+        __start:
+            la $s4, string
+            la $s5, points
+            lw $s1, $s4, 0
+            mov $s0, $s4
+            addi $s0, 24
+            mov $s2, $s5
+            li $s3, 0
+
+        1:  cmp $t0, $s1, $zero
+            jT0 $t0, 2f
+            mov $a0, $s0
+            call decodeChar
+            sw $s2, $a0, 0
+            addi $s2, 24
+            inc $s3
+            sub $s1, $s1, $a1
+            muli $a1, 6
+            add $s0, $s0, $lo
+            b 1b
+
+        2:  halt
+    "#,
+                       trytes.len(),
+                       list.join(", "),
+                       trytes.len());
+
+    let mut vm = run_with_library(&code);
+    let string = vm.read(Register::S4);
+    let points = vm.read(Register::S5);
+    let (expected, _) = text::decode_str(unsafe { vm.memory.offset(string) });
+
+    let decoded: Vec<isize> = (0..vm.read(Register::S3))
+        .map(|i| unsafe { ternary::to_int(vm.memory.offset(points + i * 24), WORD_ISIZE) })
+        .collect();
+    let expected: Vec<isize> = expected.chars().map(|c| c as isize).collect();
+    assert_eq!(decoded, expected);
+    assert!(expected.contains(&0xfffd));
+}
+
+#[test]
+fn stdlib_int_to_string() {
+    let values = [0, 1, -1, 9, 10, 42, -1234, 99999999999, 100000000000, 141214768240,
+                  -141214768240];
+
+    for &n in &values {
+        let code = format!(".include \"format.tasm\"\n.bss\ndecimal:\n%space 24 * 7\nternary:\n\
+                            %space 24 * 7\n.code\n__start:\nla $a0, decimal\nli $a1, {0}\n\
+                            call intToDecimal\nmov $s0, $a0\nla $a0, ternary\nli $a1, {0}\n\
+                            call intToTernary\nmov $s1, $a0\nhalt\n",
+                           n);
+
+        let mut word = EMPTY_WORD;
+        unsafe { ternary::from_int(mut_ptr!(word), n, WORD_ISIZE) };
+        let trits = unsafe { ternary::to_str(ptr!(word), WORD_ISIZE) };
+        let trimmed = trits.trim_start_matches('0');
+        let expected_ternary = if trimmed.is_empty() { "0" } else { trimmed };
+
+        let mut vm = run_with_library(&code);
+        assert_eq!(read_string(&mut vm, Register::S0), n.to_string());
+        assert_eq!(read_string(&mut vm, Register::S1), expected_ternary);
+    }
+}
+
+#[test]
+fn stdlib_memory() {
+    let code = r#"
+        .include "memory.tasm"
+
+        .data
+        src:
+            %word 0t1T01T01T01T01T01T01T01T, 0t11TT0011TT0011TT0011TT00, 0t10T10T10T10T10T10T10T10T
+
+        .bss
+        dest:
+            %space 24 * 3
+        filled:
+            %space 24 * 2
+
+        .code
+        __start:
+            la $a0, dest
+            li $a1, -1
+            li $a2, 72
+            call memSet
+
+            la $a0, dest
+            la $a1, src
+            li $a2, 61
+            call memCopy
+
+            la $a0, filled
+            li $a1, 1
+            li $a2, 29
+            call memSet
+
+            la $s0, src
+            la $s1, dest
+            la $s2, filled
+            halt
+    "#;
+
+    let mut vm = run_with_library(code);
+    let src = read_trits(&mut vm, Register::S0, 72);
+    let dest = read_trits(&mut vm, Register::S1, 72);
+    let filled = read_trits(&mut vm, Register::S2, 48);
+
+    // trits are printed most significant first, so the first ones in memory come last
+    assert_eq!(&dest[11..], &src[11..]);
+    assert_eq!(&dest[..11], "TTTTTTTTTTT");
+    assert_eq!(&filled[19..], &"1".repeat(29)[..]);
+    assert_eq!(&filled[..19], &"0".repeat(19)[..]);
+}
+
+#[test]
+fn stdlib_heap() {
+    let code = r#"
+        .include "heap.tasm"
+
+        .bss
+        heap:
+            %space 24 * 12
+
+        .code
+        __start:
+            la $a0, heap
+            li $a1, 24 * 12
+            call heapInit
+
+            li $a0, 30
+            call heapAlloc
+            mov $s0, $a0
+
+            li $a0, 24
+            call heapAlloc
+            mov $s1, $a0
+
+            li $a0, 24 * 20
+            call heapAlloc
+            mov $s2, $a0
+
+            mov $a0, $s0
+            call heapFree
+            li $a0, 1
+            call heapAlloc
+            mov $s3, $a0
+
+            mov $a0, $s1
+            call heapFree
+            mov $a0, $s3
+            call heapFree
+            li $a0, 24 * 11
+            call heapAlloc
+            mov $s4, $a0
+
+            la $s5, heap
+            halt
+    "#;
+
+    let mut vm = run_with_library(code);
+    let heap = vm.read(Register::S5);
+    assert_eq!(vm.read(Register::S0), heap + 24);
+    assert_eq!(vm.read(Register::S1), heap + 96);
+    assert_eq!(vm.read(Register::S2), 0);
+    assert_eq!(vm.read(Register::S3), heap + 24);
+    assert_eq!(vm.read(Register::S4), heap + 24);
+}
+
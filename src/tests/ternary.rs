@@ -140,6 +140,31 @@ fn ternary_lowest_trit() {
 
         ternary::from_str(mut_ptr!(trits), "00000T");
         assert_eq!(ternary::lowest_trit(ptr!(trits), 6), Neg);
+
+        // only the most significant trit is set
+        ternary::from_str(mut_ptr!(trits), "T00000");
+        assert_eq!(ternary::lowest_trit(ptr!(trits), 6), Neg);
+    }
+}
+
+#[test]
+fn ternary_compare() {
+    unsafe {
+        let mut a = EMPTY_TRYTE;
+        let mut b = EMPTY_TRYTE;
+
+        ternary::from_str(mut_ptr!(a), "0T0010");
+        ternary::from_str(mut_ptr!(b), "0T0010");
+        assert_eq!(ternary::compare(ptr!(a), ptr!(b), 6), Zero);
+
+        ternary::from_str(mut_ptr!(b), "0T0011");
+        assert_eq!(ternary::compare(ptr!(a), ptr!(b), 6), Neg);
+
+        // the trits differ only in the most significant position
+        ternary::from_str(mut_ptr!(a), "100000");
+        ternary::from_str(mut_ptr!(b), "T00000");
+        assert_eq!(ternary::compare(ptr!(a), ptr!(b), 6), Pos);
+        assert_eq!(ternary::compare(ptr!(b), ptr!(a), 6), Neg);
     }
 }
 
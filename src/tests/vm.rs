@@ -1,6 +1,17 @@
+use std::cell::Cell;
+use std::io::Cursor;
+use std::slice;
+use std::rc::Rc;
+
+use jit::BlockCompiler;
+use ternary;
+use text;
+use types::*;
 use registers::Register;
+use program::DecodedProgram;
+use syscalls::Syscall;
 use util::*;
-use vm::VM;
+use vm::{VM, VMError};
 
 fn test_program<F: Fn(&mut VM)>(code: &str, f: F) {
     match vm_from_code(code) {
@@ -161,6 +172,10 @@ fn vm_cmp() {
             cmp $t2, $a2, $a0
             cmp $t3, $a2, $a2
 
+            movw $a3, 0t1T0000000000000000000000
+            movw $a4, 0t01T000000000000000000000
+            cmp $t4, $a3, $a4
+
             halt
     "#;
 
@@ -169,6 +184,7 @@ fn vm_cmp() {
         assert_eq!(vm.read(Register::T1), -1);
         assert_eq!(vm.read(Register::T2), 1);
         assert_eq!(vm.read(Register::T3), 0);
+        assert_eq!(vm.read(Register::T4), 1);
     });
 }
 
@@ -212,3 +228,490 @@ fn vm_jt() {
         assert_eq!(vm.read(Register::A0), 123);
     });
 }
+
+#[test]
+fn vm_self_modifying_store() {
+    let code = r#"
+        __start:
+            mova $t0, patch
+            mova $t1, replacement
+            lw $t2, $t1, 0
+            movi $s1, 0
+
+        patch:
+            addi $s0, 1
+            j1 $s1, done
+            sw $t0, $t2, 0
+            movi $s1, 1
+            jmp patch
+
+        done:
+            halt
+
+        replacement:
+            addi $s0, 100
+    "#;
+
+    test_program(code, |ref mut vm| {
+        assert_eq!(vm.read(Register::S0), 101);
+    });
+
+    let mut vm = vm_from_code(code).unwrap();
+    vm.set_caching(false);
+    vm.run();
+    assert_eq!(vm.read(Register::S0), 101);
+}
+
+#[test]
+fn vm_pseudo_instructions() {
+    let code = r#"
+        __start:
+            li $a0, 100
+            li $a1, 1000000
+            la $t0, __start
+            neg $a2, $a0
+            inc $a2
+            dec $a2
+            dec $a2
+            sub $a3, $a1, $a0
+            sub $a1, $a1, $a0
+            sub $a4, $a4, $a4
+            nop
+            b end
+            movi $a0, 0
+
+        end:
+            halt
+    "#;
+
+    test_program(code, |ref mut vm| {
+        assert_eq!(vm.read(Register::A0), 100);
+        assert_eq!(vm.read(Register::A1), 999900);
+        assert_eq!(vm.read(Register::A2), -101);
+        assert_eq!(vm.read(Register::A3), 999900);
+        assert_eq!(vm.read(Register::A4), 0);
+    });
+}
+
+#[test]
+fn vm_pseudo_branches() {
+    let code = r#"
+        __start:
+            li $t0, 5
+            li $t1, 7
+            li $s0, 0
+
+            beq $t0, $t0, equal
+            li $s0, 100
+        equal:
+            inc $s0
+
+            blt $t1, $t0, wrong
+            blt $t0, $t1, less
+            li $s0, 100
+        less:
+            inc $s0
+
+            bgt $t0, $t1, wrong
+            bgt $t1, $t0, greater
+        wrong:
+            li $s0, 100
+        greater:
+            inc $s0
+            halt
+    "#;
+
+    test_program(code, |ref mut vm| {
+        assert_eq!(vm.read(Register::S0), 3);
+    });
+}
+
+#[test]
+fn vm_data_directives() {
+    let code = r#"
+        .data
+        list:
+            %word 1, -2, 3
+        trytes:
+            %array %tryte 5 x 4
+        padding:
+            %tryte 1
+            %align 48
+            %word 9
+        padding_end:
+        gap:
+            %space 12
+            %half 4
+
+        .rodata
+        message:
+            %word 42
+
+        .code
+        __start:
+            mova $s0, list
+            lw $a0, $s0, 0
+            lw $a1, $s0, 24
+            lw $a2, $s0, 48
+            mova $s0, trytes
+            lw $a3, $s0, 0
+            mova $s0, message
+            lw $a4, $s0, 0
+            li $a5, sizeof gap
+            mova $t1, padding
+            mova $t2, padding_end
+            mova $t3, buffer
+            halt
+
+        .bss
+        buffer:
+            %space 48
+    "#;
+
+    let mut program = DecodedProgram::new();
+    program.read_str(code).unwrap();
+    let image_size = program.image_size();
+    assert_eq!(program.size(), image_size + 48);
+
+    test_program(code, |ref mut vm| {
+        assert_eq!(vm.read(Register::A0), 1);
+        assert_eq!(vm.read(Register::A1), -2);
+        assert_eq!(vm.read(Register::A2), 3);
+        assert_eq!(vm.read(Register::A3), 5 * (1 + 729 + 729 * 729 + 729 * 729 * 729));
+        assert_eq!(vm.read(Register::A4), 42);
+        assert_eq!(vm.read(Register::A5), 24);
+
+        let padding = vm.read(Register::T1);
+        let word_addr = vm.read(Register::T2) - 24;
+        assert!(word_addr > padding);
+        assert_eq!(word_addr % 48, 0);
+        assert_eq!(unsafe { ternary::to_int(vm.memory.offset(word_addr), WORD_ISIZE) }, 9);
+
+        assert_eq!(vm.read(Register::T3) as usize, image_size);
+    });
+}
+
+#[test]
+fn vm_rodata_write() {
+    let code = r#"
+        .rodata
+        message:
+            %word 42
+
+        .code
+        __start:
+            mova $s0, message
+            movi $a0, 1
+            sw $s0, $a0, 0
+            movi $a1, 1
+            halt
+    "#;
+
+    let mut vm = vm_from_code(code).unwrap();
+    vm.run();
+
+    let addr = vm.read(Register::S0) as Addr;
+    assert_eq!(vm.error(), Some(&VMError::ReadOnlyWrite(addr)));
+    assert_eq!(vm.read(Register::A1), 0);
+    assert_eq!(unsafe { ternary::to_int(vm.memory.offset(addr as isize), WORD_ISIZE) }, 42);
+}
+
+#[test]
+fn vm_section_errors() {
+    let code = ".rodata\n    halt\n.bss\n    %space 3\n    %word 1\n.code\n__start:\n    halt\n";
+
+    match vm_from_code(code) {
+        Ok(_) => panic!("expected errors"),
+        Err(report) => {
+            let message = report.to_string();
+            assert!(message.contains("<input>:2:5: instructions are not allowed in .rodata"),
+                    "{}",
+                    message);
+            assert!(message.contains("<input>:5:6: only `%space` and `%align` are allowed in .bss"),
+                    "{}",
+                    message);
+        }
+    }
+}
+
+#[test]
+fn vm_custom_syscall() {
+    let code = r#"
+        __start:
+            movi $t0, 7
+            movi $a0, 20
+            syscall
+            movi $t0, 1
+            syscall
+            halt
+    "#;
+
+    let mut vm = vm_from_code(code).unwrap();
+    vm.register_syscall(7, |vm: &mut VM| {
+        let n = vm.read(Register::A0);
+        vm.write(Register::A0, n * 2 + 1);
+    });
+
+    let calls = Rc::new(Cell::new(0));
+    let counted = calls.clone();
+    vm.register_syscall(Syscall::PrintDecimal.number(),
+                        move |_: &mut VM| counted.set(counted.get() + 1));
+
+    vm.run();
+    assert_eq!(vm.error(), None);
+    assert_eq!(vm.read(Register::A0), 41);
+    assert_eq!(calls.get(), 1);
+}
+
+#[test]
+fn vm_exit_syscall() {
+    let code = r#"
+        __start:
+            movi $t0, 1
+            movi $a0, 5
+            syscall
+            movi $t0, 4
+            movi $a0, 3
+            syscall
+            movi $t0, 1
+            syscall
+            halt
+    "#;
+
+    let mut vm = vm_from_code(code).unwrap();
+    let output = SharedBuffer::new();
+    vm.set_output(output.clone());
+    vm.run();
+
+    assert_eq!(vm.error(), None);
+    assert_eq!(vm.exit_status(), Some(3));
+    assert_eq!(output.contents(), "5");
+
+    let mut vm = vm_from_code("__start:\n    halt\n").unwrap();
+    vm.run();
+    assert_eq!(vm.exit_status(), None);
+}
+
+#[test]
+fn vm_unknown_syscall() {
+    let code = r#"
+        __start:
+            movi $t0, -3
+            syscall
+            movi $a0, 1
+            halt
+    "#;
+
+    let mut vm = vm_from_code(code).unwrap();
+    vm.run();
+    assert_eq!(vm.error(), Some(&VMError::UnknownSyscall(-3)));
+    assert_eq!(vm.read(Register::A0), 0);
+
+    let mut vm = vm_from_code("__start:\n    movi $t0, 2\n    syscall\n    halt\n").unwrap();
+    vm.unregister_syscall(Syscall::PrintTernary.number());
+    vm.run();
+    assert_eq!(vm.error().map(|e| e.to_string()), Some("unknown syscall 2".to_string()));
+
+    assert_eq!(Syscall::from_number(5), Some(Syscall::PutChar));
+    assert_eq!(Syscall::from_number(9), None);
+}
+
+#[test]
+fn vm_read_syscalls() {
+    let code = r#"
+        .bss
+        buffer:
+            %space 24 + 6 * 4
+
+        .code
+        __start:
+            mova $a0, buffer
+            movi $a1, 24 + 6 * 4
+            movi $t0, 6
+            syscall
+            mov $s0, $a0
+            mov $s1, $a1
+
+            movi $t0, 7
+            syscall
+            mov $s2, $a0
+            syscall
+            mov $s3, $a0
+
+            movi $t0, 8
+            syscall
+            mov $s4, $a0
+            syscall
+            mov $s5, $a0
+            mov $fp, $a1
+            syscall
+            mov $sp, $a1
+
+            movi $t0, 6
+            mova $a0, buffer
+            movi $a1, 24 + 6 * 4
+            syscall
+            mova $a2, buffer
+            halt
+    "#;
+
+    let mut vm = vm_from_code(code).unwrap();
+    vm.set_input(Cursor::new("héllo, world\r\nñ-0t1T0\n-1234\n12x\n".as_bytes()));
+    vm.run();
+    assert_eq!(vm.error(), None);
+
+    assert_eq!(vm.read(Register::S0), 4);
+    assert_eq!(vm.read(Register::S1), 1);
+    let addr = vm.read(Register::A2);
+    assert_eq!(text::decode_str(unsafe { vm.memory.offset(addr) }), ("héll".to_string(), 4));
+
+    assert_eq!(vm.read(Register::S2), 'ñ' as isize);
+    assert_eq!(vm.read(Register::S3), '-' as isize);
+    assert_eq!(vm.read(Register::S4), 6);
+    assert_eq!(vm.read(Register::S5), -1234);
+    assert_eq!(vm.read(Register::FP), 1);
+    assert_eq!(vm.read(Register::SP), 0);
+    assert_eq!(vm.read(Register::A0), -1);
+    assert_eq!(vm.read(Register::A1), 0);
+}
+
+#[test]
+fn vm_read_char_malformed() {
+    let code = r#"
+        __start:
+            movi $t0, 7
+            syscall
+            mov $s0, $a0
+            syscall
+            mov $s1, $a0
+            syscall
+            mov $s2, $a0
+            syscall
+            mov $s3, $a0
+            syscall
+            mov $s4, $a0
+            syscall
+            mov $s5, $a0
+            syscall
+            halt
+    "#;
+
+    // a lead byte cut short by an ASCII byte, by another character, by a stray continuation
+    // byte and by the end of input
+    let input: &[u8] = &[0xc3, b'A', 0xe2, 0x82, b'B', 0x80, 0xe2, 0x82];
+
+    let mut vm = vm_from_code(code).unwrap();
+    vm.set_input(Cursor::new(input));
+    vm.run();
+    assert_eq!(vm.error(), None);
+
+    let replacement = '\u{fffd}' as isize;
+    let chars: Vec<isize> = [Register::S0, Register::S1, Register::S2, Register::S3, Register::S4,
+                             Register::S5, Register::A0]
+        .iter()
+        .map(|&r| vm.read(r))
+        .collect();
+    assert_eq!(chars,
+               vec![replacement, 'A' as isize, replacement, 'B' as isize, replacement,
+                    replacement, -1]);
+}
+
+#[test]
+fn vm_read_line_buffer() {
+    let code = "__start:\n    movi $a0, 48\n    movi $a1, 12\n    movi $t0, 6\n    syscall\n    \
+                halt\n";
+
+    let mut vm = vm_from_code(code).unwrap();
+    vm.set_input(Cursor::new("hi\n".as_bytes()));
+    vm.run();
+    assert_eq!(vm.error(), Some(&VMError::InvalidBuffer(48, 12)));
+
+    // lines land in memory the way stores do, so they can't overwrite read-only data
+    let code = ".rodata\nbuffer:\n    %space 48\n.code\n__start:\n    mova $a0, buffer\n    \
+                movi $a1, 48\n    movi $t0, 6\n    syscall\n    halt\n";
+
+    let mut vm = vm_from_code(code).unwrap();
+    vm.set_input(Cursor::new("hi\n".as_bytes()));
+    vm.run();
+    match vm.error() {
+        Some(&VMError::ReadOnlyWrite(_)) => {}
+        error => panic!("expected a read-only write, got {:?}", error),
+    }
+}
+
+#[test]
+fn vm_syscall_store_invalidates_cache() {
+    let code = r#"
+        __start:
+            mova $a1, patch
+            mova $a2, replacement
+            movi $s1, 0
+
+        patch:
+            addi $s0, 1
+            j1 $s1, done
+            movi $t0, 9
+            syscall
+            movi $s1, 1
+            jmp patch
+
+        done:
+            halt
+
+        replacement:
+            addi $s0, 100
+    "#;
+
+    let store = |vm: &mut VM| {
+        let dest = vm.read(Register::A1) as Addr;
+        let src = vm.read(Register::A2) as isize;
+        let trits = unsafe { slice::from_raw_parts(vm.memory.offset(src), WORD_SIZE) }.to_vec();
+        vm.store_trits(dest, &trits);
+    };
+
+    let mut vm = vm_from_code(code).unwrap();
+    vm.register_syscall(9, store);
+    vm.run();
+    assert_eq!(vm.read(Register::S0), 101);
+
+    let mut vm = vm_from_code(code).unwrap();
+    vm.register_syscall(9, store);
+    BlockCompiler::new().run(&mut vm);
+    assert_eq!(vm.read(Register::S0), 101);
+}
+
+#[test]
+fn vm_narrow_load_store() {
+    // the addresses are past what a tryte or half can hold, so they have to be read as words
+    let code = r#"
+        .bss
+        padding:
+            %space 24 * 12000
+        tryte:
+            %space 6
+            %align 24
+        half:
+            %space 12
+
+        .code
+        __start:
+            mova $s0, tryte
+            mova $s1, half
+            movi $a0, -300
+            st $s0, $a0, 0
+            movi $a0, 260000
+            sh $s1, $a0, 0
+            lt $a1, $s0, 0
+            lh $a2, $s1, 0
+            halt
+    "#;
+
+    test_program(code, |ref mut vm| {
+        assert_eq!(vm.read(Register::A1), -300);
+        assert_eq!(vm.read(Register::A2), 260000);
+
+        let tryte = vm.read(Register::S0);
+        assert!(tryte > HALF_MAX);
+        assert_eq!(unsafe { ternary::to_int(vm.memory.offset(tryte), TRYTE_ISIZE) }, -300);
+    });
+}
@@ -62,6 +62,18 @@ pub fn decode_str(trits: *const Trit) -> (String, usize) {
     (s, i)
 }
 
+// the integer made of the character's encoded trytes, so storing it writes the same trits
+pub fn char_value(c: char) -> Option<isize> {
+    let mut word = EMPTY_WORD;
+    let len = unsafe { encode_char(mut_ptr!(word), c) };
+
+    if len == 0 {
+        None
+    } else {
+        Some(unsafe { ternary::to_int(ptr!(word), (len * TRYTE_SIZE) as isize) })
+    }
+}
+
 pub unsafe fn encode_char(trits: *mut Trit, c: char) -> usize {
     let codepoint = c as u32;
     let mut word = EMPTY_WORD;
@@ -1,11 +1,17 @@
 use rand::Rng;
+use std::cell::RefCell;
+use std::io;
 use std::io::prelude::*;
+use std::rc::Rc;
 
 use ternary;
 use types::*;
 use vm::VM;
 use program::DecodedProgram;
 use program::EncodedProgram;
+use program::object::{Image, ObjectFile};
+use program::parser::ParseError;
+use program::report::ErrorReport;
 
 pub fn next_aligned_addr(addr: Addr, alignment: usize) -> Addr {
     let rem = addr % alignment;
@@ -24,26 +30,168 @@ pub fn random_word<R: Rng>(trits: *mut Trit, rng: &mut R, len: isize) {
     }
 }
 
-pub fn vm_from_code(code: &str) -> Result<VM, String> {
-    let mut program = DecodedProgram::new();
-    try!(program.read_str(code).map_err(|e| format!("{:?}", e)));
+// pulls `-I dir` and `-Idir` options out of command-line arguments
+pub fn split_include_args<I: Iterator<Item = String>>(args: I) -> (Vec<String>, Vec<String>) {
+    let mut include_paths = Vec::new();
+    let mut rest = Vec::new();
+    let mut args = args;
 
-    let vm = VM::new(program.size());
+    while let Some(arg) = args.next() {
+        if arg == "-I" {
+            include_paths.extend(args.next());
+        } else if arg.starts_with("-I") {
+            include_paths.push(arg[2..].to_string());
+        } else {
+            rest.push(arg);
+        }
+    }
 
-    let mut encoder = EncodedProgram::new(vm.memory, vm.memory_size);
-    try!(encoder.encode(program).map_err(|e| format!("{:?}", e)));
+    (include_paths, rest)
+}
+
+// a `Write` that can still be read from after a clone of it has been handed to a VM
+#[derive(Clone, Debug, Default)]
+pub struct SharedBuffer {
+    bytes: Rc<RefCell<Vec<u8>>>,
+}
+
+impl SharedBuffer {
+    pub fn new() -> SharedBuffer {
+        SharedBuffer::default()
+    }
+
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.bytes.borrow()).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
 
-    Ok(vm)
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
 
-pub fn vm_from_reader<R: Read>(reader: R) -> Result<VM, String> {
+pub fn vm_from_code(code: &str) -> Result<VM, ErrorReport> {
     let mut program = DecodedProgram::new();
-    try!(program.read(reader).map_err(|e| format!("{:?}", e)));
+    let result = program.read_str(code);
+    vm_from_program(program, result)
+}
+
+pub fn vm_from_file(path: &str) -> Result<VM, ErrorReport> {
+    let mut program = DecodedProgram::new();
+    let result = program.read_file(path);
+    vm_from_program(program, result)
+}
+
+pub fn vm_from_reader<R: Read>(reader: R) -> Result<VM, ErrorReport> {
+    let mut program = DecodedProgram::new();
+    let result = program.read(reader);
+    vm_from_program(program, result)
+}
+
+pub fn object_from_code(code: &str) -> Result<ObjectFile, ErrorReport> {
+    let mut program = DecodedProgram::new();
+    program.set_relocatable(true);
+    let result = program.read_str(code);
+    object_from_program(program, result)
+}
+
+fn object_from_program(program: DecodedProgram,
+                       read_result: Result<(), Vec<ParseError>>)
+                       -> Result<ObjectFile, ErrorReport> {
+    let mut report = ErrorReport::new();
+    if let Err(errors) = read_result {
+        report.add_parse_errors(errors);
+    }
 
     let vm = VM::new(program.size());
+    let mut encoder = EncodedProgram::new(vm.memory, vm.memory_size);
+    match encoder.encode_object(program) {
+        Ok(object) if !report.has_errors() => Ok(object),
+        Ok(_) => Err(report),
+        Err(errors) => {
+            report.add_encode_errors(errors);
+            Err(report)
+        }
+    }
+}
+
+// the encoded form of a source file as text, so two versions of it can be compared. files
+// that aren't whole programs are encoded as objects instead
+pub fn encode_source(path: &str,
+                     source: &str,
+                     include_paths: &[String])
+                     -> Result<String, ErrorReport> {
+    let read = |relocatable| {
+        let mut program = DecodedProgram::new();
+        program.set_relocatable(relocatable);
+        for dir in include_paths {
+            program.add_include_path(dir);
+        }
+
+        let result = program.read_str_as(path, source);
+        (program, result)
+    };
+
+    let (program, result) = read(false);
+    let (vm, report) = assemble(program, result);
+    if !report.has_errors() {
+        return Ok(unsafe { ternary::to_str(vm.memory, vm.memory_size as isize) });
+    }
+
+    // line numbers change with formatting while the encoding doesn't
+    let (program, result) = read(true);
+    object_from_program(program, result)
+        .map(|object| ObjectFile { lines: None, ..object }.to_string())
+        .map_err(|_| report)
+}
+
+pub fn vm_from_image(image: &Image) -> VM {
+    let mut vm = VM::new(image.memory_size);
+    unsafe { ternary::copy(vm.memory, image.trits.as_ptr(), image.trits.len() as isize) };
+
+    if let Some((start, end)) = image.read_only {
+        vm.protect(start, end);
+    }
+
+    vm
+}
+
+fn vm_from_program(program: DecodedProgram,
+                   read_result: Result<(), Vec<ParseError>>)
+                   -> Result<VM, ErrorReport> {
+    let (vm, report) = assemble(program, read_result);
+    if report.has_errors() {
+        Err(report)
+    } else {
+        Ok(vm)
+    }
+}
+
+pub fn assemble(program: DecodedProgram,
+                read_result: Result<(), Vec<ParseError>>)
+                -> (VM, ErrorReport) {
+    let mut report = ErrorReport::new();
+    if let Err(errors) = read_result {
+        report.add_parse_errors(errors);
+    }
+
+    let mut vm = VM::new(program.size());
 
     let mut encoder = EncodedProgram::new(vm.memory, vm.memory_size);
-    try!(encoder.encode(program).map_err(|e| format!("{:?}", e)));
+    if let Err(errors) = encoder.encode(program) {
+        report.add_encode_errors(errors);
+    }
+
+    if let Some((start, end)) = encoder.read_only_range() {
+        vm.protect(start, end);
+    }
 
-    Ok(vm)
+    report.add_encode_warnings(encoder.take_warnings());
+    (vm, report)
 }
@@ -1,20 +1,55 @@
 use libc::{malloc, free};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
 use std::mem::transmute;
 
 use ternary;
 use types::*;
-use opcodes::Opcode;
 use registers::{Register, REGISTER_COUNT};
-use syscalls::Syscall;
+use syscalls::{SyscallHandler, DEFAULT_SYSCALLS};
+use cache::{CachedInstruction, CachedOp, InstructionCache};
 
 pub const PROGRAM_MAGIC_NUMBER: isize = 47330224520; // 1TTTTT1TTTTT1TTTTT1TTTTT
 
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VMError {
+    ReadOnlyWrite(Addr),
+    UnknownSyscall(isize),
+    Io(String),
+    InvalidBuffer(isize, isize),
+}
+
+impl fmt::Display for VMError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VMError::ReadOnlyWrite(addr) => write!(f, "write to read-only memory at {}", addr),
+            VMError::UnknownSyscall(n) => write!(f, "unknown syscall {}", n),
+            VMError::Io(ref message) => write!(f, "i/o error: {}", message),
+            VMError::InvalidBuffer(addr, size) => {
+                write!(f, "invalid buffer of {} trits at {}", size, addr)
+            }
+        }
+    }
+}
+
 pub struct VM {
     pub registers: [Word; REGISTER_COUNT],
     pub memory: *mut Trit,
     pub memory_size: usize,
     pub pc: Addr,
     pub running: bool,
+    cache: InstructionCache,
+    caching: bool,
+    code_writes: usize,
+    protected: Vec<(Addr, Addr)>,
+    error: Option<VMError>,
+    exit_status: Option<i32>,
+    syscalls: HashMap<isize, Box<dyn SyscallHandler>>,
+    output: Box<dyn Write>,
+    input: Box<dyn BufRead>,
 }
 
 impl VM {
@@ -27,13 +62,28 @@ impl VM {
 
         unsafe { ternary::clear(memory, memory_size as isize) };
 
-        VM {
+        let mut vm = VM {
             registers: registers,
             memory: memory,
             memory_size: memory_size,
             pc: 0,
             running: false,
+            cache: InstructionCache::new(memory_size),
+            caching: true,
+            code_writes: 0,
+            protected: Vec::new(),
+            error: None,
+            exit_status: None,
+            syscalls: HashMap::new(),
+            output: Box::new(io::stdout()),
+            input: Box::new(BufReader::new(io::stdin())),
+        };
+
+        for &syscall in DEFAULT_SYSCALLS.iter() {
+            vm.register_syscall(syscall.number(), syscall);
         }
+
+        vm
     }
 
     pub fn src(&self, r: Register) -> *const Trit {
@@ -66,175 +116,258 @@ impl VM {
             unsafe { ternary::to_int(self.memory.offset(WORD_ISIZE), WORD_ISIZE) } as Addr;
         self.pc = pc_start;
 
+        self.cache.clear();
+        self.error = None;
+        self.exit_status = None;
         self.running = true;
     }
 
+    // stores into [start, end) stop the program instead of changing memory
+    pub fn protect(&mut self, start: Addr, end: Addr) {
+        self.protected.push((start, end));
+    }
+
+    pub fn error(&self) -> Option<&VMError> {
+        self.error.as_ref()
+    }
+
+    pub fn fault(&mut self, error: VMError) {
+        self.error = Some(error);
+        self.running = false;
+    }
+
+    // stops the program as if it had asked to exit with `status`, which the caller decides what
+    // to do with
+    pub fn halt(&mut self, status: i32) {
+        self.exit_status = Some(status);
+        self.running = false;
+    }
+
+    pub fn exit_status(&self) -> Option<i32> {
+        self.exit_status
+    }
+
+    // where syscalls write to and read from, stdout and stdin unless they're redirected
+    pub fn set_output<W: Write + 'static>(&mut self, output: W) {
+        self.output = Box::new(output);
+    }
+
+    pub fn set_input<R: Read + 'static>(&mut self, input: R) {
+        self.input = Box::new(BufReader::new(input));
+    }
+
+    pub fn output(&mut self) -> &mut dyn Write {
+        &mut *self.output
+    }
+
+    pub fn input(&mut self) -> &mut dyn BufRead {
+        &mut *self.input
+    }
+
+    // anything left buffered when the program stops is written out, which fails the program if
+    // nothing else already has
+    pub fn flush_output(&mut self) {
+        if let Err(e) = self.output.flush() {
+            if self.error.is_none() {
+                self.fault(VMError::Io(e.to_string()));
+            }
+        }
+    }
+
+    // replaces whatever handled `number` before, including the default syscalls
+    pub fn register_syscall<H: SyscallHandler + 'static>(&mut self, number: isize, handler: H) {
+        self.syscalls.insert(number, Box::new(handler));
+    }
+
+    pub fn unregister_syscall(&mut self, number: isize) {
+        self.syscalls.remove(&number);
+    }
+
+    // copies trits into memory the way a store would, for syscalls that fill in buffers
+    pub fn store_trits(&mut self, addr: Addr, trits: &[Trit]) {
+        let len = trits.len() as isize;
+        if self.is_protected(addr as isize, len) {
+            self.fault(VMError::ReadOnlyWrite(addr));
+            return;
+        }
+
+        unsafe { ternary::copy(self.memory.offset(addr as isize), trits.as_ptr(), len) };
+        self.invalidate(addr as isize, len);
+    }
+
+    fn is_protected(&self, addr: isize, len: isize) -> bool {
+        self.protected
+            .iter()
+            .any(|&(start, end)| addr < end as isize && addr + len > start as isize)
+    }
+
     pub fn run(&mut self) {
         self.init();
 
         while self.running {
             unsafe { self.step() };
         }
+
+        self.flush_output();
     }
 
-    unsafe fn next_inst(&mut self) -> Word {
-        let mut inst = [Trit::Zero; WORD_SIZE];
-        let location = self.memory.offset(self.pc as isize);
-        ternary::copy(mut_ptr!(inst), location, WORD_ISIZE);
+    pub unsafe fn step(&mut self) {
+        let pc = self.pc;
+        let inst = self.fetch(pc);
+        self.pc += inst.size;
+        self.execute(inst.op);
+        self.clear(Register::ZERO);
+    }
+
+    pub(crate) unsafe fn fetch(&mut self, pc: Addr) -> CachedInstruction {
+        if !self.caching {
+            return CachedInstruction::decode(self.memory.offset(pc as isize));
+        }
+
+        if let Some(inst) = self.cache.get(pc) {
+            return inst;
+        }
 
-        self.pc += WORD_SIZE;
+        let inst = CachedInstruction::decode(self.memory.offset(pc as isize));
+        self.cache.insert(pc, inst);
         inst
     }
 
-    pub unsafe fn step(&mut self) {
-        let inst = self.next_inst();
-        let (t0, t1, t2, t3) = ternary::read_trytes(ptr!(inst));
-        let opcode = Opcode::from(t0);
-
-        match opcode {
-            Opcode::Mov => {
-                self.op_mov(Register::from(t1), Register::from(t2));
-            }
+    pub fn invalidate_cache(&mut self) {
+        self.cache.clear();
+    }
 
-            Opcode::Movi => {
-                let half = inst_half(inst);
-                self.op_movi(Register::from(t1), half);
-            }
+    fn invalidate(&mut self, addr: isize, len: isize) {
+        if self.cache.invalidate(addr, len) {
+            self.code_writes += 1;
+        }
+    }
 
-            Opcode::Movw => {
-                let word = self.next_inst();
-                self.op_movw(Register::from(t1), word);
-            }
+    // counts writes that replaced cached instructions, so anything built from fetched
+    // instructions can tell when it's gone stale
+    pub(crate) fn code_writes(&self) -> usize {
+        self.code_writes
+    }
 
-            Opcode::Mova => {
-                let inst = self.next_inst();
-                let addr = inst_addr(inst);
-                self.op_mova(Register::from(t1), addr);
-            }
+    // decodes every instruction as it's reached instead of keeping them, for comparing
+    // against the cache
+    pub fn set_caching(&mut self, caching: bool) {
+        self.caching = caching;
+        self.cache.clear();
+    }
 
-            Opcode::Lt => {
-                self.op_load(Register::from(t1), Register::from(t2), t3, TRYTE_ISIZE);
+    pub(crate) unsafe fn execute(&mut self, op: CachedOp) {
+        match op {
+            CachedOp::Mov(r_dest, r_src) => {
+                self.op_mov(r_dest, r_src);
             }
 
-            Opcode::Lh => {
-                self.op_load(Register::from(t1), Register::from(t2), t3, HALF_ISIZE);
+            CachedOp::Movi(r, half) => {
+                self.op_movi(r, half);
             }
 
-            Opcode::Lw => {
-                self.op_load(Register::from(t1), Register::from(t2), t3, WORD_ISIZE);
+            CachedOp::Movw(r, word) => {
+                self.op_movw(r, word);
             }
 
-            Opcode::St => {
-                self.op_store(Register::from(t1), Register::from(t2), t3, TRYTE_ISIZE);
+            CachedOp::Mova(r, addr) => {
+                self.op_mova(r, addr);
             }
 
-            Opcode::Sh => {
-                self.op_store(Register::from(t1), Register::from(t2), t3, HALF_ISIZE);
+            CachedOp::Load(r_dest, r_addr, offset, len) => {
+                self.op_load(r_dest, r_addr, offset, len);
             }
 
-            Opcode::Sw => {
-                self.op_store(Register::from(t1), Register::from(t2), t3, WORD_ISIZE);
+            CachedOp::Store(r_addr, r_src, offset, len) => {
+                self.op_store(r_addr, r_src, offset, len);
             }
 
-            Opcode::Add => {
-                self.op_add(Register::from(t1), Register::from(t2), Register::from(t3));
+            CachedOp::Add(r_dest, r_lhs, r_rhs) => {
+                self.op_add(r_dest, r_lhs, r_rhs);
             }
 
-            Opcode::Addi => {
-                let half = inst_half(inst);
-                self.op_addi(Register::from(t1), half);
+            CachedOp::Addi(r, half) => {
+                self.op_addi(r, half);
             }
 
-            Opcode::Mul => {
-                self.op_mul(Register::from(t1), Register::from(t2));
+            CachedOp::Mul(r_lhs, r_rhs) => {
+                self.op_mul(r_lhs, r_rhs);
             }
 
-            Opcode::Muli => {
-                let half = inst_half(inst);
-                self.op_muli(Register::from(t1), half);
+            CachedOp::Muli(r, half) => {
+                self.op_muli(r, half);
             }
 
-            Opcode::Not => {
-                self.op_not(Register::from(t1), Register::from(t2));
+            CachedOp::Not(r_dest, r_src) => {
+                self.op_not(r_dest, r_src);
             }
 
-            Opcode::And => {
-                self.op_and(Register::from(t1), Register::from(t2), Register::from(t3));
+            CachedOp::And(r_dest, r_lhs, r_rhs) => {
+                self.op_and(r_dest, r_lhs, r_rhs);
             }
 
-            Opcode::Or => {
-                self.op_or(Register::from(t1), Register::from(t2), Register::from(t3));
+            CachedOp::Or(r_dest, r_lhs, r_rhs) => {
+                self.op_or(r_dest, r_lhs, r_rhs);
             }
 
-            Opcode::Shf => {
-                self.op_shf(Register::from(t1), Register::from(t2), Register::from(t3));
+            CachedOp::Shf(r_dest, r_src, r_offset) => {
+                self.op_shf(r_dest, r_src, r_offset);
             }
 
-            Opcode::Shfi => {
-                let offset = inst_half_isize(inst);
-                self.op_shfi(Register::from(t1), offset);
+            CachedOp::Shfi(r, offset) => {
+                self.op_shfi(r, offset);
             }
 
-            Opcode::Cmp => {
-                self.op_cmp(Register::from(t1), Register::from(t2), Register::from(t3));
+            CachedOp::Cmp(r_dest, r_lhs, r_rhs) => {
+                self.op_cmp(r_dest, r_lhs, r_rhs);
             }
 
-            Opcode::Jmp => {
-                let addr = inst_addr(self.next_inst());
+            CachedOp::Jmp(addr) => {
                 self.op_jmp(addr);
             }
 
-            Opcode::JT => {
-                let addr = inst_reladdr(inst);
-                self.op_jmp_conditional(Register::from(t1), addr, |t| t == Trit::Neg);
+            CachedOp::JT(r, addr) => {
+                self.op_jmp_conditional(r, addr, |t| t == Trit::Neg);
             }
 
-            Opcode::J0 => {
-                let addr = inst_reladdr(inst);
-                self.op_jmp_conditional(Register::from(t1), addr, |t| t == Trit::Zero);
+            CachedOp::J0(r, addr) => {
+                self.op_jmp_conditional(r, addr, |t| t == Trit::Zero);
             }
 
-            Opcode::J1 => {
-                let addr = inst_reladdr(inst);
-                self.op_jmp_conditional(Register::from(t1), addr, |t| t == Trit::Pos);
+            CachedOp::J1(r, addr) => {
+                self.op_jmp_conditional(r, addr, |t| t == Trit::Pos);
             }
 
-            Opcode::JT0 => {
-                let addr = inst_reladdr(inst);
-                self.op_jmp_conditional(Register::from(t1), addr, |t| t != Trit::Pos);
+            CachedOp::JT0(r, addr) => {
+                self.op_jmp_conditional(r, addr, |t| t != Trit::Pos);
             }
 
-            Opcode::JT1 => {
-                let addr = inst_reladdr(inst);
-                self.op_jmp_conditional(Register::from(t1), addr, |t| t != Trit::Zero);
+            CachedOp::JT1(r, addr) => {
+                self.op_jmp_conditional(r, addr, |t| t != Trit::Zero);
             }
 
-            Opcode::J01 => {
-                let addr = inst_reladdr(inst);
-                self.op_jmp_conditional(Register::from(t1), addr, |t| t != Trit::Neg);
+            CachedOp::J01(r, addr) => {
+                self.op_jmp_conditional(r, addr, |t| t != Trit::Neg);
             }
 
-            Opcode::Call => {
-                let addr = inst_addr(self.next_inst());
+            CachedOp::Call(addr) => {
                 self.op_call(addr);
             }
 
-            Opcode::Ret => {
+            CachedOp::Ret => {
                 self.op_ret();
             }
 
-            Opcode::Syscall => {
+            CachedOp::Syscall => {
                 self.op_syscall(Register::T0);
             }
 
-            Opcode::Halt => {
+            CachedOp::Halt => {
                 self.running = false;
             }
 
-            _ => {}
+            CachedOp::Nop => {}
         }
-
-        self.clear(Register::ZERO);
     }
 
     unsafe fn op_mov(&mut self, r_dest: Register, r_src: Register) {
@@ -263,7 +396,7 @@ impl VM {
         let dest = self.dest(r_dest);
 
         let addr_src = self.src(r_addr);
-        let addr = ternary::to_int(addr_src, len);
+        let addr = ternary::to_int(addr_src, WORD_ISIZE);
         let src = self.memory.offset(addr + offset);
 
         ternary::clear(dest, WORD_ISIZE);
@@ -274,10 +407,16 @@ impl VM {
         let src = self.src(r_src);
 
         let addr_src = self.src(r_addr);
-        let addr = ternary::to_int(addr_src, len);
+        let addr = ternary::to_int(addr_src, WORD_ISIZE);
+        if self.is_protected(addr + offset, len) {
+            self.fault(VMError::ReadOnlyWrite((addr + offset) as Addr));
+            return;
+        }
+
         let dest = self.memory.offset(addr + offset);
 
         ternary::copy(dest, src, len);
+        self.invalidate(addr + offset, len);
     }
 
     unsafe fn op_add(&mut self, r_dest: Register, r_lhs: Register, r_rhs: Register) {
@@ -426,28 +565,17 @@ impl VM {
     }
 
     unsafe fn op_syscall(&mut self, r: Register) {
-        let index = self.read(r);
-        let syscall = Syscall::from(index);
-        syscall.perform(self);
-    }
-}
+        let number = self.read(r);
 
-fn inst_half(inst: Word) -> Half {
-    let mut half = EMPTY_HALF;
-    unsafe { ternary::copy(mut_ptr!(half), tryte_ptr!(inst, 2), HALF_ISIZE) };
-    half
-}
-
-fn inst_half_isize(inst: Word) -> isize {
-    unsafe { ternary::to_int(tryte_ptr!(inst, 2), HALF_ISIZE) }
-}
-
-fn inst_reladdr(inst: Word) -> RelAddr {
-    unsafe { ternary::to_int(tryte_ptr!(inst, 2), HALF_ISIZE) as RelAddr }
-}
-
-fn inst_addr(inst: Word) -> Addr {
-    unsafe { ternary::to_int(ptr!(inst), WORD_ISIZE) as Addr }
+        // the handler is taken out while it runs so it can borrow the VM
+        match self.syscalls.remove(&number) {
+            Some(mut handler) => {
+                handler.call(self);
+                self.syscalls.entry(number).or_insert(handler);
+            }
+            None => self.fault(VMError::UnknownSyscall(number)),
+        }
+    }
 }
 
 impl Drop for VM {